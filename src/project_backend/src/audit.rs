@@ -0,0 +1,245 @@
+use candid::utils::ArgumentEncoder;
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+
+use crate::error::BackendError;
+use crate::schema::{self, Versioned};
+use crate::{auth, AUDIT_LOG};
+
+// Page size limits for get_audit_log, and how many entries one call may scan
+// while looking for matches before it hands back a cursor
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+const MAX_SCANNED_ENTRIES: u64 = 10_000;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    Failure(BackendError),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AuditEntry {
+    // Position in the log; entries are never removed, so this is stable
+    pub id: u64,
+    pub caller: Principal,
+    // Email of the account bound to the caller at the time of the call
+    pub actor: Option<String>,
+    pub method: String,
+    // Hex SHA-256 of the Candid-encoded arguments; secrets are left out
+    pub args_digest: String,
+    pub outcome: AuditOutcome,
+    pub timestamp: u64,
+}
+
+impl Versioned for AuditEntry {
+    const VERSION: u8 = 2;
+
+    // Version 1 entries decode as they are unless they recorded the
+    // ValidationFailed error, which InvalidInput replaced
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        candid::decode_one(bytes).unwrap_or_else(|_| {
+            let old: AuditEntryV1 = schema::decode_or_trap(version, bytes);
+            let AuditOutcomeV1::Failure(ValidationFailedV1::ValidationFailed(field, reason)) = old.outcome;
+            AuditEntry {
+                id: old.id,
+                caller: old.caller,
+                actor: old.actor,
+                method: old.method,
+                args_digest: old.args_digest,
+                outcome: AuditOutcome::Failure(BackendError::validation(&field, &reason)),
+                timestamp: old.timestamp,
+            }
+        })
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+enum ValidationFailedV1 {
+    ValidationFailed(String, String),
+}
+
+#[derive(CandidType, Deserialize)]
+enum AuditOutcomeV1 {
+    Failure(ValidationFailedV1),
+}
+
+#[derive(CandidType, Deserialize)]
+struct AuditEntryV1 {
+    id: u64,
+    caller: Principal,
+    actor: Option<String>,
+    method: String,
+    args_digest: String,
+    outcome: AuditOutcomeV1,
+    timestamp: u64,
+}
+
+impl Storable for AuditEntry {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub method: Option<String>,
+    pub caller: Option<Principal>,
+    pub actor: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub failures_only: bool,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.method.as_ref().is_none_or(|method| &entry.method == method)
+            && self.caller.is_none_or(|caller| entry.caller == caller)
+            && self.actor.as_ref().is_none_or(|actor| entry.actor.as_ref() == Some(actor))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && (!self.failures_only || entry.outcome != AuditOutcome::Success)
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AuditLogPage {
+    // Newest first
+    pub entries: Vec<AuditEntry>,
+    // Pass back as `cursor` to continue with older entries; empty once the
+    // start of the log is reached
+    pub next_cursor: Option<u64>,
+}
+
+pub fn digest<Args: ArgumentEncoder>(args: Args) -> String {
+    let encoded = candid::encode_args(args).unwrap_or_default();
+    Sha256::digest(encoded).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Run a privileged operation and append its outcome to the audit log
+pub fn audited<T>(
+    method: &str,
+    args_digest: String,
+    operation: impl FnOnce() -> Result<T, BackendError>,
+) -> Result<T, BackendError> {
+    // Resolved up front: the operation may remove the caller's own account
+    let actor = actor();
+    let result = operation();
+    record(method, actor, args_digest, &result);
+    result
+}
+
+pub async fn audited_async<T>(
+    method: &str,
+    args_digest: String,
+    operation: impl Future<Output = Result<T, BackendError>>,
+) -> Result<T, BackendError> {
+    let actor = actor();
+    let result = operation.await;
+    record(method, actor, args_digest, &result);
+    result
+}
+
+fn actor() -> Option<String> {
+    auth::current_user().map(|user| user.email)
+}
+
+fn record<T>(method: &str, actor: Option<String>, args_digest: String, result: &Result<T, BackendError>) {
+    let outcome = match result {
+        Ok(_) => AuditOutcome::Success,
+        Err(e) => AuditOutcome::Failure(e.clone()),
+    };
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let entry = AuditEntry {
+            id: log.len(),
+            caller: ic_cdk::caller(),
+            actor,
+            method: method.to_string(),
+            args_digest,
+            outcome,
+            timestamp: ic_cdk::api::time(),
+        };
+        // A full log must not block the operation itself, which already happened
+        if let Err(e) = log.append(&entry) {
+            ic_cdk::println!("Failed to write audit entry for {}: {:?}", method, e);
+        }
+    });
+}
+
+// Matching entries, newest first, starting below `cursor` (or at the newest entry)
+pub fn page(filter: &AuditFilter, cursor: Option<u64>, limit: Option<u32>) -> AuditLogPage {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let mut next = cursor.unwrap_or(u64::MAX).min(log.len());
+        let scan_floor = next.saturating_sub(MAX_SCANNED_ENTRIES);
+        let mut entries = Vec::new();
+        while next > scan_floor && entries.len() < limit {
+            next -= 1;
+            if let Some(entry) = log.get(next) {
+                if filter.matches(&entry) {
+                    entries.push(entry);
+                }
+            }
+        }
+        AuditLogPage {
+            entries,
+            next_cursor: if next > 0 { Some(next) } else { None },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(outcome: AuditOutcome) -> AuditEntry {
+        AuditEntry {
+            id: 3,
+            caller: Principal::anonymous(),
+            actor: Some("admin@disasterrelief.com".to_string()),
+            method: "update_request_status".to_string(),
+            args_digest: "ab".to_string(),
+            outcome,
+            timestamp: 42,
+        }
+    }
+
+    #[test]
+    fn version_1_validation_failures_read_as_invalid_input() {
+        let old = AuditEntryV1 {
+            id: 3,
+            caller: Principal::anonymous(),
+            actor: Some("admin@disasterrelief.com".to_string()),
+            method: "update_request_status".to_string(),
+            args_digest: "ab".to_string(),
+            outcome: AuditOutcomeV1::Failure(ValidationFailedV1::ValidationFailed(
+                "note".to_string(),
+                "too long".to_string(),
+            )),
+            timestamp: 42,
+        };
+        let mut bytes = vec![1];
+        bytes.extend(candid::encode_one(&old).unwrap());
+        let decoded: AuditEntry = schema::from_envelope(&bytes);
+        assert_eq!(
+            decoded.outcome,
+            AuditOutcome::Failure(BackendError::validation("note", "too long"))
+        );
+        assert_eq!(decoded.method, "update_request_status");
+
+        let mut bytes = vec![1];
+        bytes.extend(candid::encode_one(entry(AuditOutcome::Success)).unwrap());
+        let decoded: AuditEntry = schema::from_envelope(&bytes);
+        assert_eq!(decoded.outcome, AuditOutcome::Success);
+        assert_eq!(decoded.timestamp, 42);
+    }
+}
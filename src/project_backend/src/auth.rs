@@ -0,0 +1,372 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::error::BackendError;
+use crate::schema::{self, Versioned};
+use crate::{StableString, User, PRINCIPALS, SESSIONS, USERS};
+
+// How long a login stays valid, and how often expired sessions are swept
+const SESSION_TTL_NANOS: u64 = 8 * 60 * 60 * 1_000_000_000;
+pub const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Victim,
+    Volunteer,
+    Organization,
+    Donor,
+    Admin,
+}
+
+impl Role {
+    // Map the free-form user_type/is_admin pair stored before roles were typed.
+    // Unknown user types get the least privileged role.
+    pub fn from_legacy(user_type: &str, is_admin: bool) -> Role {
+        if is_admin {
+            return Role::Admin;
+        }
+        match user_type.to_lowercase().as_str() {
+            "admin" => Role::Admin,
+            "organization" => Role::Organization,
+            "volunteer" => Role::Volunteer,
+            "donor" => Role::Donor,
+            _ => Role::Victim,
+        }
+    }
+
+    // Roles anyone can pick when registering; the others are granted by an admin
+    pub fn is_self_assignable(self) -> bool {
+        matches!(self, Role::Victim | Role::Volunteer | Role::Donor)
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    ViewUsers,
+    ManageUsers,
+    CreateHelpRequest,
+    ViewAllRequests,
+    ViewNearbyRequests,
+    VerifyRequest,
+    ManageRequests,
+    UpdateLocation,
+    WorkOnAssignments,
+    EditVolunteerProfile,
+    ViewVolunteers,
+    ManageSupplies,
+    ViewDonations,
+    MakeDonation,
+    ClearData,
+    ViewAuditLog,
+    ConfigureMatching,
+    ConfigureTracking,
+    ManageOrganizations,
+    RouteRequests,
+    ManageIncidents,
+}
+
+impl Permission {
+    // The permission matrix: the roles allowed to exercise each permission
+    pub fn allowed_roles(self) -> &'static [Role] {
+        use Role::*;
+        match self {
+            Permission::ViewUsers => &[Organization, Admin],
+            Permission::ManageUsers => &[Admin],
+            Permission::CreateHelpRequest => &[Victim, Admin],
+            Permission::ViewAllRequests => &[Organization, Admin],
+            Permission::ViewNearbyRequests => &[Volunteer, Organization, Admin],
+            Permission::VerifyRequest => &[Volunteer, Organization, Admin],
+            Permission::ManageRequests => &[Organization, Admin],
+            Permission::UpdateLocation => &[Volunteer],
+            Permission::WorkOnAssignments => &[Volunteer],
+            Permission::EditVolunteerProfile => &[Volunteer],
+            Permission::ViewVolunteers => &[Organization, Admin],
+            Permission::ManageSupplies => &[Organization, Admin],
+            Permission::ViewDonations => &[Organization, Admin],
+            Permission::MakeDonation => &[Donor],
+            Permission::ClearData => &[Admin],
+            Permission::ViewAuditLog => &[Admin],
+            Permission::ConfigureMatching => &[Admin],
+            Permission::ConfigureTracking => &[Admin],
+            Permission::ManageOrganizations => &[Admin],
+            Permission::RouteRequests => &[Admin],
+            Permission::ManageIncidents => &[Admin],
+        }
+    }
+
+    pub fn is_granted_to(self, role: Role) -> bool {
+        self.allowed_roles().contains(&role)
+    }
+}
+
+// Guard for update methods: the anonymous principal can never act on an account
+pub fn caller_is_not_anonymous() -> Result<(), String> {
+    if ic_cdk::caller() == Principal::anonymous() {
+        Err("Anonymous principals are not allowed to call this method".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+// Shared guard: resolve the calling account and check it holds the permission
+pub fn authorize(session_token: &str, permission: Permission) -> Result<User, BackendError> {
+    let user = authenticated(session_token)?;
+    if permission.is_granted_to(user.role) {
+        Ok(user)
+    } else {
+        ic_cdk::println!("{} ({:?}) lacks permission {:?}", user.email, user.role, permission);
+        Err(BackendError::Unauthorized {
+            role: user.role,
+            permission,
+        })
+    }
+}
+
+// Like `authorize`, but the owner of a record needs no extra permission to access it
+pub fn authorize_owner_or(
+    session_token: &str,
+    owner_email: &str,
+    permission: Permission,
+) -> Result<User, BackendError> {
+    let user = authenticated(session_token)?;
+    if user.email == owner_email || permission.is_granted_to(user.role) {
+        Ok(user)
+    } else {
+        ic_cdk::println!("{} ({:?}) lacks permission {:?}", user.email, user.role, permission);
+        Err(BackendError::Unauthorized {
+            role: user.role,
+            permission,
+        })
+    }
+}
+
+// Resolve the calling account, failing for anonymous or unbound principals
+// and for callers without a live session. The token must be the one the
+// caller's principal got at login, so a leaked token is useless from another
+// principal and a bound principal alone doesn't prove a login.
+pub fn authenticated(session_token: &str) -> Result<User, BackendError> {
+    let user = current_user().ok_or(BackendError::Unauthenticated)?;
+    let checked = check_session(ic_cdk::caller(), &user, session_token, ic_cdk::api::time());
+    if let Err(BackendError::SessionExpired) = checked {
+        ic_cdk::println!("Session of {} has expired", user.email);
+    }
+    checked.map(|()| user)
+}
+
+// Whether the principal holds a live session of the user under this token
+pub fn check_session(principal: Principal, user: &User, session_token: &str, now: u64) -> Result<(), BackendError> {
+    let principal_key = StableString::from(principal.to_text());
+    let session = SESSIONS
+        .with(|sessions| sessions.borrow().get(&principal_key))
+        .ok_or(BackendError::Unauthenticated)?;
+    if session.email != user.email || session.token.is_empty() || session.token != session_token {
+        return Err(BackendError::Unauthenticated);
+    }
+    if session.expires_at <= now {
+        return Err(BackendError::SessionExpired);
+    }
+    Ok(())
+}
+
+// Resolve the account bound to the calling principal, if any
+pub fn current_user() -> Option<User> {
+    let caller = ic_cdk::caller();
+    let principal_key = StableString::from(caller.to_text());
+    let user = PRINCIPALS
+        .with(|principals| principals.borrow().get(&principal_key))
+        .and_then(|email| USERS.with(|users| users.borrow().get(&email)));
+    if user.is_none() {
+        ic_cdk::println!("Principal {} is not bound to any account", caller);
+    }
+    user
+}
+
+// Link the calling principal to the account with the given email.
+// A principal can only be bound to one account and an account to one principal,
+// so this fails if either side is already bound elsewhere.
+pub fn bind_caller(email: &str) -> Result<(), BackendError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        ic_cdk::println!("Refusing to bind the anonymous principal to {}", email);
+        return Err(BackendError::Unauthenticated);
+    }
+
+    let principal_key = StableString::from(caller.to_text());
+    if let Some(bound_email) = PRINCIPALS.with(|principals| principals.borrow().get(&principal_key)) {
+        if bound_email.as_ref() != email {
+            ic_cdk::println!("Principal {} is already bound to another account", caller);
+            return Err(BackendError::Conflict(format!(
+                "principal {} is bound to another account",
+                caller
+            )));
+        }
+    }
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        let email_key = StableString::from(email.to_string());
+        let Some(mut user) = users.get(&email_key) else {
+            ic_cdk::println!("No user found with email: {}", email);
+            return Err(BackendError::not_found("user", email));
+        };
+
+        match user.principal {
+            Some(principal) if principal != caller => {
+                ic_cdk::println!("Account {} is already bound to another principal", email);
+                Err(BackendError::Conflict(format!(
+                    "account {} is bound to another principal",
+                    email
+                )))
+            }
+            Some(_) => Ok(()),
+            None => {
+                user.principal = Some(caller);
+                users.insert(email_key, user);
+                ic_cdk::println!("Bound principal {} to account {}", caller, email);
+                Ok(())
+            }
+        }
+    })?;
+
+    PRINCIPALS.with(|principals| {
+        principals
+            .borrow_mut()
+            .insert(principal_key, StableString::from(email.to_string()));
+    });
+    Ok(())
+}
+
+// Drop the principal binding and session of an account that is being removed
+pub fn unbind(user: &User) {
+    if let Some(principal) = user.principal {
+        let principal_key = StableString::from(principal.to_text());
+        PRINCIPALS.with(|principals| {
+            principals.borrow_mut().remove(&principal_key);
+        });
+        SESSIONS.with(|sessions| {
+            sessions.borrow_mut().remove(&principal_key);
+        });
+    }
+}
+
+// A login, held by the principal that made it. Calls present the token
+// along with coming from that principal.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Session {
+    pub token: String,
+    pub email: String,
+    pub expires_at: u64,
+}
+
+// Version 2 stored no token
+#[derive(CandidType, Deserialize)]
+struct SessionV2 {
+    email: String,
+    expires_at: u64,
+}
+
+// Version 1 had the current layout
+impl Versioned for Session {
+    const VERSION: u8 = 3;
+
+    // Sessions from version 2 keep an empty token, which never authenticates,
+    // so their holders log in again
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        candid::decode_one(bytes).unwrap_or_else(|_| {
+            let old: SessionV2 = schema::decode_or_trap(version, bytes);
+            Session {
+                token: String::new(),
+                email: old.email,
+                expires_at: old.expires_at,
+            }
+        })
+    }
+}
+
+impl Storable for Session {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+// Issue a new session for the calling principal, replacing any previous one.
+// The token is opaque to clients: 32 random bytes from the management canister.
+pub async fn start_session(email: &str) -> Result<Session, BackendError> {
+    let (bytes,) = raw_rand().await.map_err(|(code, msg)| {
+        BackendError::Internal(format!("failed to generate session token: {:?} {}", code, msg))
+    })?;
+    let session = Session {
+        token: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        email: email.to_string(),
+        expires_at: ic_cdk::api::time() + SESSION_TTL_NANOS,
+    };
+    SESSIONS.with(|sessions| {
+        sessions.borrow_mut().insert(
+            StableString::from(ic_cdk::caller().to_text()),
+            session.clone(),
+        );
+    });
+    ic_cdk::println!("Started session for {}", email);
+    Ok(session)
+}
+
+pub fn end_session() -> Result<(), BackendError> {
+    let principal_key = StableString::from(ic_cdk::caller().to_text());
+    SESSIONS
+        .with(|sessions| sessions.borrow_mut().remove(&principal_key))
+        .map(|_| ())
+        .ok_or_else(|| BackendError::NotFound("session".to_string()))
+}
+
+// Timer job: drop every session past its expiry
+pub fn sweep_expired_sessions() {
+    let now = ic_cdk::api::time();
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let expired: Vec<StableString> = sessions
+            .iter()
+            .filter(|(_, session)| session.expires_at <= now)
+            .map(|(key, _)| key)
+            .collect();
+        for key in &expired {
+            sessions.remove(key);
+        }
+        if !expired.is_empty() {
+            ic_cdk::println!("Swept {} expired sessions", expired.len());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_without_a_token_come_back_with_an_empty_one() {
+        let old = SessionV2 {
+            email: "ann@example.org".to_string(),
+            expires_at: 42,
+        };
+        let mut bytes = vec![2];
+        bytes.extend(candid::encode_one(&old).unwrap());
+        let decoded: Session = schema::from_envelope(&bytes);
+        assert!(decoded.token.is_empty());
+        assert_eq!((decoded.email.as_str(), decoded.expires_at), ("ann@example.org", 42));
+
+        let current = Session {
+            token: "ab12".to_string(),
+            ..decoded
+        };
+        let mut bytes = vec![1];
+        bytes.extend(candid::encode_one(&current).unwrap());
+        let decoded: Session = schema::from_envelope(&bytes);
+        assert_eq!(decoded.token, "ab12");
+    }
+}
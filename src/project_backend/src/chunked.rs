@@ -0,0 +1,294 @@
+use ic_stable_structures::{btreemap, BoundedStorable, StableBTreeMap, Storable};
+use std::iter::Peekable;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeInclusive};
+
+use crate::MemoryType;
+
+// Largest piece of a record kept in one map entry
+const CHUNK_SIZE: usize = 1024;
+
+#[derive(Clone)]
+pub struct Chunk(Vec<u8>);
+
+impl Storable for Chunk {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Borrowed(&self.0)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Chunk(bytes.into_owned())
+    }
+}
+
+impl BoundedStorable for Chunk {
+    const MAX_SIZE: u32 = CHUNK_SIZE as u32;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Called with a key's previous and new value whenever a record is inserted or
+// removed, in the same message as the write so derived data can't drift from it
+pub type ChangeHook<K, V> = fn(&K, Option<&V>, Option<&V>);
+
+// A map whose values have no size limit. StableBTreeMap needs a bound on
+// every value, so each record is split into CHUNK_SIZE pieces stored under
+// (key, piece number); the pieces of one record are adjacent in key order.
+pub struct ChunkedMap<K, V>
+where
+    K: BoundedStorable + Ord + Clone + Default,
+{
+    chunks: StableBTreeMap<(K, u32), Chunk, MemoryType>,
+    on_change: Option<ChangeHook<K, V>>,
+    _value: PhantomData<V>,
+}
+
+impl<K, V> ChunkedMap<K, V>
+where
+    K: BoundedStorable + Ord + Clone + Default,
+    V: Storable,
+{
+    pub fn init(memory: MemoryType) -> Self {
+        ChunkedMap {
+            chunks: StableBTreeMap::init(memory),
+            on_change: None,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn with_change_hook(self, hook: ChangeHook<K, V>) -> Self {
+        ChunkedMap {
+            on_change: Some(hook),
+            ..self
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.range(key.clone()..=key.clone()).next().map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.chunks.contains_key(&(key.clone(), 0))
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(hook) = self.on_change {
+            hook(&key, self.get(&key).as_ref(), Some(&value));
+        }
+        self.remove_chunks(&key);
+        let bytes = value.to_bytes();
+        // A record always has at least its first piece, so contains_key works
+        // even for an empty encoding
+        let pieces: Vec<&[u8]> = if bytes.is_empty() {
+            vec![&[]]
+        } else {
+            bytes.chunks(CHUNK_SIZE).collect()
+        };
+        for (index, piece) in pieces.into_iter().enumerate() {
+            self.chunks
+                .insert((key.clone(), index as u32), Chunk(piece.to_vec()));
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.get(key)?;
+        self.remove_chunks(key);
+        if let Some(hook) = self.on_change {
+            hook(key, Some(&value), None);
+        }
+        Some(value)
+    }
+
+    fn remove_chunks(&mut self, key: &K) {
+        let indices: Vec<u32> = self
+            .chunks
+            .range((key.clone(), 0)..=(key.clone(), u32::MAX))
+            .map(|((_, index), _)| index)
+            .collect();
+        for index in indices {
+            self.chunks.remove(&(key.clone(), index));
+        }
+    }
+
+    // The records stored under the given keys, in the order given; keys
+    // without a record are skipped
+    pub fn entries_of(&self, keys: Vec<K>) -> impl Iterator<Item = (K, V)> + '_ {
+        keys.into_iter()
+            .filter_map(|key| self.get(&key).map(|value| (key, value)))
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            chunks: self.chunks.iter().peekable(),
+            _value: PhantomData,
+        }
+    }
+
+    // Records after the given key (or from the first one), in key order
+    pub fn iter_after(&self, start_after: Option<&K>) -> Iter<'_, K, V> {
+        let start = match start_after {
+            Some(key) => Bound::Excluded((key.clone(), u32::MAX)),
+            None => Bound::Unbounded,
+        };
+        Iter {
+            chunks: self.chunks.range((start, Bound::Unbounded)).peekable(),
+            _value: PhantomData,
+        }
+    }
+
+    // The greatest key below `bound`, found without reading its record
+    pub fn last_key_below(&self, bound: &K) -> Option<K> {
+        self.chunks.iter_upper_bound(&(bound.clone(), 0)).next().map(|((key, _), _)| key)
+    }
+
+    // Records whose keys fall within the inclusive range, in key order
+    pub fn range(&self, keys: RangeInclusive<K>) -> Iter<'_, K, V> {
+        let (start, end) = keys.into_inner();
+        Iter {
+            chunks: self.chunks.range((start, 0)..=(end, u32::MAX)).peekable(),
+            _value: PhantomData,
+        }
+    }
+}
+
+pub struct Iter<'a, K, V>
+where
+    K: BoundedStorable + Ord + Clone + Default,
+{
+    chunks: Peekable<btreemap::Iter<'a, (K, u32), Chunk, MemoryType>>,
+    _value: PhantomData<V>,
+}
+
+impl<K, V> Iterator for Iter<'_, K, V>
+where
+    K: BoundedStorable + Ord + Clone + Default,
+    V: Storable,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let ((key, _), first) = self.chunks.next()?;
+        let mut bytes = first.0;
+        while let Some(((next_key, _), _)) = self.chunks.peek() {
+            if *next_key != key {
+                break;
+            }
+            let (_, piece) = self.chunks.next()?;
+            bytes.extend(piece.0);
+        }
+        Some((key, V::from_bytes(std::borrow::Cow::Owned(bytes))))
+    }
+}
+
+// Raw bytes of a record in one of the maps that stored whole records with a
+// fixed bound. Reading such a map needs the bound it was created with.
+pub struct BoundedRecord<const MAX_SIZE: u32>(pub Vec<u8>);
+
+impl<const MAX_SIZE: u32> Storable for BoundedRecord<MAX_SIZE> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Borrowed(&self.0)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        BoundedRecord(bytes.into_owned())
+    }
+}
+
+impl<const MAX_SIZE: u32> BoundedStorable for BoundedRecord<MAX_SIZE> {
+    const MAX_SIZE: u32 = MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Move every record of a bounded map into its chunked replacement, decoding
+// it on the way so it is stored in the current envelope
+pub fn move_records<K, V, const MAX_SIZE: u32>(
+    from: &mut StableBTreeMap<K, BoundedRecord<MAX_SIZE>, MemoryType>,
+    to: &mut ChunkedMap<K, V>,
+) -> usize
+where
+    K: BoundedStorable + Ord + Clone + Default,
+    V: Storable,
+{
+    let records: Vec<(K, BoundedRecord<MAX_SIZE>)> = from.iter().collect();
+    for (key, record) in &records {
+        to.insert(key.clone(), V::from_bytes(std::borrow::Cow::Borrowed(&record.0)));
+        from.remove(key);
+    }
+    records.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::memory_manager::{MemoryId, MemoryManager};
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    #[derive(Debug, PartialEq)]
+    struct Blob(Vec<u8>);
+
+    impl Storable for Blob {
+        fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+            std::borrow::Cow::Borrowed(&self.0)
+        }
+
+        fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+            Blob(bytes.into_owned())
+        }
+    }
+
+    fn map() -> ChunkedMap<u64, Blob> {
+        ChunkedMap::init(MemoryManager::init(DefaultMemoryImpl::default()).get(MemoryId::new(0)))
+    }
+
+    fn blob(len: usize, fill: u8) -> Blob {
+        Blob((0..len).map(|i| fill.wrapping_add(i as u8)).collect())
+    }
+
+    fn pieces(map: &ChunkedMap<u64, Blob>, key: u64) -> usize {
+        map.chunks.range((key, 0)..=(key, u32::MAX)).count()
+    }
+
+    #[test]
+    fn records_split_at_the_chunk_size() {
+        let mut map = map();
+        for (key, len, expected) in [
+            (1, 0, 1),
+            (2, CHUNK_SIZE - 1, 1),
+            (3, CHUNK_SIZE, 1),
+            (4, CHUNK_SIZE + 1, 2),
+            (5, 3 * CHUNK_SIZE, 3),
+        ] {
+            map.insert(key, blob(len, key as u8));
+            assert_eq!(pieces(&map, key), expected, "{} bytes", len);
+            assert_eq!(map.get(&key), Some(blob(len, key as u8)), "{} bytes", len);
+        }
+        assert!(map.contains_key(&1));
+
+        let keys: Vec<u64> = map.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, [1, 2, 3, 4, 5]);
+        let lens: Vec<usize> = map.range(3..=4).map(|(_, value)| value.0.len()).collect();
+        assert_eq!(lens, [CHUNK_SIZE, CHUNK_SIZE + 1]);
+        let keys: Vec<u64> = map.iter_after(Some(&4)).map(|(key, _)| key).collect();
+        assert_eq!(keys, [5]);
+        assert_eq!(map.iter_after(None).count(), 5);
+        assert_eq!(map.last_key_below(&5), Some(4));
+        assert_eq!(map.last_key_below(&9), Some(5));
+        assert_eq!(map.last_key_below(&1), None);
+    }
+
+    #[test]
+    fn shrinking_or_removing_a_record_drops_its_extra_pieces() {
+        let mut map = map();
+        map.insert(7, blob(2 * CHUNK_SIZE + 5, 1));
+        map.insert(8, blob(10, 2));
+        assert_eq!(pieces(&map, 7), 3);
+
+        map.insert(7, blob(CHUNK_SIZE, 3));
+        assert_eq!(pieces(&map, 7), 1);
+        assert_eq!(map.get(&7), Some(blob(CHUNK_SIZE, 3)));
+
+        assert_eq!(map.remove(&7), Some(blob(CHUNK_SIZE, 3)));
+        assert_eq!(pieces(&map, 7), 0);
+        assert_eq!(map.get(&8), Some(blob(10, 2)));
+        assert_eq!(map.remove(&7), None);
+    }
+}
@@ -0,0 +1,56 @@
+use candid::CandidType;
+use serde::Deserialize;
+
+use crate::auth::{Permission, Role};
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum BackendError {
+    // The caller is anonymous, its principal is not bound to any account,
+    // or it has not logged in
+    Unauthenticated,
+    // The caller's session has expired and it needs to log in again
+    SessionExpired,
+    // The caller's role does not grant the permission the call requires
+    Unauthorized { role: Role, permission: Permission },
+    // The addressed record does not exist; carries what was looked up
+    NotFound(String),
+    // The record is not in a state that allows the requested change
+    InvalidState { from: String, to: String },
+    // Arguments were rejected: every offending field and why
+    InvalidInput(Vec<FieldError>),
+    // The data belongs to an organization the caller isn't a member of, or
+    // the change needs an admin of that organization
+    OrganizationAccessDenied { organization_id: String },
+    // The change clashes with existing data, e.g. a duplicate key
+    Conflict(String),
+    // Stable memory is close to the memory manager's limit
+    StorageFull,
+    // A system call the canister depends on failed
+    Internal(String),
+}
+
+impl BackendError {
+    pub fn not_found(kind: &str, id: &str) -> Self {
+        BackendError::NotFound(format!("{} {}", kind, id))
+    }
+
+    pub fn invalid_state(from: &str, to: &str) -> Self {
+        BackendError::InvalidState {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    pub fn validation(field: &str, reason: &str) -> Self {
+        BackendError::InvalidInput(vec![FieldError {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }])
+    }
+}
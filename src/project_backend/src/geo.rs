@@ -0,0 +1,370 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::ops::Bound;
+
+use crate::{
+    HelpRequest, StableString, VolunteerLocation, GEO_INDEX, HELP_REQUESTS, VOLUNTEER_GRID, VOLUNTEER_LOCATIONS,
+};
+
+// The indexes bucket requests and volunteers into a fixed grid of
+// CELL_DEGREES x CELL_DEGREES cells (about 11 km at the equator). A cell is
+// numbered row * LON_CELLS + column, so one row of cells is a single
+// contiguous key range.
+const CELL_DEGREES: f64 = 0.1;
+const LAT_CELLS: u64 = 1_800;
+const LON_CELLS: u64 = 3_600;
+const KM_PER_DEGREE: f64 = 111.32;
+
+// (grid cell, request id) -> (latitude, longitude) of the request
+pub type GridKey = (u64, u64);
+pub type Coordinates = (f64, f64);
+// (grid cell, volunteer email) -> (latitude, longitude) of their position
+pub type VolunteerGridKey = (u64, StableString);
+
+// A position in decimal degrees
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lon)
+    }
+}
+
+// The area between two parallels and two meridians. A box whose west edge is
+// east of its east edge crosses the antimeridian.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        let within_lon = if self.west <= self.east {
+            (self.west..=self.east).contains(&point.lon)
+        } else {
+            point.lon >= self.west || point.lon <= self.east
+        };
+        (self.south..=self.north).contains(&point.lat) && within_lon
+    }
+
+    // Degrees of longitude between the west and the east edge
+    pub fn width(&self) -> f64 {
+        if self.west <= self.east {
+            self.east - self.west
+        } else {
+            self.east + 360.0 - self.west
+        }
+    }
+
+    // The box with its edges moved out by the given degrees, stopping at the
+    // poles. A box grown all the way around spans every longitude.
+    pub fn grown(&self, lat_degrees: f64, lon_degrees: f64) -> BoundingBox {
+        let wrap = |lon: f64| {
+            if lon < -180.0 {
+                lon + 360.0
+            } else if lon > 180.0 {
+                lon - 360.0
+            } else {
+                lon
+            }
+        };
+        let (west, east) = if self.width() + 2.0 * lon_degrees >= 360.0 {
+            (-180.0, 180.0)
+        } else {
+            (wrap(self.west - lon_degrees), wrap(self.east + lon_degrees))
+        };
+        BoundingBox {
+            south: (self.south - lat_degrees).max(-90.0),
+            west,
+            north: (self.north + lat_degrees).min(90.0),
+            east,
+        }
+    }
+}
+
+fn row_of(lat: f64) -> u64 {
+    (((lat + 90.0) / CELL_DEGREES).floor().max(0.0) as u64).min(LAT_CELLS - 1)
+}
+
+fn column_of(lon: f64) -> u64 {
+    let wrapped = (lon + 180.0).rem_euclid(360.0);
+    ((wrapped / CELL_DEGREES).floor() as u64).min(LON_CELLS - 1)
+}
+
+fn cell_of(lat: f64, lon: f64) -> u64 {
+    row_of(lat) * LON_CELLS + column_of(lon)
+}
+
+// Only open requests with coordinates are in the grid, so finished requests
+// don't add to the cost of a search
+fn grid_entry(request_id: u64, request: &HelpRequest) -> Option<(GridKey, Coordinates)> {
+    if request.status.allowed_next().is_empty() {
+        return None;
+    }
+    let point = request.coordinates?;
+    Some(((cell_of(point.lat, point.lon), request_id), (point.lat, point.lon)))
+}
+
+// Change hook of the request map, run alongside the secondary indexes
+pub fn on_request_change(request_id: &u64, old: Option<&HelpRequest>, new: Option<&HelpRequest>) {
+    let old = old.and_then(|request| grid_entry(*request_id, request));
+    let new = new.and_then(|request| grid_entry(*request_id, request));
+    if old == new {
+        return;
+    }
+    GEO_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some((key, _)) = old {
+            index.remove(&key);
+        }
+        if let Some((key, coordinates)) = new {
+            index.insert(key, coordinates);
+        }
+    });
+}
+
+pub fn clear_index() {
+    GEO_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<GridKey> = index.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+}
+
+// Index every stored request from scratch
+pub fn rebuild_index() {
+    clear_index();
+    let requests: Vec<(u64, HelpRequest)> = HELP_REQUESTS.with(|requests| requests.borrow().iter().collect());
+    for (request_id, request) in &requests {
+        on_request_change(request_id, None, Some(request));
+    }
+    let indexed = GEO_INDEX.with(|index| index.borrow().len());
+    ic_cdk::println!("Indexed {} of {} help requests by location", indexed, requests.len());
+}
+
+fn volunteer_grid_entry(location: &VolunteerLocation) -> Option<(VolunteerGridKey, Coordinates)> {
+    let point = location.coordinates?;
+    let key = (cell_of(point.lat, point.lon), StableString::from(location.email.clone()));
+    Some((key, (point.lat, point.lon)))
+}
+
+// Change hook of the volunteer location map
+pub fn on_volunteer_location_change(
+    _email: &StableString,
+    old: Option<&VolunteerLocation>,
+    new: Option<&VolunteerLocation>,
+) {
+    let old = old.and_then(volunteer_grid_entry);
+    let new = new.and_then(volunteer_grid_entry);
+    if old == new {
+        return;
+    }
+    VOLUNTEER_GRID.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some((key, _)) = old {
+            index.remove(&key);
+        }
+        if let Some((key, coordinates)) = new {
+            index.insert(key, coordinates);
+        }
+    });
+}
+
+// Index every stored volunteer position from scratch
+pub fn rebuild_volunteer_index() {
+    VOLUNTEER_GRID.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<VolunteerGridKey> = index.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+    let locations: Vec<(StableString, VolunteerLocation)> =
+        VOLUNTEER_LOCATIONS.with(|locations| locations.borrow().iter().collect());
+    for (email, location) in &locations {
+        on_volunteer_location_change(email, None, Some(location));
+    }
+    ic_cdk::println!("Indexed {} volunteer positions by location", locations.len());
+}
+
+// Column ranges covering lon +- dlon, split in two where they cross the antimeridian
+fn column_ranges(lon: f64, dlon: f64) -> Vec<(u64, u64)> {
+    if dlon >= 180.0 {
+        return vec![(0, LON_CELLS - 1)];
+    }
+    let first = column_of(lon - dlon);
+    let last = column_of(lon + dlon);
+    if first <= last {
+        vec![(first, last)]
+    } else {
+        vec![(first, LON_CELLS - 1), (0, last)]
+    }
+}
+
+// Ids of the indexed requests within radius_km of the point, nearest first,
+// with their distance in km. Only the grid cells overlapping the search
+// circle's bounding box are read.
+pub fn within_radius(origin: GeoPoint, radius_km: f64) -> Vec<(u64, f64)> {
+    let GeoPoint { lat, lon } = origin;
+    let dlat = radius_km / KM_PER_DEGREE;
+    let first_row = row_of(lat - dlat);
+    let last_row = row_of(lat + dlat);
+    // Longitude degrees shrink towards the poles, so size the box for the
+    // latitude in it that is closest to a pole
+    let max_abs_lat = (lat.abs() + dlat).min(90.0);
+    let lon_km = KM_PER_DEGREE * max_abs_lat.to_radians().cos();
+    let dlon = if lon_km > f64::EPSILON { radius_km / lon_km } else { 180.0 };
+    let columns = column_ranges(lon, dlon);
+
+    let mut found = Vec::new();
+    GEO_INDEX.with(|index| {
+        let index = index.borrow();
+        for row in first_row..=last_row {
+            for &(first_column, last_column) in &columns {
+                let start = (row * LON_CELLS + first_column, 0);
+                let end = (row * LON_CELLS + last_column, u64::MAX);
+                for ((_, request_id), (request_lat, request_lon)) in index.range(start..=end) {
+                    let distance = calculate_distance(lat, lon, request_lat, request_lon);
+                    if distance <= radius_km {
+                        found.push((request_id, distance));
+                    }
+                }
+            }
+        }
+    });
+    found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    found
+}
+
+// Emails of the volunteers whose indexed position lies inside the box. Only
+// the grid cells overlapping the box are read.
+pub fn volunteers_within(bbox: &BoundingBox) -> Vec<String> {
+    let half_width = bbox.width() / 2.0;
+    let columns = column_ranges(bbox.west + half_width, half_width);
+    let mut found = Vec::new();
+    VOLUNTEER_GRID.with(|index| {
+        let index = index.borrow();
+        for row in row_of(bbox.south)..=row_of(bbox.north) {
+            for &(first_column, last_column) in &columns {
+                let start = (row * LON_CELLS + first_column, StableString::default());
+                let end = (row * LON_CELLS + last_column + 1, StableString::default());
+                for ((_, email), (lat, lon)) in index.range((Bound::Included(start), Bound::Excluded(end))) {
+                    if bbox.contains(&GeoPoint { lat, lon }) {
+                        found.push(email.0);
+                    }
+                }
+            }
+        }
+    });
+    found
+}
+
+pub fn calculate_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let r = 6371.0; // Earth's radius in kilometers
+    let dlat = (lat2 - lat1).to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat/2.0).sin() * (dlat/2.0).sin() +
+        lat1.to_radians().cos() * lat2.to_radians().cos() *
+        (dlon/2.0).sin() * (dlon/2.0).sin();
+    let c = 2.0 * a.sqrt().atan2((1.0-a).sqrt());
+    r * c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::RequestStatus;
+
+    fn request(request_id: u64, lat: f64, lon: f64, status: RequestStatus) -> HelpRequest {
+        HelpRequest {
+            request_id,
+            victim_id: "ann@example.org".to_string(),
+            request_type: "food".to_string(),
+            description: String::new(),
+            urgency: "high".to_string(),
+            location: String::new(),
+            status,
+            timestamp: 0,
+            coordinates: Some(GeoPoint { lat, lon }),
+            verification_note: None,
+            verified_by: None,
+            organization_id: None,
+            assigned_volunteer: None,
+            incident_id: None,
+        }
+    }
+
+    fn found(origin: GeoPoint) -> Vec<u64> {
+        within_radius(origin, 5.0).into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn grid_holds_only_open_requests() {
+        let here = GeoPoint { lat: 10.0, lon: 20.0 };
+        let there = GeoPoint { lat: -30.0, lon: 179.99 };
+        let pending = request(1, here.lat, here.lon, RequestStatus::Pending);
+        on_request_change(&1, None, Some(&pending));
+        on_request_change(&2, None, Some(&request(2, here.lat, here.lon, RequestStatus::Rejected)));
+        assert_eq!(found(here), [1]);
+
+        let moved = request(1, there.lat, there.lon, RequestStatus::Assigned);
+        on_request_change(&1, Some(&pending), Some(&moved));
+        assert!(found(here).is_empty());
+        assert_eq!(found(there), [1]);
+
+        let completed = request(1, there.lat, there.lon, RequestStatus::Completed);
+        on_request_change(&1, Some(&moved), Some(&completed));
+        assert!(found(there).is_empty());
+        assert_eq!(GEO_INDEX.with(|index| index.borrow().len()), 0);
+    }
+
+    #[test]
+    fn grown_boxes_wrap_at_the_antimeridian_and_stop_at_the_poles() {
+        let bbox = BoundingBox { south: 89.5, west: 179.0, north: 89.9, east: 179.5 }.grown(0.5, 1.0);
+        assert_eq!((bbox.south, bbox.north), (89.0, 90.0));
+        assert_eq!((bbox.west, bbox.east), (178.0, -179.5));
+        assert!(bbox.contains(&GeoPoint { lat: 89.5, lon: -179.8 }));
+        assert!(!bbox.contains(&GeoPoint { lat: 89.5, lon: -179.0 }));
+
+        let everywhere = BoundingBox { south: 0.0, west: -179.0, north: 1.0, east: 179.0 }.grown(0.0, 2.0);
+        assert_eq!((everywhere.west, everywhere.east), (-180.0, 180.0));
+    }
+
+    #[test]
+    fn volunteer_grid_finds_positions_inside_the_box() {
+        let location = |email: &str, lat: f64, lon: f64| VolunteerLocation {
+            email: email.to_string(),
+            coordinates: Some(GeoPoint { lat, lon }),
+            address: String::new(),
+            last_updated: 0,
+            presence: crate::tracking::Presence::Active,
+        };
+        let key = |email: &str| StableString::from(email.to_string());
+        let bob = location("bob@example.org", 10.05, 179.95);
+        on_volunteer_location_change(&key("bob@example.org"), None, Some(&bob));
+        on_volunteer_location_change(&key("cat@example.org"), None, Some(&location("cat@example.org", 10.05, -179.95)));
+        on_volunteer_location_change(&key("dan@example.org"), None, Some(&location("dan@example.org", 10.3, 0.0)));
+
+        let across = BoundingBox { south: 10.0, west: 179.9, north: 10.1, east: -179.9 };
+        let mut found = volunteers_within(&across);
+        found.sort();
+        assert_eq!(found, ["bob@example.org", "cat@example.org"]);
+        let everywhere = BoundingBox { south: -90.0, west: -180.0, north: 90.0, east: 180.0 };
+        assert_eq!(volunteers_within(&everywhere).len(), 3);
+
+        let moved = location("bob@example.org", 10.3, 0.05);
+        on_volunteer_location_change(&key("bob@example.org"), Some(&bob), Some(&moved));
+        assert_eq!(volunteers_within(&across), ["cat@example.org"]);
+        on_volunteer_location_change(&key("bob@example.org"), Some(&moved), None);
+        assert_eq!(volunteers_within(&everywhere).len(), 2);
+    }
+}
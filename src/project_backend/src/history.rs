@@ -0,0 +1,134 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use serde::Deserialize;
+
+use crate::chunked::ChunkedMap;
+use crate::status::RequestStatus;
+use crate::schema::{self, Versioned};
+use crate::REQUEST_HISTORY;
+
+// One status change of a help request. `from` is empty for the event that
+// records the request being filed; a reassignment to another organization
+// keeps the status.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RequestEvent {
+    pub request_id: u64,
+    // Organization the request belonged to afterwards, so its history stays
+    // scoped once the request itself is gone
+    pub organization_id: Option<String>,
+    pub from: Option<RequestStatus>,
+    pub to: RequestStatus,
+    pub actor: String,
+    pub actor_principal: Principal,
+    pub note: Option<String>,
+    pub timestamp: u64,
+}
+
+impl Versioned for RequestEvent {
+    const VERSION: u8 = 2;
+
+    // Events recorded before they carried an organization are left to admins
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        let old: RequestEventV1 = schema::decode_or_trap(version, bytes);
+        RequestEvent {
+            request_id: old.request_id,
+            organization_id: None,
+            from: old.from,
+            to: old.to,
+            actor: old.actor,
+            actor_principal: old.actor_principal,
+            note: old.note,
+            timestamp: old.timestamp,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct RequestEventV1 {
+    request_id: u64,
+    from: Option<RequestStatus>,
+    to: RequestStatus,
+    actor: String,
+    actor_principal: Principal,
+    note: Option<String>,
+    timestamp: u64,
+}
+
+impl Storable for RequestEvent {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+// Append an event to the request's history. Entries are keyed by
+// (request id, sequence number) and never rewritten or removed.
+pub fn record(
+    request_id: u64,
+    organization_id: Option<String>,
+    from: Option<RequestStatus>,
+    to: RequestStatus,
+    actor: &str,
+    note: Option<String>,
+) {
+    let event = RequestEvent {
+        request_id,
+        organization_id,
+        from,
+        to,
+        actor: actor.to_string(),
+        actor_principal: ic_cdk::caller(),
+        note,
+        timestamp: ic_cdk::api::time(),
+    };
+    REQUEST_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let seq = last_key(&history, request_id).map_or(0, |(_, seq)| seq + 1);
+        history.insert((request_id, seq), event);
+    });
+}
+
+// Key of the request's latest event; only its key is read, not the events
+fn last_key(history: &ChunkedMap<(u64, u64), RequestEvent>, request_id: u64) -> Option<(u64, u64)> {
+    history
+        .last_key_below(&(request_id, u64::MAX))
+        .filter(|(id, _)| *id == request_id)
+}
+
+// Every recorded event of a request, oldest first
+pub fn events_of(request_id: u64) -> Vec<RequestEvent> {
+    REQUEST_HISTORY.with(|history| {
+        history
+            .borrow()
+            .range((request_id, 0)..=(request_id, u64::MAX))
+            .map(|(_, event)| event)
+            .collect()
+    })
+}
+
+// What the request was before its current assignment began, going by the
+// last time it was handed to a volunteer from the queue
+pub fn status_before_assignment(request_id: u64) -> RequestStatus {
+    events_of(request_id)
+        .into_iter()
+        .rev()
+        .find(|event| {
+            event.to == RequestStatus::Assigned
+                && matches!(event.from, Some(RequestStatus::Pending | RequestStatus::Verified))
+        })
+        .and_then(|event| event.from)
+        .unwrap_or(RequestStatus::Pending)
+}
+
+// The organization the request belonged to when its last event was recorded
+pub fn last_organization(request_id: u64) -> Option<String> {
+    REQUEST_HISTORY.with(|history| {
+        let history = history.borrow();
+        last_key(&history, request_id)
+            .and_then(|key| history.get(&key))
+            .and_then(|event| event.organization_id)
+    })
+}
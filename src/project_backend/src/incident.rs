@@ -0,0 +1,218 @@
+use candid::CandidType;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BackendError;
+use crate::geo::GeoPoint;
+use crate::organization::AreaShape;
+use crate::schema::{self, Versioned};
+use crate::status::RequestStatus;
+use crate::{index, HelpRequest, StableString, DONATIONS, HELP_REQUESTS, INCIDENTS, SUPPLY_BUNDLES};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncidentType {
+    Flood,
+    Earthquake,
+    Wildfire,
+    Storm,
+    Landslide,
+    Drought,
+    Epidemic,
+    Other,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncidentStatus {
+    // Response under way; new requests in the affected area are linked to it
+    Active,
+    // Response winding down; records can still be linked by hand
+    Monitoring,
+    Closed,
+}
+
+// One disaster and the response to it
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Incident {
+    pub id: String,
+    pub name: String,
+    pub incident_type: IncidentType,
+    pub affected_area: Vec<AreaShape>,
+    pub status: IncidentStatus,
+    // Nanoseconds since the epoch; the end is set when the incident is closed
+    pub start_time: u64,
+    pub end_time: Option<u64>,
+    pub created_by: String,
+}
+
+impl Versioned for Incident {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for Incident {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct NewIncident {
+    pub id: String,
+    pub name: String,
+    pub incident_type: IncidentType,
+    pub affected_area: Vec<AreaShape>,
+    // Defaults to now
+    pub start_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IncidentUpdate {
+    pub name: String,
+    pub incident_type: IncidentType,
+    pub affected_area: Vec<AreaShape>,
+    pub status: IncidentStatus,
+}
+
+// Counts over the records linked to an incident that the caller may see
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct IncidentDashboard {
+    pub requests_by_status: Vec<(RequestStatus, u64)>,
+    pub requests_by_urgency: Vec<(String, u64)>,
+    pub unassigned_open_requests: u64,
+    pub volunteers_engaged: u64,
+    pub supply_bundles: u64,
+    pub supply_bundles_distributed: u64,
+    pub donations: u64,
+    pub donated_amount: f64,
+}
+
+pub fn get(id: &str) -> Option<Incident> {
+    INCIDENTS.with(|incidents| incidents.borrow().get(&StableString::from(id.to_string())))
+}
+
+pub fn find(id: &str) -> Result<Incident, BackendError> {
+    get(id).ok_or_else(|| BackendError::not_found("incident", id))
+}
+
+fn save(incident: Incident) {
+    INCIDENTS.with(|incidents| {
+        incidents
+            .borrow_mut()
+            .insert(StableString::from(incident.id.clone()), incident);
+    });
+}
+
+pub fn all() -> Vec<Incident> {
+    INCIDENTS.with(|incidents| incidents.borrow().iter().map(|(_, i)| i).collect())
+}
+
+pub fn create(new: NewIncident, created_by: &str) -> Result<(), BackendError> {
+    if get(&new.id).is_some() {
+        return Err(BackendError::Conflict(format!("incident {} already exists", new.id)));
+    }
+    save(Incident {
+        id: new.id,
+        name: new.name,
+        incident_type: new.incident_type,
+        affected_area: new.affected_area,
+        status: IncidentStatus::Active,
+        start_time: new.start_time.unwrap_or_else(ic_cdk::api::time),
+        end_time: None,
+        created_by: created_by.to_string(),
+    });
+    Ok(())
+}
+
+pub fn update(incident: Incident, update: IncidentUpdate) {
+    // Closing records the end; reopening clears it
+    let end_time = match update.status {
+        IncidentStatus::Closed => incident.end_time.or_else(|| Some(ic_cdk::api::time())),
+        _ => None,
+    };
+    save(Incident {
+        name: update.name,
+        incident_type: update.incident_type,
+        affected_area: update.affected_area,
+        status: update.status,
+        end_time,
+        ..incident
+    });
+}
+
+pub fn clear() {
+    INCIDENTS.with(|incidents| {
+        let mut incidents = incidents.borrow_mut();
+        let keys: Vec<_> = incidents.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            incidents.remove(&key);
+        }
+    });
+}
+
+// The active incident whose affected area covers a point. Where several do,
+// the one that started last is the more specific response.
+pub fn covering(point: &GeoPoint) -> Option<String> {
+    all()
+        .into_iter()
+        .filter(|incident| incident.status == IncidentStatus::Active)
+        .filter(|incident| incident.affected_area.iter().any(|area| area.contains(point)))
+        .max_by_key(|incident| incident.start_time)
+        .map(|incident| incident.id)
+}
+
+fn count<K: PartialEq>(counts: &mut Vec<(K, u64)>, key: K) {
+    match counts.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, n)) => *n += 1,
+        None => counts.push((key, 1)),
+    }
+}
+
+// `visible` decides which organizations' records count
+pub fn dashboard(incident_id: &str, visible: impl Fn(Option<&String>) -> bool) -> IncidentDashboard {
+    let mut dashboard = IncidentDashboard::default();
+    let mut volunteers: Vec<String> = Vec::new();
+    let requests: Vec<HelpRequest> = HELP_REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .entries_of(index::requests_of_incident(incident_id))
+            .map(|(_, request)| request)
+            .filter(|request| visible(request.organization_id.as_ref()))
+            .collect()
+    });
+    for request in requests {
+        count(&mut dashboard.requests_by_status, request.status);
+        count(&mut dashboard.requests_by_urgency, request.urgency.clone());
+        let open = !request.status.allowed_next().is_empty();
+        match request.assigned_volunteer {
+            Some(volunteer) if open => volunteers.push(volunteer),
+            None if open => dashboard.unassigned_open_requests += 1,
+            _ => {}
+        }
+    }
+    volunteers.sort();
+    volunteers.dedup();
+    dashboard.volunteers_engaged = volunteers.len() as u64;
+
+    SUPPLY_BUNDLES.with(|bundles| {
+        for (_, bundle) in bundles.borrow().entries_of(index::bundles_of_incident(incident_id)) {
+            if visible(Some(&bundle.organization_id)) {
+                dashboard.supply_bundles += 1;
+                if bundle.status == "distributed" {
+                    dashboard.supply_bundles_distributed += 1;
+                }
+            }
+        }
+    });
+    DONATIONS.with(|donations| {
+        for (_, donation) in donations.borrow().entries_of(index::donations_of_incident(incident_id)) {
+            if visible(Some(&donation.organization_id)) {
+                dashboard.donations += 1;
+                dashboard.donated_amount += donation.amount;
+            }
+        }
+    });
+    dashboard
+}
@@ -0,0 +1,325 @@
+use ic_stable_structures::{BoundedStorable, StableBTreeMap};
+use std::cell::RefCell;
+use std::ops::Bound;
+use std::thread::LocalKey;
+
+use crate::auth::Role;
+use crate::geo;
+use crate::organization::{self, Organization};
+use crate::{
+    Donation, HelpRequest, MemoryType, StableString, SupplyBundle, User, BUNDLES_BY_INCIDENT,
+    BUNDLES_BY_ORGANIZATION, DONATIONS, DONATIONS_BY_DONOR, DONATIONS_BY_INCIDENT,
+    DONATIONS_BY_ORGANIZATION, HELP_REQUESTS, ORGANIZATIONS, ORGANIZATIONS_BY_MEMBER,
+    REQUESTS_BY_INCIDENT, REQUESTS_BY_ORGANIZATION, REQUESTS_BY_VICTIM, REQUESTS_BY_VOLUNTEER,
+    SUPPLY_BUNDLES, UNROUTED_REQUESTS, USERS, USERS_BY_ROLE,
+};
+
+// Secondary indexes map (indexed value, primary key) -> (), so the primary
+// keys sharing one value are a contiguous range in primary key order. They
+// are kept up to date by the change hooks of the primary maps.
+pub type Index<P> = StableBTreeMap<(StableString, P), (), MemoryType>;
+
+type IndexKey<P> = &'static LocalKey<RefCell<Index<P>>>;
+
+fn update<P>(index: IndexKey<P>, primary: &P, old: Option<&str>, new: Option<&str>)
+where
+    P: BoundedStorable + Ord + Clone + Default,
+{
+    if old == new {
+        return;
+    }
+    index.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(old) = old {
+            index.remove(&(StableString::from(old.to_string()), primary.clone()));
+        }
+        if let Some(new) = new {
+            index.insert((StableString::from(new.to_string()), primary.clone()), ());
+        }
+    });
+}
+
+// For records indexed under several values at once
+fn update_all<P>(index: IndexKey<P>, primary: &P, old: &[String], new: &[String])
+where
+    P: BoundedStorable + Ord + Clone + Default,
+{
+    for value in old.iter().filter(|value| !new.contains(value)) {
+        update(index, primary, Some(value), None);
+    }
+    for value in new.iter().filter(|value| !old.contains(value)) {
+        update(index, primary, None, Some(value));
+    }
+}
+
+// Primary keys of the records indexed under `value`, in primary key order
+fn lookup<P>(index: IndexKey<P>, value: &str) -> Vec<P>
+where
+    P: BoundedStorable + Ord + Clone + Default,
+{
+    let start = (StableString::from(value.to_string()), P::default());
+    index.with(|index| {
+        index
+            .borrow()
+            .range((Bound::Included(start), Bound::Unbounded))
+            .take_while(|((indexed, _), _)| indexed.as_ref() == value)
+            .map(|((_, primary), _)| primary)
+            .collect()
+    })
+}
+
+fn clear<P>(index: IndexKey<P>)
+where
+    P: BoundedStorable + Ord + Clone + Default,
+{
+    index.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<_> = index.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+}
+
+fn role_key(role: Role) -> String {
+    format!("{:?}", role)
+}
+
+// The fallback queue is an index with a single value
+const UNROUTED: &str = "unrouted";
+
+fn unrouted_key(request: &HelpRequest) -> Option<&'static str> {
+    organization::is_unrouted(request).then_some(UNROUTED)
+}
+
+pub fn on_request_change(request_id: &u64, old: Option<&HelpRequest>, new: Option<&HelpRequest>) {
+    geo::on_request_change(request_id, old, new);
+    update(
+        &REQUESTS_BY_VICTIM,
+        request_id,
+        old.map(|r| r.victim_id.as_str()),
+        new.map(|r| r.victim_id.as_str()),
+    );
+    update(
+        &REQUESTS_BY_VOLUNTEER,
+        request_id,
+        old.and_then(|r| r.assigned_volunteer.as_deref()),
+        new.and_then(|r| r.assigned_volunteer.as_deref()),
+    );
+    update(
+        &REQUESTS_BY_ORGANIZATION,
+        request_id,
+        old.and_then(|r| r.organization_id.as_deref()),
+        new.and_then(|r| r.organization_id.as_deref()),
+    );
+    update(
+        &REQUESTS_BY_INCIDENT,
+        request_id,
+        old.and_then(|r| r.incident_id.as_deref()),
+        new.and_then(|r| r.incident_id.as_deref()),
+    );
+    update(&UNROUTED_REQUESTS, request_id, old.and_then(unrouted_key), new.and_then(unrouted_key));
+}
+
+pub fn on_donation_change(id: &StableString, old: Option<&Donation>, new: Option<&Donation>) {
+    update(
+        &DONATIONS_BY_DONOR,
+        id,
+        old.map(|d| d.donor_email.as_str()),
+        new.map(|d| d.donor_email.as_str()),
+    );
+    update(
+        &DONATIONS_BY_ORGANIZATION,
+        id,
+        old.map(|d| d.organization_id.as_str()),
+        new.map(|d| d.organization_id.as_str()),
+    );
+    update(
+        &DONATIONS_BY_INCIDENT,
+        id,
+        old.and_then(|d| d.incident_id.as_deref()),
+        new.and_then(|d| d.incident_id.as_deref()),
+    );
+}
+
+pub fn on_bundle_change(id: &StableString, old: Option<&SupplyBundle>, new: Option<&SupplyBundle>) {
+    update(
+        &BUNDLES_BY_ORGANIZATION,
+        id,
+        old.map(|b| b.organization_id.as_str()),
+        new.map(|b| b.organization_id.as_str()),
+    );
+    update(
+        &BUNDLES_BY_INCIDENT,
+        id,
+        old.and_then(|b| b.incident_id.as_deref()),
+        new.and_then(|b| b.incident_id.as_deref()),
+    );
+}
+
+pub fn on_organization_change(id: &StableString, old: Option<&Organization>, new: Option<&Organization>) {
+    let members = |organization: Option<&Organization>| organization.map(|o| o.members.clone()).unwrap_or_default();
+    update_all(&ORGANIZATIONS_BY_MEMBER, id, &members(old), &members(new));
+}
+
+pub fn on_user_change(email: &StableString, old: Option<&User>, new: Option<&User>) {
+    let old_role = old.map(|u| role_key(u.role));
+    let new_role = new.map(|u| role_key(u.role));
+    update(&USERS_BY_ROLE, email, old_role.as_deref(), new_role.as_deref());
+}
+
+pub fn requests_of_victim(victim_id: &str) -> Vec<u64> {
+    lookup(&REQUESTS_BY_VICTIM, victim_id)
+}
+
+pub fn requests_of_volunteer(volunteer_id: &str) -> Vec<u64> {
+    lookup(&REQUESTS_BY_VOLUNTEER, volunteer_id)
+}
+
+pub fn requests_of_organization(organization_id: &str) -> Vec<u64> {
+    lookup(&REQUESTS_BY_ORGANIZATION, organization_id)
+}
+
+pub fn donations_of_donor(donor_email: &str) -> Vec<StableString> {
+    lookup(&DONATIONS_BY_DONOR, donor_email)
+}
+
+pub fn users_with_role(role: Role) -> Vec<StableString> {
+    lookup(&USERS_BY_ROLE, &role_key(role))
+}
+
+pub fn donations_of_organization(organization_id: &str) -> Vec<StableString> {
+    lookup(&DONATIONS_BY_ORGANIZATION, organization_id)
+}
+
+pub fn bundles_of_organization(organization_id: &str) -> Vec<StableString> {
+    lookup(&BUNDLES_BY_ORGANIZATION, organization_id)
+}
+
+pub fn organizations_of_member(email: &str) -> Vec<StableString> {
+    lookup(&ORGANIZATIONS_BY_MEMBER, email)
+}
+
+pub fn requests_of_incident(incident_id: &str) -> Vec<u64> {
+    lookup(&REQUESTS_BY_INCIDENT, incident_id)
+}
+
+pub fn bundles_of_incident(incident_id: &str) -> Vec<StableString> {
+    lookup(&BUNDLES_BY_INCIDENT, incident_id)
+}
+
+pub fn donations_of_incident(incident_id: &str) -> Vec<StableString> {
+    lookup(&DONATIONS_BY_INCIDENT, incident_id)
+}
+
+// Every request has one victim and every user one role, so these indexes
+// hold exactly one entry per record
+pub fn request_count() -> u64 {
+    REQUESTS_BY_VICTIM.with(|index| index.borrow().len())
+}
+
+pub fn user_count() -> u64 {
+    USERS_BY_ROLE.with(|index| index.borrow().len())
+}
+
+pub fn unrouted_requests() -> Vec<u64> {
+    lookup(&UNROUTED_REQUESTS, UNROUTED)
+}
+
+// Index every stored record from scratch
+pub fn rebuild() {
+    clear(&REQUESTS_BY_VICTIM);
+    clear(&REQUESTS_BY_VOLUNTEER);
+    clear(&REQUESTS_BY_ORGANIZATION);
+    clear(&DONATIONS_BY_DONOR);
+    clear(&USERS_BY_ROLE);
+    clear(&DONATIONS_BY_ORGANIZATION);
+    clear(&BUNDLES_BY_ORGANIZATION);
+    clear(&ORGANIZATIONS_BY_MEMBER);
+    clear(&REQUESTS_BY_INCIDENT);
+    clear(&BUNDLES_BY_INCIDENT);
+    clear(&DONATIONS_BY_INCIDENT);
+    clear(&UNROUTED_REQUESTS);
+    geo::clear_index();
+    HELP_REQUESTS.with(|requests| {
+        for (request_id, request) in requests.borrow().iter() {
+            on_request_change(&request_id, None, Some(&request));
+        }
+    });
+    DONATIONS.with(|donations| {
+        for (id, donation) in donations.borrow().iter() {
+            on_donation_change(&id, None, Some(&donation));
+        }
+    });
+    USERS.with(|users| {
+        for (email, user) in users.borrow().iter() {
+            on_user_change(&email, None, Some(&user));
+        }
+    });
+    SUPPLY_BUNDLES.with(|bundles| {
+        for (id, bundle) in bundles.borrow().iter() {
+            on_bundle_change(&id, None, Some(&bundle));
+        }
+    });
+    ORGANIZATIONS.with(|organizations| {
+        for (id, organization) in organizations.borrow().iter() {
+            on_organization_change(&id, None, Some(&organization));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_stay_within_one_value() {
+        update(&REQUESTS_BY_VICTIM, &2, None, Some("ann@example.org"));
+        update(&REQUESTS_BY_VICTIM, &1, None, Some("ann@example.org"));
+        update(&REQUESTS_BY_VICTIM, &3, None, Some("ann@example.org.uk"));
+        update(&REQUESTS_BY_VICTIM, &4, None, Some("amy@example.org"));
+        assert_eq!(requests_of_victim("ann@example.org"), [1, 2]);
+
+        update(&REQUESTS_BY_VICTIM, &2, Some("ann@example.org"), Some("amy@example.org"));
+        update(&REQUESTS_BY_VICTIM, &3, Some("ann@example.org.uk"), None);
+        assert_eq!(requests_of_victim("ann@example.org"), [1]);
+        assert_eq!(requests_of_victim("amy@example.org"), [2, 4]);
+        assert!(requests_of_victim("ann@example.org.uk").is_empty());
+    }
+
+    #[test]
+    fn unrouted_requests_leave_the_queue_once_taken_or_finished() {
+        use crate::status::RequestStatus;
+
+        let request = |organization_id: Option<&str>, status| HelpRequest {
+            request_id: 1,
+            victim_id: "ann@example.org".to_string(),
+            request_type: "food".to_string(),
+            description: String::new(),
+            urgency: "high".to_string(),
+            location: String::new(),
+            status,
+            timestamp: 0,
+            coordinates: None,
+            verification_note: None,
+            verified_by: None,
+            organization_id: organization_id.map(str::to_string),
+            assigned_volunteer: None,
+            incident_id: None,
+        };
+        let unrouted = request(None, RequestStatus::Pending);
+        on_request_change(&1, None, Some(&unrouted));
+        on_request_change(&2, None, Some(&request(None, RequestStatus::Rejected)));
+        on_request_change(&3, None, Some(&request(Some("red-cross"), RequestStatus::Pending)));
+        assert_eq!(unrouted_requests(), [1]);
+
+        let routed = request(Some("red-cross"), RequestStatus::Pending);
+        on_request_change(&1, Some(&unrouted), Some(&routed));
+        assert!(unrouted_requests().is_empty());
+
+        let cancelled = request(None, RequestStatus::Cancelled);
+        on_request_change(&1, Some(&routed), Some(&unrouted));
+        on_request_change(&1, Some(&unrouted), Some(&cancelled));
+        assert!(unrouted_requests().is_empty());
+    }
+}
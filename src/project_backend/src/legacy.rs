@@ -0,0 +1,164 @@
+use std::cell::Cell;
+
+use crate::geo::GeoPoint;
+
+// Coordinates and timestamps used to be stored as text. These turn that text
+// into the numeric fields that replaced it, reporting whatever doesn't parse.
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+thread_local! {
+    // Fields that failed to convert since the canister was last started
+    static UNPARSEABLE_FIELDS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn report(record: &str, field: &str, value: &str, fallback: &str) {
+    ic_cdk::println!("{}: {} {:?} could not be parsed, {}", record, field, value, fallback);
+    UNPARSEABLE_FIELDS.with(|count| count.set(count.get() + 1));
+}
+
+pub fn unparseable_fields() -> u64 {
+    UNPARSEABLE_FIELDS.with(|count| count.get())
+}
+
+// The stored point, or none if either coordinate isn't a valid number
+pub fn point(record: &str, latitude: &str, longitude: &str) -> Option<GeoPoint> {
+    let point = match (latitude.trim().parse(), longitude.trim().parse()) {
+        (Ok(lat), Ok(lon)) => Some(GeoPoint { lat, lon }).filter(GeoPoint::is_valid),
+        _ => None,
+    };
+    if point.is_none() {
+        let value = format!("{}, {}", latitude, longitude);
+        report(record, "coordinates", &value, "stored without coordinates");
+    }
+    point
+}
+
+// Nanoseconds since the epoch, or 0 if the text isn't a timestamp
+pub fn timestamp(record: &str, field: &str, value: &str) -> u64 {
+    parse_timestamp(value).unwrap_or_else(|| {
+        report(record, field, value, "stored as 0");
+        0
+    })
+}
+
+// Stored timestamps are either numbers, as written by the canister (ns) and by
+// browsers' Date.now() (ms), or ISO 8601 text from Date.toISOString()
+pub fn parse_timestamp(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        let number: u64 = value.parse().ok()?;
+        // Anything from 1973 on is unambiguous in every unit
+        let multiplier: u64 = match number {
+            n if n >= 100_000_000_000_000_000 => 1,
+            n if n >= 100_000_000_000_000 => 1_000,
+            n if n >= 100_000_000_000 => 1_000_000,
+            _ => 1_000_000_000,
+        };
+        return number.checked_mul(multiplier);
+    }
+    parse_iso8601(value)
+}
+
+// YYYY-MM-DD, optionally followed by THH:MM[:SS[.fraction]] and Z or +-HH:MM
+fn parse_iso8601(value: &str) -> Option<u64> {
+    let (date, time) = match value.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let mut fields = date.splitn(3, '-');
+    let year: i64 = fields.next()?.parse().ok()?;
+    let month: i64 = fields.next()?.parse().ok()?;
+    let day: i64 = fields.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut nanos = days_from_civil(year, month, day) * SECONDS_PER_DAY * NANOS_PER_SECOND;
+    if let Some(time) = time {
+        let (clock, offset_seconds) = split_offset(time)?;
+        nanos += clock_nanos(clock)? - offset_seconds * NANOS_PER_SECOND;
+    }
+    u64::try_from(nanos).ok()
+}
+
+fn split_offset(time: &str) -> Option<(&str, i64)> {
+    if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        return Some((clock, 0));
+    }
+    let Some(sign_at) = time.rfind(['+', '-']) else {
+        return Some((time, 0));
+    };
+    let (clock, offset) = time.split_at(sign_at);
+    let sign = if offset.starts_with('-') { -1 } else { 1 };
+    let (hours, minutes) = offset[1..].split_once(':').unwrap_or((&offset[1..], "0"));
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    Some((clock, sign * (hours * 3_600 + minutes * 60)))
+}
+
+fn clock_nanos(clock: &str) -> Option<i64> {
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut fields = clock.splitn(3, ':');
+    let hours: i64 = fields.next()?.parse().ok()?;
+    let minutes: i64 = fields.next()?.parse().ok()?;
+    let seconds: i64 = fields.next().map_or(Some(0), |s| s.parse().ok())?;
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let mut fraction_nanos = 0;
+    if !fraction.is_empty() {
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let digits = &fraction[..fraction.len().min(9)];
+        fraction_nanos = digits.parse::<i64>().ok()? * 10_i64.pow(9 - digits.len() as u32);
+    }
+    Some((hours * 3_600 + minutes * 60 + seconds) * NANOS_PER_SECOND + fraction_nanos)
+}
+
+// Days between 1970-01-01 and the given date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JAN_15_2024_1030_UTC: u64 = 1_705_314_600 * 1_000_000_000;
+
+    #[test]
+    fn numeric_timestamps_in_any_unit() {
+        assert_eq!(parse_timestamp("1705314600"), Some(JAN_15_2024_1030_UTC));
+        assert_eq!(parse_timestamp("1705314600000"), Some(JAN_15_2024_1030_UTC));
+        assert_eq!(parse_timestamp("1705314600000000000"), Some(JAN_15_2024_1030_UTC));
+    }
+
+    #[test]
+    fn iso8601_timestamps() {
+        assert_eq!(parse_timestamp("2024-01-15T10:30:00.000Z"), Some(JAN_15_2024_1030_UTC));
+        assert_eq!(parse_timestamp("2024-01-15T12:30:00+02:00"), Some(JAN_15_2024_1030_UTC));
+        assert_eq!(parse_timestamp("2024-01-15 10:30"), Some(JAN_15_2024_1030_UTC));
+        assert_eq!(
+            parse_timestamp("2024-01-15T10:30:00.5Z"),
+            Some(JAN_15_2024_1030_UTC + 500_000_000)
+        );
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2000-03-01"), Some(951_868_800 * 1_000_000_000));
+    }
+
+    #[test]
+    fn rejects_text_that_is_not_a_timestamp() {
+        for bad in ["", "yesterday", "2024-13-01", "2024-01-15T25:00Z", "1969-12-31", "2024-01-15T10:30:00.x"] {
+            assert_eq!(parse_timestamp(bad), None, "{}", bad);
+        }
+    }
+}
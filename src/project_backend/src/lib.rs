@@ -1,48 +1,168 @@
-use candid::CandidType;
+use candid::{CandidType, Principal};
 use ic_cdk_macros::{init, query, update, post_upgrade, pre_upgrade};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{BoundedStorable, DefaultMemoryImpl, StableBTreeMap, Storable};
+use ic_stable_structures::{BoundedStorable, DefaultMemoryImpl, StableBTreeMap, StableCell, StableLog, Storable};
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::borrow::Cow;
-use std::cmp::Ordering;
-use std::collections::HashMap;
 
-const MAX_VALUE_SIZE: u32 = 100;
+mod audit;
+mod auth;
+mod chunked;
+mod error;
+mod geo;
+mod history;
+mod incident;
+mod index;
+mod legacy;
+mod matching;
+mod organization;
+mod pagination;
+mod password;
+mod privacy;
+mod profile;
+mod schema;
+mod status;
+mod tracking;
+mod validation;
+
+use audit::{AuditEntry, AuditFilter, AuditLogPage};
+use auth::{caller_is_not_anonymous, Permission, Role, Session};
+use chunked::ChunkedMap;
+use error::BackendError;
+use geo::{BoundingBox, GeoPoint};
+use history::RequestEvent;
+use incident::{Incident, IncidentDashboard, IncidentUpdate, NewIncident};
+use matching::{MatchCandidate, MatchingConfig};
+use organization::{NewOrganization, Organization, OrganizationSummary, OrganizationUpdate, Scope};
+use pagination::Page;
+use password::Credential;
+use profile::{VolunteerFilter, VolunteerProfile, VolunteerProfileUpdate};
+use schema::Versioned;
+use status::RequestStatus;
+use tracking::{Breadcrumb, Presence, TrackingConfig};
+use validation::Validate;
 
 type MemoryType = VirtualMemory<DefaultMemoryImpl>;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct User {
+    pub email: String,
+    pub credential: Credential,
+    pub name: String,
+    pub role: Role,
+    pub phone: String,
+    pub address: String,
+    pub principal: Option<Principal>,
+}
+
+// Layout of users stored with a plaintext password, either with the
+// free-form user_type/is_admin pair or with a typed role
+#[derive(CandidType, Deserialize)]
+struct LegacyUser {
+    email: String,
+    password: String,
+    name: String,
+    user_type: Option<String>,
+    is_admin: Option<bool>,
+    role: Option<Role>,
+    phone: String,
+    address: String,
+    principal: Option<Principal>,
+}
+
+impl From<LegacyUser> for User {
+    fn from(legacy: LegacyUser) -> Self {
+        let role = legacy.role.unwrap_or_else(|| {
+            Role::from_legacy(
+                legacy.user_type.as_deref().unwrap_or_default(),
+                legacy.is_admin.unwrap_or(false),
+            )
+        });
+        User {
+            role,
+            email: legacy.email,
+            credential: Credential::Plaintext(legacy.password),
+            name: legacy.name,
+            phone: legacy.phone,
+            address: legacy.address,
+            principal: legacy.principal,
+        }
+    }
+}
+
+// What the API exposes about an account; credentials never leave the canister
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UserProfile {
+    pub email: String,
+    pub name: String,
+    pub role: Role,
+    pub phone: String,
+    pub address: String,
+    pub principal: Option<Principal>,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        UserProfile {
+            email: user.email,
+            name: user.name,
+            role: user.role,
+            phone: user.phone,
+            address: user.address,
+            principal: user.principal,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct UserRegistration {
     pub email: String,
     pub password: String,
     pub name: String,
-    pub user_type: String,
+    pub role: Role,
+    pub phone: String,
+    pub address: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct UserUpdate {
+    pub name: String,
+    pub role: Role,
     pub phone: String,
     pub address: String,
-    pub is_admin: bool,
+    // Only changed when set
+    pub password: Option<String>,
+}
+
+impl Versioned for User {
+    const VERSION: u8 = 2;
+
+    // Version 1 had no provisional credentials and decodes as it is. Bare
+    // records are either in that layout or LegacyUser.
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        candid::decode_one(bytes).unwrap_or_else(|_| {
+            let legacy: LegacyUser = schema::decode_or_trap(version, bytes);
+            legacy.into()
+        })
+    }
 }
 
 impl Storable for User {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::from_envelope(&bytes)
     }
 }
 
-impl BoundedStorable for User {
-    const MAX_SIZE: u32 = 512;
-    const IS_FIXED_SIZE: bool = false;
-}
-
 #[derive(Default, Clone, Eq, PartialEq, Ord, PartialOrd)]
 struct StableString(String);
 
 impl Storable for StableString {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(self.0.as_bytes().to_vec())
     }
 
@@ -68,102 +188,362 @@ impl AsRef<str> for StableString {
     }
 }
 
-// Memory IDs for different data types
-const USERS_MEM_ID: MemoryId = MemoryId::new(0);
-const HELP_REQUESTS_MEM_ID: MemoryId = MemoryId::new(1);
-const SUPPLY_BUNDLES_MEM_ID: MemoryId = MemoryId::new(2);
-const DONATIONS_MEM_ID: MemoryId = MemoryId::new(3);
-const VOLUNTEER_LOCATIONS_MEM_ID: MemoryId = MemoryId::new(4);
+// Memory IDs for different data types.
+// The BOUNDED_ regions hold maps that stored whole records with a fixed size
+// bound; they are only read to move their records into the chunked maps.
+const BOUNDED_USERS_MEM_ID: MemoryId = MemoryId::new(0);
+// Help requests keyed by "victim_timestamp", read only to migrate them
+const LEGACY_HELP_REQUESTS_MEM_ID: MemoryId = MemoryId::new(1);
+const BOUNDED_SUPPLY_BUNDLES_MEM_ID: MemoryId = MemoryId::new(2);
+const BOUNDED_DONATIONS_MEM_ID: MemoryId = MemoryId::new(3);
+const BOUNDED_VOLUNTEER_LOCATIONS_MEM_ID: MemoryId = MemoryId::new(4);
+const PRINCIPALS_MEM_ID: MemoryId = MemoryId::new(5);
+const BOUNDED_SESSIONS_MEM_ID: MemoryId = MemoryId::new(6);
+const BOUNDED_HELP_REQUESTS_MEM_ID: MemoryId = MemoryId::new(7);
+const REQUEST_ID_COUNTER_MEM_ID: MemoryId = MemoryId::new(8);
+const BOUNDED_REQUEST_HISTORY_MEM_ID: MemoryId = MemoryId::new(9);
+const AUDIT_LOG_INDEX_MEM_ID: MemoryId = MemoryId::new(10);
+const AUDIT_LOG_DATA_MEM_ID: MemoryId = MemoryId::new(11);
+const GEO_INDEX_MEM_ID: MemoryId = MemoryId::new(12);
+const SCHEMA_VERSION_MEM_ID: MemoryId = MemoryId::new(13);
+const USERS_MEM_ID: MemoryId = MemoryId::new(14);
+const HELP_REQUESTS_MEM_ID: MemoryId = MemoryId::new(15);
+const SUPPLY_BUNDLES_MEM_ID: MemoryId = MemoryId::new(16);
+const DONATIONS_MEM_ID: MemoryId = MemoryId::new(17);
+const VOLUNTEER_LOCATIONS_MEM_ID: MemoryId = MemoryId::new(18);
+const SESSIONS_MEM_ID: MemoryId = MemoryId::new(19);
+const REQUEST_HISTORY_MEM_ID: MemoryId = MemoryId::new(20);
+const REQUESTS_BY_VICTIM_MEM_ID: MemoryId = MemoryId::new(21);
+const REQUESTS_BY_VOLUNTEER_MEM_ID: MemoryId = MemoryId::new(22);
+const REQUESTS_BY_ORGANIZATION_MEM_ID: MemoryId = MemoryId::new(23);
+const DONATIONS_BY_DONOR_MEM_ID: MemoryId = MemoryId::new(24);
+const USERS_BY_ROLE_MEM_ID: MemoryId = MemoryId::new(25);
+const VOLUNTEER_PROFILES_MEM_ID: MemoryId = MemoryId::new(26);
+const MATCHING_CONFIG_MEM_ID: MemoryId = MemoryId::new(27);
+const LOCATION_TRAILS_MEM_ID: MemoryId = MemoryId::new(28);
+const TRACKING_CONFIG_MEM_ID: MemoryId = MemoryId::new(29);
+const ORGANIZATIONS_MEM_ID: MemoryId = MemoryId::new(30);
+const ORGANIZATIONS_BY_MEMBER_MEM_ID: MemoryId = MemoryId::new(31);
+const BUNDLES_BY_ORGANIZATION_MEM_ID: MemoryId = MemoryId::new(32);
+const DONATIONS_BY_ORGANIZATION_MEM_ID: MemoryId = MemoryId::new(33);
+const INCIDENTS_MEM_ID: MemoryId = MemoryId::new(34);
+const REQUESTS_BY_INCIDENT_MEM_ID: MemoryId = MemoryId::new(35);
+const BUNDLES_BY_INCIDENT_MEM_ID: MemoryId = MemoryId::new(36);
+const DONATIONS_BY_INCIDENT_MEM_ID: MemoryId = MemoryId::new(37);
+const UNROUTED_REQUESTS_MEM_ID: MemoryId = MemoryId::new(38);
+const VOLUNTEER_GRID_MEM_ID: MemoryId = MemoryId::new(39);
+const MEMORY_REGION_COUNT: u8 = 38;
+
+// The memory manager can't grow past MAX_NUM_BUCKETS * BUCKET_SIZE_IN_PAGES Wasm
+// pages; new records are refused once stable memory gets within the headroom of it
+const MAX_STABLE_MEMORY_PAGES: u64 = 32_768 * 128;
+const STABLE_MEMORY_HEADROOM_PAGES: u64 = 1_024;
+
+fn ensure_storage_available() -> Result<(), BackendError> {
+    if ic_cdk::api::stable::stable64_size() + STABLE_MEMORY_HEADROOM_PAGES >= MAX_STABLE_MEMORY_PAGES {
+        ic_cdk::println!("Stable memory is full");
+        return Err(BackendError::StorageFull);
+    }
+    Ok(())
+}
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
         MemoryManager::init(DefaultMemoryImpl::default())
     );
 
-    static USERS: RefCell<StableBTreeMap<StableString, User, MemoryType>> = RefCell::new(
+    static USERS: RefCell<ChunkedMap<StableString, User>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USERS_MEM_ID))
+        ).with_change_hook(index::on_user_change)
+    );
+
+    // Server-assigned request id -> help request
+    static HELP_REQUESTS: RefCell<ChunkedMap<u64, HelpRequest>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HELP_REQUESTS_MEM_ID))
+        ).with_change_hook(index::on_request_change)
+    );
+
+    // Secondary indexes of the maps above, see index.rs
+    static REQUESTS_BY_VICTIM: RefCell<index::Index<u64>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_BY_VICTIM_MEM_ID))
+        )
+    );
+
+    static REQUESTS_BY_VOLUNTEER: RefCell<index::Index<u64>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_BY_VOLUNTEER_MEM_ID))
+        )
+    );
+
+    static REQUESTS_BY_ORGANIZATION: RefCell<index::Index<u64>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_BY_ORGANIZATION_MEM_ID))
+        )
+    );
+
+    static DONATIONS_BY_DONOR: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DONATIONS_BY_DONOR_MEM_ID))
+        )
+    );
+
+    static USERS_BY_ROLE: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USERS_BY_ROLE_MEM_ID))
+        )
+    );
+
+    static DONATIONS_BY_ORGANIZATION: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DONATIONS_BY_ORGANIZATION_MEM_ID))
+        )
+    );
+
+    static BUNDLES_BY_ORGANIZATION: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BUNDLES_BY_ORGANIZATION_MEM_ID))
+        )
+    );
+
+    static ORGANIZATIONS_BY_MEMBER: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ORGANIZATIONS_BY_MEMBER_MEM_ID))
+        )
+    );
+
+    static REQUESTS_BY_INCIDENT: RefCell<index::Index<u64>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_BY_INCIDENT_MEM_ID))
+        )
+    );
+
+    static BUNDLES_BY_INCIDENT: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BUNDLES_BY_INCIDENT_MEM_ID))
+        )
+    );
+
+    static DONATIONS_BY_INCIDENT: RefCell<index::Index<StableString>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DONATIONS_BY_INCIDENT_MEM_ID))
+        )
+    );
+
+    static UNROUTED_REQUESTS: RefCell<index::Index<u64>> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
+            MEMORY_MANAGER.with(|m| m.borrow().get(UNROUTED_REQUESTS_MEM_ID))
         )
     );
 
-    static HELP_REQUESTS: RefCell<StableBTreeMap<StableString, HelpRequest, MemoryType>> = RefCell::new(
+    static LEGACY_HELP_REQUESTS: RefCell<StableBTreeMap<StableString, LegacyHelpRequest, MemoryType>> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+            MEMORY_MANAGER.with(|m| m.borrow().get(LEGACY_HELP_REQUESTS_MEM_ID))
+        )
+    );
+
+    // (request id, sequence number) -> status change; append-only
+    static REQUEST_HISTORY: RefCell<ChunkedMap<(u64, u64), RequestEvent>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUEST_HISTORY_MEM_ID))
         )
     );
 
-    static SUPPLY_BUNDLES: RefCell<StableBTreeMap<StableString, SupplyBundle, MemoryType>> = RefCell::new(
+    // Location index of help requests, see geo.rs
+    static GEO_INDEX: RefCell<StableBTreeMap<geo::GridKey, geo::Coordinates, MemoryType>> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+            MEMORY_MANAGER.with(|m| m.borrow().get(GEO_INDEX_MEM_ID))
         )
     );
 
-    static DONATIONS: RefCell<StableBTreeMap<StableString, Donation, MemoryType>> = RefCell::new(
+    static VOLUNTEER_GRID: RefCell<StableBTreeMap<geo::VolunteerGridKey, geo::Coordinates, MemoryType>> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+            MEMORY_MANAGER.with(|m| m.borrow().get(VOLUNTEER_GRID_MEM_ID))
+        )
+    );
+
+    // Append-only record of privileged calls
+    static AUDIT_LOG: RefCell<StableLog<AuditEntry, MemoryType, MemoryType>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_LOG_INDEX_MEM_ID)),
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_LOG_DATA_MEM_ID)),
+        ).expect("failed to initialize the audit log")
+    );
+
+    // Layout version of everything in stable memory, see schema.rs
+    static SCHEMA_VERSION: RefCell<StableCell<u32, MemoryType>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEMA_VERSION_MEM_ID)),
+            schema::UNVERSIONED_SCHEMA,
+        ).expect("failed to initialize the schema version")
+    );
+
+    // The id the next help request will get; ids are never reused
+    static NEXT_REQUEST_ID: RefCell<StableCell<u64, MemoryType>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUEST_ID_COUNTER_MEM_ID)),
+            1,
+        ).expect("failed to initialize the request id counter")
+    );
+
+    static SUPPLY_BUNDLES: RefCell<ChunkedMap<StableString, SupplyBundle>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SUPPLY_BUNDLES_MEM_ID))
+        ).with_change_hook(index::on_bundle_change)
+    );
+
+    // Incident id -> incident, see incident.rs
+    static INCIDENTS: RefCell<ChunkedMap<StableString, Incident>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(INCIDENTS_MEM_ID))
+        )
+    );
+
+    // Organization id -> organization, see organization.rs
+    static ORGANIZATIONS: RefCell<ChunkedMap<StableString, Organization>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ORGANIZATIONS_MEM_ID))
+        ).with_change_hook(index::on_organization_change)
+    );
+
+    static DONATIONS: RefCell<ChunkedMap<StableString, Donation>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DONATIONS_MEM_ID))
+        ).with_change_hook(index::on_donation_change)
+    );
+
+    static VOLUNTEER_LOCATIONS: RefCell<ChunkedMap<StableString, VolunteerLocation>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(VOLUNTEER_LOCATIONS_MEM_ID))
+        ).with_change_hook(geo::on_volunteer_location_change)
+    );
+
+    // (volunteer email, time) -> reported position; bounded per volunteer, see tracking.rs
+    static LOCATION_TRAILS: RefCell<ChunkedMap<(StableString, u64), Breadcrumb>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LOCATION_TRAILS_MEM_ID))
         )
     );
 
-    static VOLUNTEER_LOCATIONS: RefCell<StableBTreeMap<StableString, VolunteerLocation, MemoryType>> = RefCell::new(
+    static TRACKING_CONFIG: RefCell<StableCell<TrackingConfig, MemoryType>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TRACKING_CONFIG_MEM_ID)),
+            TrackingConfig::default(),
+        ).expect("failed to initialize the tracking config")
+    );
+
+    // Volunteer email -> what they declared about themselves
+    static VOLUNTEER_PROFILES: RefCell<ChunkedMap<StableString, VolunteerProfile>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(VOLUNTEER_PROFILES_MEM_ID))
+        )
+    );
+
+    // Settings of the matching engine, see matching.rs
+    static MATCHING_CONFIG: RefCell<StableCell<MatchingConfig, MemoryType>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MATCHING_CONFIG_MEM_ID)),
+            MatchingConfig::default(),
+        ).expect("failed to initialize the matching config")
+    );
+
+    // Principal (text form) -> email of the account it is bound to
+    static PRINCIPALS: RefCell<StableBTreeMap<StableString, StableString, MemoryType>> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+            MEMORY_MANAGER.with(|m| m.borrow().get(PRINCIPALS_MEM_ID))
+        )
+    );
+
+    // Principal (text form) -> its current login session
+    static SESSIONS: RefCell<ChunkedMap<StableString, Session>> = RefCell::new(
+        ChunkedMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SESSIONS_MEM_ID))
         )
     );
 }
 
+#[derive(CandidType, Deserialize)]
+pub struct InitArgs {
+    // Principal of the first admin; defaults to the installer
+    pub admin: Option<Principal>,
+}
+
+const DEFAULT_ADMIN_EMAIL: &str = "admin@disasterrelief.com";
+
+fn seed_admin(principal: Principal) {
+    let admin_user = User {
+        email: DEFAULT_ADMIN_EMAIL.to_string(),
+        credential: Credential::Unset,
+        name: "Admin".to_string(),
+        role: Role::Admin,
+        phone: String::new(),
+        address: String::new(),
+        principal: Some(principal),
+    };
+    USERS.with(|users| {
+        users
+            .borrow_mut()
+            .insert(StableString::from(admin_user.email.clone()), admin_user);
+    });
+    PRINCIPALS.with(|principals| {
+        principals.borrow_mut().insert(
+            StableString::from(principal.to_text()),
+            StableString::from(DEFAULT_ADMIN_EMAIL.to_string()),
+        );
+    });
+    ic_cdk::println!("Bound admin account {} to principal {}", DEFAULT_ADMIN_EMAIL, principal);
+}
+
 #[init]
-fn init() {
+fn init(args: Option<InitArgs>) {
     // Initialize memory manager and pre-allocate ALL memory regions
     MEMORY_MANAGER.with(|m| {
-        let mut memory_manager = m.borrow_mut();
+        let memory_manager = m.borrow_mut();
         // Pre-allocate memory regions with specific sizes
-        memory_manager.get(MemoryId::new(0)); // Users - 1MB
-        memory_manager.get(MemoryId::new(1)); // Help Requests - 1MB
-        memory_manager.get(MemoryId::new(2)); // Supply Bundles - 1MB
-        memory_manager.get(MemoryId::new(3)); // Donations - 1MB
-        memory_manager.get(MemoryId::new(4)); // Volunteer Locations - 1MB
+        memory_manager.get(USERS_MEM_ID); // Users - 1MB
+        memory_manager.get(HELP_REQUESTS_MEM_ID); // Help Requests by id - 1MB
+        memory_manager.get(SUPPLY_BUNDLES_MEM_ID); // Supply Bundles - 1MB
+        memory_manager.get(DONATIONS_MEM_ID); // Donations - 1MB
+        memory_manager.get(VOLUNTEER_LOCATIONS_MEM_ID); // Volunteer Locations - 1MB
+        memory_manager.get(PRINCIPALS_MEM_ID); // Principal bindings - 1MB
+        memory_manager.get(SESSIONS_MEM_ID); // Login sessions - 1MB
+        memory_manager.get(REQUEST_ID_COUNTER_MEM_ID); // Request id counter
+        memory_manager.get(REQUEST_HISTORY_MEM_ID); // Request history - 1MB
+        memory_manager.get(AUDIT_LOG_INDEX_MEM_ID); // Audit log index
+        memory_manager.get(AUDIT_LOG_DATA_MEM_ID); // Audit log entries - 1MB
+        memory_manager.get(GEO_INDEX_MEM_ID); // Request location index - 1MB
+        memory_manager.get(SCHEMA_VERSION_MEM_ID); // Schema version
+        memory_manager.get(REQUESTS_BY_VICTIM_MEM_ID); // Requests by victim
+        memory_manager.get(REQUESTS_BY_VOLUNTEER_MEM_ID); // Requests by volunteer
+        memory_manager.get(REQUESTS_BY_ORGANIZATION_MEM_ID); // Requests by organization
+        memory_manager.get(DONATIONS_BY_DONOR_MEM_ID); // Donations by donor
+        memory_manager.get(USERS_BY_ROLE_MEM_ID); // Users by role
+        memory_manager.get(VOLUNTEER_PROFILES_MEM_ID); // Volunteer profiles - 1MB
+        memory_manager.get(MATCHING_CONFIG_MEM_ID); // Matching settings
+        memory_manager.get(LOCATION_TRAILS_MEM_ID); // Volunteer location trails - 1MB
+        memory_manager.get(TRACKING_CONFIG_MEM_ID); // Location tracking settings
+        memory_manager.get(ORGANIZATIONS_MEM_ID); // Organizations - 1MB
+        memory_manager.get(ORGANIZATIONS_BY_MEMBER_MEM_ID); // Organizations by member
+        memory_manager.get(BUNDLES_BY_ORGANIZATION_MEM_ID); // Supply bundles by organization
+        memory_manager.get(DONATIONS_BY_ORGANIZATION_MEM_ID); // Donations by organization
+        memory_manager.get(INCIDENTS_MEM_ID); // Incidents - 1MB
+        memory_manager.get(REQUESTS_BY_INCIDENT_MEM_ID); // Requests by incident
+        memory_manager.get(BUNDLES_BY_INCIDENT_MEM_ID); // Supply bundles by incident
+        memory_manager.get(DONATIONS_BY_INCIDENT_MEM_ID); // Donations by incident
+        memory_manager.get(UNROUTED_REQUESTS_MEM_ID); // Requests outside every service area
+        memory_manager.get(VOLUNTEER_GRID_MEM_ID); // Volunteer location index
     });
 
-    // Initialize all stable maps
-    USERS.with(|users| {
-        let mut users = users.borrow_mut();
-        if users.is_empty() {
-            // Only add default users if the map is empty
-            let admin_user = User {
-                email: "admin@disasterrelief.com".to_string(),
-                password: "admin123".to_string(),
-                name: "Admin".to_string(),
-                user_type: "admin".to_string(),
-                phone: "1234567890".to_string(),
-                address: "Admin Office".to_string(),
-                is_admin: true,
-            };
-
-            let org_user = User {
-                email: "organization@disasterrelief.com".to_string(),
-                password: "1234".to_string(),
-                name: "Organization".to_string(),
-                user_type: "organization".to_string(),
-                phone: "0987654321".to_string(),
-                address: "Organization HQ".to_string(),
-                is_admin: false,
-            };
-
-            let donor_user = User {
-                email: "donor@disasterrelief.com".to_string(),
-                password: "donor123".to_string(),
-                name: "Default Donor".to_string(),
-                user_type: "donor".to_string(),
-                phone: "5555555555".to_string(),
-                address: "123 Donor Street".to_string(),
-                is_admin: false,
-            };
-
-            users.insert(StableString::from(admin_user.email.clone()), admin_user);
-            users.insert(StableString::from(org_user.email.clone()), org_user);
-            users.insert(StableString::from(donor_user.email.clone()), donor_user);
-        }
-    });
+    // The first admin is bound to the installer, or to the principal given at
+    // install time. It has no password and signs in through that principal.
+    let admin = args.and_then(|args| args.admin).unwrap_or_else(ic_cdk::caller);
+    if admin == Principal::anonymous() {
+        ic_cdk::println!("Installed by the anonymous principal; no admin account was created");
+    } else {
+        seed_admin(admin);
+    }
 
     // Initialize other maps only if they haven't been initialized
     HELP_REQUESTS.with(|requests| {
@@ -181,217 +561,479 @@ fn init() {
     VOLUNTEER_LOCATIONS.with(|locations| {
         let _ = locations.borrow_mut();
     });
+
+    PRINCIPALS.with(|principals| {
+        let _ = principals.borrow_mut();
+    });
+
+    SESSIONS.with(|sessions| {
+        let _ = sessions.borrow_mut();
+    });
+
+    NEXT_REQUEST_ID.with(|counter| {
+        let _ = counter.borrow_mut();
+    });
+
+    REQUEST_HISTORY.with(|history| {
+        let _ = history.borrow_mut();
+    });
+
+    AUDIT_LOG.with(|log| {
+        let _ = log.borrow_mut();
+    });
+
+    GEO_INDEX.with(|index| {
+        let _ = index.borrow_mut();
+    });
+
+    schema::mark_current();
+
+    start_timers();
 }
 
-#[update]
-fn register_user(user: User) -> bool {
+#[update(guard = "caller_is_not_anonymous")]
+async fn register_user(registration: UserRegistration) -> Result<(), BackendError> {
+    if !registration.role.is_self_assignable() {
+        ic_cdk::println!("Role {:?} can't be chosen at registration", registration.role);
+        return Err(BackendError::Unauthorized {
+            role: registration.role,
+            permission: Permission::ManageUsers,
+        });
+    }
+    if let Some(existing) = auth::current_user() {
+        ic_cdk::println!("Caller is already registered as {}", existing.email);
+        return Err(BackendError::Conflict(format!(
+            "caller is already registered as {}",
+            existing.email
+        )));
+    }
+    registration.validate()?;
+    ensure_storage_available()?;
+
+    let salt = password::random_salt().await?;
+
+    let email = registration.email.clone();
     USERS.with(|users| {
         let mut users = users.borrow_mut();
         
         // Check if user already exists
-        if users.get(&StableString::from(user.email.clone())).is_some() {
+        if users.get(&StableString::from(registration.email.clone())).is_some() {
             ic_cdk::println!("User already exists");
-            return false;
+            return Err(BackendError::Conflict(format!(
+                "user {} already exists",
+                registration.email
+            )));
         }
 
+        // The principal binding is set by the canister, never by the client
+        let new_user = User {
+            credential: password::hash_password(&registration.password, salt),
+            email: registration.email,
+            name: registration.name,
+            role: registration.role,
+            phone: registration.phone,
+            address: registration.address,
+            principal: None,
+        };
+
         // Insert new user
-        match users.insert(StableString::from(user.email.clone()), user) {
-            Some(_) => {
-                ic_cdk::println!("Failed to register user");
-                false
-            }
-            None => {
-                ic_cdk::println!("Successfully registered user");
-                true
-            }
-        }
-    })
+        users.insert(StableString::from(new_user.email.clone()), new_user);
+        ic_cdk::println!("Successfully registered user");
+        Ok(())
+    })?;
+
+    auth::bind_caller(&email)
 }
 
 #[ic_cdk_macros::query]
-fn get_user(email: String) -> Option<User> {
+fn get_user(session_token: String, email: String) -> Result<Option<UserProfile>, BackendError> {
+    let caller = auth::authorize_owner_or(&session_token, &email, Permission::ViewUsers)?;
+    // Organization accounts only see the members of their own organizations
+    if caller.email != email {
+        if let Scope::Organizations(organization_ids) = organization::scope_of(&caller) {
+            if !organization_ids.iter().any(|id| organization::is_member(&email, id)) {
+                ic_cdk::println!("{} is not a member of any organization of {}", email, caller.email);
+                return Ok(None);
+            }
+        }
+    }
     ic_cdk::println!("Attempting to get user with email: {}", email);
-    USERS.with(|users| {
+    Ok(USERS.with(|users| {
         let users = users.borrow();
         let email_key = StableString::from(email);
         let result = users.get(&email_key).map(|u| {
             ic_cdk::println!("Found user: {:?}", u);
-            u.clone()
+            UserProfile::from(u)
         });
         if result.is_none() {
             ic_cdk::println!("No user found with this email");
         }
         result
-    })
+    }))
 }
 
-#[ic_cdk_macros::query]
-fn get_all_users() -> Vec<User> {
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct UserFilter {
+    pub role: Option<Role>,
+}
+
+impl UserFilter {
+    fn matches(&self, user: &User) -> bool {
+        self.role.is_none_or(|role| user.role == role)
+    }
+}
+
+// Users in email order; the users of one role are read through the role index
+// Organization accounts only list the members of their own organizations
+fn list_users(caller: &User, filter: &UserFilter, start_after: Option<String>, limit: Option<u32>) -> Page<String, UserProfile> {
+    let mut emails: Option<Vec<StableString>> = filter.role.map(index::users_with_role);
+    if let Scope::Organizations(ids) = organization::scope_of(caller) {
+        let mut members: Vec<StableString> = ids
+            .iter()
+            .filter_map(|id| organization::get(id))
+            .flat_map(|organization| organization.members)
+            .map(StableString)
+            .collect();
+        members.sort();
+        members.dedup();
+        emails = Some(match emails {
+            Some(emails) => emails.into_iter().filter(|email| members.binary_search(email).is_ok()).collect(),
+            None => members,
+        });
+    }
+    let total = emails.as_ref().map_or_else(index::user_count, |emails| emails.len() as u64);
+    let cursor = start_after.clone().map(StableString);
     USERS.with(|users| {
         let users = users.borrow();
-        users.iter().map(|(_, user)| user.clone()).collect()
+        let records: Box<dyn Iterator<Item = (StableString, User)>> = match emails {
+            Some(emails) => Box::new(users.entries_of(pagination::keys_after(emails, cursor.as_ref()))),
+            None => Box::new(users.iter_after(cursor.as_ref())),
+        };
+        pagination::paginate(
+            records.map(|(email, user)| (email.0, user)),
+            total,
+            start_after,
+            limit,
+            |user| filter.matches(user),
+            UserProfile::from,
+        )
     })
 }
 
-#[ic_cdk_macros::update]
-fn update_user(email: String, user: User) -> bool {
-    USERS.with(|users| {
-        let mut users = users.borrow_mut();
-        let email_key = StableString::from(email);
-        if !users.contains_key(&email_key) {
-            return false;
-        }
-        users.insert(email_key, user);
-        true
-    })
+#[ic_cdk_macros::query]
+fn get_all_users(
+    session_token: String,
+    filter: UserFilter,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<String, UserProfile>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ViewUsers)?;
+    Ok(list_users(&caller, &filter, start_after, limit))
 }
 
-#[ic_cdk_macros::update]
-fn delete_user(email: String) -> bool {
-    USERS.with(|users| {
-        let mut users = users.borrow_mut();
-        let email_key = StableString::from(email);
-        if !users.contains_key(&email_key) {
-            return false;
-        }
-        users.remove(&email_key);
-        true
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+async fn update_user(session_token: String, email: String, update: UserUpdate) -> Result<(), BackendError> {
+    // The new password is left out of the digest
+    let args_digest = audit::digest((
+        &email,
+        &update.name,
+        &update.role,
+        &update.phone,
+        &update.address,
+    ));
+    audit::audited_async("update_user", args_digest, async {
+        let caller = auth::authorize_owner_or(&session_token, &email, Permission::ManageUsers)?;
+        update.validate()?;
+        let can_manage_users = Permission::ManageUsers.is_granted_to(caller.role);
+
+        let credential = match update.password {
+            Some(new_password) => Some(password::hash_password(
+                &new_password,
+                password::random_salt().await?,
+            )),
+            None => None,
+        };
+
+        USERS.with(|users| {
+            let mut users = users.borrow_mut();
+            let email_key = StableString::from(email.clone());
+            let Some(existing) = users.get(&email_key) else {
+                return Err(BackendError::not_found("user", &email));
+            };
+
+            // The key and principal binding can't be changed through a profile update,
+            // and only user managers can change a role
+            let updated_user = User {
+                email,
+                credential: credential.unwrap_or(existing.credential),
+                name: update.name,
+                role: if can_manage_users { update.role } else { existing.role },
+                phone: update.phone,
+                address: update.address,
+                principal: existing.principal,
+            };
+            users.insert(email_key, updated_user);
+            Ok(())
+        })
     })
+    .await
 }
 
-#[ic_cdk_macros::query]
-fn is_admin(email: String) -> bool {
-    ic_cdk::println!("Checking admin status for email: {}", email);
-    USERS.with(|users| {
-        let users = users.borrow();
-        let email_key = StableString::from(email);
-        let result = users.get(&email_key).map(|user| {
-            ic_cdk::println!("Found user: {:?}, is_admin: {}", user.email, user.is_admin);
-            user.is_admin
-        }).unwrap_or_else(|| {
-            ic_cdk::println!("User not found");
-            false
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn delete_user(session_token: String, email: String) -> Result<(), BackendError> {
+    audit::audited("delete_user", audit::digest((&email,)), || {
+        auth::authorize_owner_or(&session_token, &email, Permission::ManageUsers)?;
+
+        let removed = USERS.with(|users| {
+            let mut users = users.borrow_mut();
+            let email_key = StableString::from(email.clone());
+            users.remove(&email_key)
         });
-        ic_cdk::println!("Returning admin status: {}", result);
-        result
+        let user = removed.ok_or_else(|| BackendError::not_found("user", &email))?;
+        auth::unbind(&user);
+        profile::remove(&user.email);
+        tracking::remove_volunteer(&user.email);
+        organization::remove_from_all(&user.email);
+        Ok(())
     })
 }
 
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct VolunteerListing {
+    pub user: UserProfile,
+    pub profile: VolunteerProfile,
+}
+
 #[ic_cdk_macros::query]
-fn verify_password(email: String, password: String) -> bool {
-    ic_cdk::println!("Verifying password for email: {}", email);
-    USERS.with(|users| {
+fn get_all_volunteers(
+    session_token: String,
+    filter: VolunteerFilter,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<String, VolunteerListing>, BackendError> {
+    auth::authorize(&session_token, Permission::ViewVolunteers)?;
+    let now = ic_cdk::api::time();
+    let volunteers = index::users_with_role(Role::Volunteer);
+    let total = volunteers.len() as u64;
+    let volunteers = pagination::keys_after(volunteers, start_after.clone().map(StableString).as_ref());
+    Ok(USERS.with(|users| {
         let users = users.borrow();
-        let email_key = StableString::from(email);
-        match users.get(&email_key) {
-            Some(user) => {
-                let result = user.password == password;
-                ic_cdk::println!("Password verification result: {}", result);
-                result
-            }
-            None => {
-                ic_cdk::println!("User not found");
-                false
-            }
+        let records = users
+            .entries_of(volunteers)
+            .map(|(email, user)| {
+                let profile = profile::get_or_default(&email.0);
+                (email.0, (user, profile))
+            });
+        pagination::paginate(
+            records,
+            total,
+            start_after,
+            limit,
+            |(_, profile)| filter.matches(profile, now),
+            |(user, profile)| VolunteerListing {
+                user: UserProfile::from(user),
+                profile,
+            },
+        )
+    }))
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+pub fn create_supply_bundle(session_token: String, bundle: SupplyBundle) -> Result<(), BackendError> {
+    audit::audited("create_supply_bundle", audit::digest((&bundle,)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageSupplies)?;
+        bundle.validate()?;
+        organization::ensure_member(&caller, &bundle.organization_id)?;
+        if let Some(incident_id) = &bundle.incident_id {
+            incident::find(incident_id)?;
         }
+        ensure_storage_available()?;
+        ic_cdk::println!("Creating supply bundle: {:?}", bundle);
+        SUPPLY_BUNDLES.with(|bundles| {
+            let mut bundles = bundles.borrow_mut();
+            let bundle_key = StableString::from(bundle.id.clone());
+            if bundles.contains_key(&bundle_key) {
+                return Err(BackendError::Conflict(format!(
+                    "supply bundle {} already exists",
+                    bundle.id
+                )));
+            }
+            let bundle = SupplyBundle {
+                created_at: ic_cdk::api::time(),
+                ..bundle.clone()
+            };
+            bundles.insert(bundle_key, bundle);
+            ic_cdk::println!("Successfully created supply bundle");
+            Ok(())
+        })
     })
 }
 
-#[ic_cdk_macros::query]
-fn get_all_volunteers() -> Vec<User> {
-    ic_cdk::println!("Getting all volunteers");
-    let volunteers = USERS.with(|users| {
-        users.borrow()
-            .iter()
-            .filter(|(_, user)| user.user_type == "volunteer")
-            .map(|(_, user)| user.clone())
-            .collect::<Vec<User>>()
-    });
-    ic_cdk::println!("Found {} volunteers", volunteers.len());
-    volunteers
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct SupplyBundleFilter {
+    pub status: Option<String>,
+    pub assigned_to: Option<String>,
+    pub incident_id: Option<String>,
 }
 
-#[ic_cdk_macros::update]
-pub fn create_supply_bundle(bundle: SupplyBundle) -> bool {
-    ic_cdk::println!("Creating supply bundle: {:?}", bundle);
-    SUPPLY_BUNDLES.with(|bundles| {
-        let mut bundles = bundles.borrow_mut();
-        let bundle_key = StableString::from(bundle.id.clone());
-        bundles.insert(bundle_key, bundle.clone());
-        ic_cdk::println!("Successfully created supply bundle");
-        true
-    })
+impl SupplyBundleFilter {
+    fn matches(&self, bundle: &SupplyBundle) -> bool {
+        self.status.as_ref().is_none_or(|status| bundle.status.eq_ignore_ascii_case(status))
+            && self
+                .assigned_to
+                .as_ref()
+                .is_none_or(|volunteer| bundle.assigned_to.as_ref() == Some(volunteer))
+            && self
+                .incident_id
+                .as_ref()
+                .is_none_or(|incident| bundle.incident_id.as_ref() == Some(incident))
+    }
 }
 
 #[ic_cdk_macros::query]
-pub fn get_organization_supply_bundles() -> Vec<SupplyBundle> {
-    ic_cdk::println!("Getting all supply bundles");
-    let bundles = SUPPLY_BUNDLES.with(|bundles| {
-        bundles.borrow().iter().map(|(_, bundle)| bundle.clone()).collect::<Vec<SupplyBundle>>()
-    });
-    ic_cdk::println!("Found {} supply bundles", bundles.len());
-    bundles
+pub fn get_organization_supply_bundles(
+    session_token: String,
+    organization_id: String,
+    filter: SupplyBundleFilter,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<String, SupplyBundle>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ManageSupplies)?;
+    organization::ensure_member(&caller, &organization_id)?;
+    let bundle_ids = index::bundles_of_organization(&organization_id);
+    let total = bundle_ids.len() as u64;
+    let bundle_ids = pagination::keys_after(bundle_ids, start_after.clone().map(StableString).as_ref());
+    Ok(SUPPLY_BUNDLES.with(|bundles| {
+        pagination::paginate(
+            bundles.borrow().entries_of(bundle_ids).map(|(id, bundle)| (id.0, bundle)),
+            total,
+            start_after,
+            limit,
+            |bundle| filter.matches(bundle),
+            |bundle| bundle,
+        )
+    }))
 }
 
-#[ic_cdk_macros::update]
-pub fn distribute_supply_bundle(bundle_id: String, volunteer_id: String) -> bool {
-    ic_cdk::println!("Attempting to distribute bundle {} to volunteer {}", bundle_id, volunteer_id);
-    SUPPLY_BUNDLES.with(|bundles| {
-        let mut bundles = bundles.borrow_mut();
-        let bundle_id_clone = bundle_id.clone();
-        let bundle_key = StableString::from(bundle_id);
-        
-        if let Some(bundle) = bundles.get(&bundle_key) {
-            ic_cdk::println!("Found bundle: {:?}", bundle);
-            let mut updated_bundle = bundle.clone();
-            updated_bundle.status = "distributed".to_string();
-            updated_bundle.assigned_to = Some(volunteer_id);
-            bundles.insert(bundle_key, updated_bundle.clone());
-            ic_cdk::println!("Successfully distributed bundle");
-            true
-        } else {
-            ic_cdk::println!("Bundle not found with ID: {}", bundle_id_clone);
-            false
-        }
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+pub fn distribute_supply_bundle(
+    session_token: String,
+    bundle_id: String,
+    volunteer_id: String,
+) -> Result<(), BackendError> {
+    audit::audited("distribute_supply_bundle", audit::digest((&bundle_id, &volunteer_id)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageSupplies)?;
+        ic_cdk::println!("Attempting to distribute bundle {} to volunteer {}", bundle_id, volunteer_id);
+        ensure_volunteer(&volunteer_id)?;
+        SUPPLY_BUNDLES.with(|bundles| {
+            let mut bundles = bundles.borrow_mut();
+            let bundle_id_clone = bundle_id.clone();
+            let bundle_key = StableString::from(bundle_id);
+            
+            if let Some(bundle) = bundles.get(&bundle_key) {
+                ic_cdk::println!("Found bundle: {:?}", bundle);
+                organization::ensure_member(&caller, &bundle.organization_id)?;
+                if bundle.status == "distributed" {
+                    return Err(BackendError::invalid_state(&bundle.status, "distributed"));
+                }
+                let mut updated_bundle = bundle.clone();
+                updated_bundle.status = "distributed".to_string();
+                updated_bundle.assigned_to = Some(volunteer_id);
+                bundles.insert(bundle_key, updated_bundle.clone());
+                ic_cdk::println!("Successfully distributed bundle");
+                Ok(())
+            } else {
+                ic_cdk::println!("Bundle not found with ID: {}", bundle_id_clone);
+                Err(BackendError::not_found("supply bundle", &bundle_id_clone))
+            }
+        })
     })
 }
 
+// Check that an email passed as a volunteer reference is a volunteer account
+fn ensure_volunteer(email: &str) -> Result<(), BackendError> {
+    match USERS.with(|users| users.borrow().get(&StableString::from(email.to_string()))) {
+        Some(user) if user.role == Role::Volunteer => Ok(()),
+        Some(_) => Err(BackendError::validation("volunteer_id", "account is not a volunteer")),
+        None => Err(BackendError::not_found("volunteer", email)),
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct DonationFilter {
+    pub donor_email: Option<String>,
+    pub incident_id: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl DonationFilter {
+    fn matches(&self, donation: &Donation) -> bool {
+        self.donor_email.as_ref().is_none_or(|donor| &donation.donor_email == donor)
+            && self
+                .incident_id
+                .as_ref()
+                .is_none_or(|incident| donation.incident_id.as_ref() == Some(incident))
+            && self.since.is_none_or(|since| donation.date >= since)
+            && self.until.is_none_or(|until| donation.date <= until)
+    }
+}
+
 #[ic_cdk_macros::query]
-pub fn get_organization_donations() -> Vec<Donation> {
-    ic_cdk::println!("Getting all donations");
-    let donations = DONATIONS.with(|donations| {
+pub fn get_organization_donations(
+    session_token: String,
+    organization_id: String,
+    filter: DonationFilter,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<String, Donation>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ViewDonations)?;
+    organization::ensure_member(&caller, &organization_id)?;
+    Ok(DONATIONS.with(|donations| {
         let donations = donations.borrow();
-        ic_cdk::println!("Found {} donations in storage", donations.len());
-        let result = donations.iter().map(|(_, donation)| {
-            ic_cdk::println!("Donation details: {:?}", donation);
-            donation.clone()
-        }).collect::<Vec<Donation>>();
-        ic_cdk::println!("Returning {} donations", result.len());
-        result
-    });
-    donations
+        let donation_ids = match &filter.donor_email {
+            Some(donor) => index::donations_of_donor(donor),
+            None => index::donations_of_organization(&organization_id),
+        };
+        let total = donation_ids.len() as u64;
+        let donation_ids = pagination::keys_after(donation_ids, start_after.clone().map(StableString).as_ref());
+        pagination::paginate(
+            donations.entries_of(donation_ids).map(|(id, donation)| (id.0, donation)),
+            total,
+            start_after,
+            limit,
+            |donation| donation.organization_id == organization_id && filter.matches(donation),
+            |donation| donation,
+        )
+    }))
 }
 
-#[ic_cdk_macros::update]
-pub fn assign_volunteer_to_request(request_id: String, volunteer_id: String) -> bool {
-    ic_cdk::println!("Attempting to assign volunteer {} to request {}", volunteer_id, request_id);
-    HELP_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        let request_id_clone = request_id.clone();
-        let request_key = StableString::from(request_id);
-        
-        if let Some(request) = requests.get(&request_key) {
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+pub fn assign_volunteer_to_request(
+    session_token: String,
+    request_id: u64,
+    volunteer_id: String,
+) -> Result<(), BackendError> {
+    audit::audited("assign_volunteer_to_request", audit::digest((&request_id, &volunteer_id)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageRequests)?;
+        ic_cdk::println!("Attempting to assign volunteer {} to request {}", volunteer_id, request_id);
+        ensure_volunteer(&volunteer_id)?;
+        HELP_REQUESTS.with(|requests| {
+            let mut requests = requests.borrow_mut();
+            let mut request = find_request(&requests, request_id)?;
             ic_cdk::println!("Found request: {:?}", request);
-            let mut updated_request = request.clone();
-            updated_request.assigned_volunteer = Some(volunteer_id.clone());
-            updated_request.status = "assigned".to_string();
-            requests.insert(request_key, updated_request.clone());
+            organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+            status::transition(
+                &mut request,
+                RequestStatus::Assigned,
+                &caller.email,
+                Some(format!("assigned to {}", volunteer_id)),
+            )?;
+            request.assigned_volunteer = Some(volunteer_id);
+            requests.insert(request_id, request);
             ic_cdk::println!("Successfully assigned volunteer to request");
-            true
-        } else {
-            ic_cdk::println!("Request not found with ID: {}", request_id_clone);
-            false
-        }
+            Ok(())
+        })
     })
 }
 
@@ -410,9 +1052,9 @@ fn post_upgrade() {
     ic_cdk::println!("Starting post_upgrade");
     // Re-initialize everything but preserve existing data
     MEMORY_MANAGER.with(|m| {
-        let mut memory_manager = m.borrow_mut();
+        let memory_manager = m.borrow_mut();
         // Re-allocate all memory regions
-        for i in 0..5 {
+        for i in 0..MEMORY_REGION_COUNT {
             memory_manager.get(MemoryId::new(i));
         }
     });
@@ -437,242 +1079,1136 @@ fn post_upgrade() {
     VOLUNTEER_LOCATIONS.with(|locations| {
         let _ = locations.borrow_mut();
     });
-}
+
+    PRINCIPALS.with(|principals| {
+        let _ = principals.borrow_mut();
+    });
+
+    SESSIONS.with(|sessions| {
+        let _ = sessions.borrow_mut();
+    });
+
+    NEXT_REQUEST_ID.with(|counter| {
+        let _ = counter.borrow_mut();
+    });
+
+    REQUEST_HISTORY.with(|history| {
+        let _ = history.borrow_mut();
+    });
+
+    AUDIT_LOG.with(|log| {
+        let _ = log.borrow_mut();
+    });
+
+    GEO_INDEX.with(|index| {
+        let _ = index.borrow_mut();
+    });
+
+    schema::run_migrations();
+
+    // Timers don't survive upgrades, so they're registered again
+    start_timers();
+}
+
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(auth::SESSION_SWEEP_INTERVAL, auth::sweep_expired_sessions);
+    ic_cdk_timers::set_timer_interval(matching::MATCHING_INTERVAL, matching::run_auto_assignment);
+    ic_cdk_timers::set_timer_interval(tracking::STALENESS_SWEEP_INTERVAL, tracking::mark_stale_locations);
+}
+
+// Replace passwords stored before hashing was introduced with salted hashes.
+// Re-inserting the record also rewrites it in the current layout.
+fn migrate_plaintext_passwords() {
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        let plaintext_users: Vec<User> = users
+            .iter()
+            .map(|(_, user)| user)
+            .filter(|user| user.credential.is_plaintext())
+            .collect();
+
+        for mut user in plaintext_users.iter().cloned() {
+            if let Credential::Plaintext(plain) = &user.credential {
+                user.credential = password::provisional_hash(plain, &user.email, ic_cdk::api::time());
+            }
+            users.insert(StableString::from(user.email.clone()), user);
+        }
+        ic_cdk::println!("Hashed {} plaintext passwords", plaintext_users.len());
+    });
+}
+
+// The plaintext migration used to store its hashes as final ones
+fn mark_password_hashes_provisional() {
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        let hashed: Vec<User> = users
+            .iter()
+            .map(|(_, user)| user)
+            .filter(|user| matches!(user.credential, Credential::Hashed(_)))
+            .collect();
+        for mut user in hashed {
+            user.credential = password::mark_provisional(user.credential);
+            users.insert(StableString::from(user.email.clone()), user);
+        }
+    });
+}
+
+// Replace a provisional hash with one salted from the management canister's
+// randomness, now that the verified password is at hand
+async fn rehash_if_provisional(email: &str, password: &str) -> Result<(), BackendError> {
+    let email_key = StableString::from(email.to_string());
+    let is_provisional = USERS.with(|users| {
+        users
+            .borrow()
+            .get(&email_key)
+            .is_some_and(|user| matches!(user.credential, Credential::Provisional(_)))
+    });
+    if !is_provisional {
+        return Ok(());
+    }
+    let salt = password::random_salt().await?;
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        // The account may have changed while the salt was being fetched
+        let Some(mut user) = users.get(&email_key) else {
+            return;
+        };
+        if let Some(credential) = user.credential.rehashed(password, salt) {
+            user.credential = credential;
+            users.insert(email_key, user);
+            ic_cdk::println!("Rehashed the password of {} with a random salt", email);
+        }
+    });
+    Ok(())
+}
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct HelpRequest {
+    pub request_id: u64,
     pub victim_id: String,
     pub request_type: String,
     pub description: String,
     pub urgency: String,
     pub location: String,
-    pub status: String,
-    pub timestamp: String,
-    pub latitude: String,
-    pub longitude: String,
+    pub status: RequestStatus,
+    // Nanoseconds since the epoch at which the request was filed
+    pub timestamp: u64,
+    // Only empty for requests whose stored text coordinates didn't parse
+    pub coordinates: Option<GeoPoint>,
     pub verification_note: Option<String>,
     pub verified_by: Option<String>,
     pub organization_id: Option<String>,
-    pub assigned_volunteer: Option<String>
+    pub assigned_volunteer: Option<String>,
+    pub incident_id: Option<String>,
+}
+
+impl Versioned for HelpRequest {
+    const VERSION: u8 = 3;
+
+    // Version 1 and bare records are either HelpRequestV1 or LegacyHelpRequest
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        if version == 2 {
+            let old: HelpRequestV2 = schema::decode_or_trap(version, bytes);
+            return old.into();
+        }
+        let request: HelpRequestV1 = candid::decode_one(bytes).unwrap_or_else(|_| {
+            let legacy: LegacyHelpRequest = schema::decode_or_trap(version, bytes);
+            let request_id = legacy.request_id.unwrap_or_default();
+            legacy.with_id(request_id)
+        });
+        request.into()
+    }
 }
 
 impl Storable for HelpRequest {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+// What a victim submits; the id, timestamp and status are set by the canister
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct NewHelpRequest {
+    pub request_type: String,
+    pub description: String,
+    pub urgency: String,
+    pub location: String,
+    pub coordinates: GeoPoint,
+    pub organization_id: Option<String>,
+    // Defaults to the active incident whose affected area covers the request
+    pub incident_id: Option<String>,
+}
+
+// Layout of help requests stored while coordinates and the timestamp were text
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct HelpRequestV1 {
+    request_id: u64,
+    victim_id: String,
+    request_type: String,
+    description: String,
+    urgency: String,
+    location: String,
+    status: RequestStatus,
+    timestamp: String,
+    latitude: String,
+    longitude: String,
+    verification_note: Option<String>,
+    verified_by: Option<String>,
+    organization_id: Option<String>,
+    assigned_volunteer: Option<String>,
+}
+
+impl From<HelpRequestV1> for HelpRequest {
+    fn from(old: HelpRequestV1) -> Self {
+        let record = format!("Help request {}", old.request_id);
+        HelpRequest {
+            request_id: old.request_id,
+            victim_id: old.victim_id,
+            request_type: old.request_type,
+            description: old.description,
+            urgency: old.urgency,
+            location: old.location,
+            status: old.status,
+            timestamp: legacy::timestamp(&record, "timestamp", &old.timestamp),
+            coordinates: legacy::point(&record, &old.latitude, &old.longitude),
+            verification_note: old.verification_note,
+            verified_by: old.verified_by,
+            organization_id: old.organization_id,
+            assigned_volunteer: old.assigned_volunteer,
+            incident_id: None,
+        }
+    }
+}
+
+// Layout of help requests stored before they could belong to an incident
+#[derive(CandidType, Deserialize)]
+struct HelpRequestV2 {
+    request_id: u64,
+    victim_id: String,
+    request_type: String,
+    description: String,
+    urgency: String,
+    location: String,
+    status: RequestStatus,
+    timestamp: u64,
+    coordinates: Option<GeoPoint>,
+    verification_note: Option<String>,
+    verified_by: Option<String>,
+    organization_id: Option<String>,
+    assigned_volunteer: Option<String>,
+}
+
+impl From<HelpRequestV2> for HelpRequest {
+    fn from(old: HelpRequestV2) -> Self {
+        HelpRequest {
+            request_id: old.request_id,
+            victim_id: old.victim_id,
+            request_type: old.request_type,
+            description: old.description,
+            urgency: old.urgency,
+            location: old.location,
+            status: old.status,
+            timestamp: old.timestamp,
+            coordinates: old.coordinates,
+            verification_note: old.verification_note,
+            verified_by: old.verified_by,
+            organization_id: old.organization_id,
+            assigned_volunteer: old.assigned_volunteer,
+            incident_id: None,
+        }
+    }
+}
+
+// Layout of help requests stored before statuses were typed; the oldest of
+// these also predate server-assigned ids
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct LegacyHelpRequest {
+    request_id: Option<u64>,
+    victim_id: String,
+    request_type: String,
+    description: String,
+    urgency: String,
+    location: String,
+    status: String,
+    timestamp: String,
+    latitude: String,
+    longitude: String,
+    verification_note: Option<String>,
+    verified_by: Option<String>,
+    organization_id: Option<String>,
+    assigned_volunteer: Option<String>,
+}
+
+impl Storable for LegacyHelpRequest {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::decode_or_trap(schema::BARE_CANDID, &bytes)
     }
 }
 
-impl BoundedStorable for HelpRequest {
+impl BoundedStorable for LegacyHelpRequest {
     const MAX_SIZE: u32 = 1024;
     const IS_FIXED_SIZE: bool = false;
 }
 
-#[ic_cdk_macros::update]
-fn create_help_request(request: HelpRequest) -> bool {
-    ic_cdk::println!("Creating help request: {:?}", request);
-    HELP_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        let request_id = format!("{}_{}", request.victim_id, request.timestamp);
-        let request_key = StableString::from(request_id.clone());
-        
-        // Set organization_id to the default organization if not set
-        let mut request_with_org = request.clone();
-        if request_with_org.organization_id.is_none() {
-            request_with_org.organization_id = Some("organization@disasterrelief.com".to_string());
+impl LegacyHelpRequest {
+    fn with_id(self, request_id: u64) -> HelpRequestV1 {
+        HelpRequestV1 {
+            request_id,
+            victim_id: self.victim_id,
+            request_type: self.request_type,
+            description: self.description,
+            urgency: self.urgency,
+            location: self.location,
+            status: RequestStatus::from_legacy(&self.status),
+            timestamp: self.timestamp,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            verification_note: self.verification_note,
+            verified_by: self.verified_by,
+            organization_id: self.organization_id,
+            assigned_volunteer: self.assigned_volunteer,
         }
-        
-        requests.insert(request_key, request_with_org.clone());
-        ic_cdk::println!("Help request created successfully with ID: {}", request_id);
-        true
+    }
+}
+
+fn next_request_id() -> Result<u64, BackendError> {
+    NEXT_REQUEST_ID.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let request_id = *counter.get();
+        counter.set(request_id + 1).map_err(|e| {
+            BackendError::Internal(format!("failed to advance the request id counter: {:?}", e))
+        })?;
+        Ok(request_id)
     })
 }
 
-#[ic_cdk_macros::query]
-fn get_user_requests(victim_id: String) -> Vec<HelpRequest> {
+// Move requests stored under "victim_timestamp" keys into the id-keyed map,
+// numbering them in key order
+fn migrate_legacy_help_requests() {
+    let legacy: Vec<(StableString, LegacyHelpRequest)> =
+        LEGACY_HELP_REQUESTS.with(|requests| requests.borrow().iter().collect());
+    if legacy.is_empty() {
+        return;
+    }
+
+    for (key, request) in legacy {
+        let request_id = match next_request_id() {
+            Ok(request_id) => request_id,
+            Err(e) => {
+                ic_cdk::println!("Stopped migrating help requests: {:?}", e);
+                return;
+            }
+        };
+        let request = HelpRequest::from(request.with_id(request_id));
+        HELP_REQUESTS.with(|requests| {
+            requests.borrow_mut().insert(request_id, request);
+        });
+        LEGACY_HELP_REQUESTS.with(|requests| {
+            requests.borrow_mut().remove(&key);
+        });
+        ic_cdk::println!("Migrated help request {} to id {}", key.as_ref(), request_id);
+    }
+}
+
+// Fetch a request by id for an update, or report it missing
+fn find_request(
+    requests: &ChunkedMap<u64, HelpRequest>,
+    request_id: u64,
+) -> Result<HelpRequest, BackendError> {
+    requests.get(&request_id).ok_or_else(|| {
+        ic_cdk::println!("Request not found with ID: {}", request_id);
+        BackendError::not_found("help request", &request_id.to_string())
+    })
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn create_help_request(session_token: String, request: NewHelpRequest) -> Result<u64, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::CreateHelpRequest)?;
+    request.validate()?;
+    if let Some(organization_id) = &request.organization_id {
+        organization::find(organization_id)?;
+    }
+    if let Some(incident_id) = &request.incident_id {
+        incident::find(incident_id)?;
+    }
+    ensure_storage_available()?;
+    ic_cdk::println!("Creating help request: {:?}", request);
+    let request_id = next_request_id()?;
+    // Requests are always filed on behalf of the calling account. Without an
+    // organization they go to the one whose service area covers them, or
+    // wait in the fallback queue for an admin.
+    let organization_id = request
+        .organization_id
+        .or_else(|| organization::route(&request.coordinates));
+    if organization_id.is_none() {
+        ic_cdk::println!("No service area covers request {}, queueing it for an admin", request_id);
+    }
+    let incident_id = request
+        .incident_id
+        .or_else(|| incident::covering(&request.coordinates));
+    let help_request = HelpRequest {
+        request_id,
+        victim_id: caller.email.clone(),
+        request_type: request.request_type.to_ascii_lowercase(),
+        description: request.description,
+        urgency: request.urgency.to_ascii_lowercase(),
+        location: request.location,
+        status: RequestStatus::Pending,
+        timestamp: ic_cdk::api::time(),
+        coordinates: Some(request.coordinates),
+        verification_note: None,
+        verified_by: None,
+        organization_id,
+        assigned_volunteer: None,
+        incident_id,
+    };
+    history::record(
+        request_id,
+        help_request.organization_id.clone(),
+        None,
+        RequestStatus::Pending,
+        &caller.email,
+        None,
+    );
     HELP_REQUESTS.with(|requests| {
-        let requests = requests.borrow();
+        requests.borrow_mut().insert(request_id, help_request);
+    });
+    ic_cdk::println!("Help request created successfully with ID: {}", request_id);
+    Ok(request_id)
+}
+
+#[ic_cdk_macros::query]
+fn get_user_requests(session_token: String, victim_id: String) -> Result<Vec<HelpRequest>, BackendError> {
+    let caller = auth::authorize_owner_or(&session_token, &victim_id, Permission::ViewAllRequests)?;
+    // Coordinators only see the requests of their own organizations
+    let scope = if caller.email == victim_id {
+        Scope::Everything
+    } else {
+        organization::scope_of(&caller)
+    };
+    let request_ids = index::requests_of_victim(&victim_id);
+    Ok(HELP_REQUESTS.with(|requests| {
         requests
-            .iter()
-            .filter(|(_, request)| request.victim_id == victim_id)
-            .map(|(_, request)| request.clone())
+            .borrow()
+            .entries_of(request_ids)
+            .filter(|(_, request)| scope.includes(request.organization_id.as_ref()))
+            .map(|(_, request)| privacy::request_for(&caller, request))
             .collect()
-    })
+    }))
 }
 
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct RequestFilter {
+    pub status: Option<RequestStatus>,
+    pub urgency: Option<String>,
+    pub request_type: Option<String>,
+    pub organization_id: Option<String>,
+    pub assigned_volunteer: Option<String>,
+    pub incident_id: Option<String>,
+    // Bounds on the filing time, inclusive
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl RequestFilter {
+    fn matches(&self, request: &HelpRequest) -> bool {
+        self.status.is_none_or(|status| request.status == status)
+            && self
+                .urgency
+                .as_ref()
+                .is_none_or(|urgency| request.urgency.eq_ignore_ascii_case(urgency))
+            && self
+                .request_type
+                .as_ref()
+                .is_none_or(|request_type| request.request_type.eq_ignore_ascii_case(request_type))
+            && self
+                .organization_id
+                .as_ref()
+                .is_none_or(|organization| request.organization_id.as_ref() == Some(organization))
+            && self
+                .assigned_volunteer
+                .as_ref()
+                .is_none_or(|volunteer| request.assigned_volunteer.as_ref() == Some(volunteer))
+            && self
+                .incident_id
+                .as_ref()
+                .is_none_or(|incident| request.incident_id.as_ref() == Some(incident))
+            && self.since.is_none_or(|since| request.timestamp >= since)
+            && self.until.is_none_or(|until| request.timestamp <= until)
+    }
+}
+
+// Requests in id order, which is also the order they were filed in
 #[ic_cdk_macros::query]
-pub fn get_all_requests() -> Vec<HelpRequest> {
-    ic_cdk::println!("Getting all help requests");
-    HELP_REQUESTS.with(|requests| {
+pub fn get_all_requests(
+    session_token: String,
+    filter: RequestFilter,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Page<u64, HelpRequest>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ViewAllRequests)?;
+    let scope = organization::scope_of(&caller);
+    if let Some(organization_id) = &filter.organization_id {
+        organization::ensure_member(&caller, organization_id)?;
+    }
+    Ok(HELP_REQUESTS.with(|requests| {
         let requests = requests.borrow();
-        let all_requests: Vec<HelpRequest> = requests.iter().map(|(_, request)| request.clone()).collect();
-        ic_cdk::println!("Found {} help requests", all_requests.len());
-        all_requests
+        // Narrow the scan through an index when the filter or scope allows it
+        let request_ids: Option<Vec<u64>> = if let Some(volunteer) = &filter.assigned_volunteer {
+            Some(index::requests_of_volunteer(volunteer))
+        } else if let Some(incident) = &filter.incident_id {
+            Some(index::requests_of_incident(incident))
+        } else if let Some(organization) = &filter.organization_id {
+            Some(index::requests_of_organization(organization))
+        } else if let Scope::Organizations(organization_ids) = &scope {
+            let mut request_ids: Vec<u64> = organization_ids
+                .iter()
+                .flat_map(|id| index::requests_of_organization(id))
+                .collect();
+            request_ids.sort();
+            Some(request_ids)
+        } else {
+            None
+        };
+        let total = request_ids.as_ref().map_or_else(index::request_count, |ids| ids.len() as u64);
+        let records: Box<dyn Iterator<Item = (u64, HelpRequest)>> = match request_ids {
+            Some(request_ids) => {
+                Box::new(requests.entries_of(pagination::keys_after(request_ids, start_after.as_ref())))
+            }
+            None => Box::new(requests.iter_after(start_after.as_ref())),
+        };
+        pagination::paginate(
+            records,
+            total,
+            start_after,
+            limit,
+            |request| scope.includes(request.organization_id.as_ref()) && filter.matches(request),
+            |request| privacy::request_for(&caller, request),
+        )
+    }))
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn update_request_status(
+    session_token: String,
+    request_id: u64,
+    new_status: RequestStatus,
+    note: Option<String>,
+) -> Result<(), BackendError> {
+    audit::audited("update_request_status", audit::digest((&request_id, &new_status, &note)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageRequests)?;
+        if let Some(note) = &note {
+            validation::note("note", note, false)?;
+        }
+        HELP_REQUESTS.with(|requests| {
+            let mut requests = requests.borrow_mut();
+            let mut request = find_request(&requests, request_id)?;
+            organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+            status::transition(&mut request, new_status, &caller.email, note)?;
+            requests.insert(request_id, request);
+            Ok(())
+        })
     })
 }
 
-#[ic_cdk_macros::update]
-fn update_request_status(victim_id: String, timestamp: String, new_status: String) -> bool {
+// The fallback queue: open requests outside every organization's service area
+#[ic_cdk_macros::query]
+fn get_unrouted_requests(
+    session_token: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Page<u64, HelpRequest>, BackendError> {
+    auth::authorize(&session_token, Permission::RouteRequests)?;
+    let request_ids = index::unrouted_requests();
+    let total = request_ids.len() as u64;
+    let request_ids = pagination::keys_after(request_ids, start_after.as_ref());
+    Ok(HELP_REQUESTS.with(|requests| {
+        pagination::paginate(
+            requests.borrow().entries_of(request_ids),
+            total,
+            start_after,
+            limit,
+            |_| true,
+            |request| request,
+        )
+    }))
+}
+
+// Move a request to another organization, or hand one out of the fallback queue.
+// Finished requests stay where they are, and an assigned request goes back to
+// the queue it was assigned from, since its volunteer worked for the old one.
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn reassign_request_organization(
+    session_token: String,
+    request_id: u64,
+    organization_id: String,
+) -> Result<(), BackendError> {
+    audit::audited(
+        "reassign_request_organization",
+        audit::digest((&request_id, &organization_id)),
+        || {
+            let caller = auth::authorize(&session_token, Permission::RouteRequests)?;
+            organization::find(&organization_id)?;
+            HELP_REQUESTS.with(|requests| {
+                let mut requests = requests.borrow_mut();
+                let mut request = find_request(&requests, request_id)?;
+                if request.status.allowed_next().is_empty() {
+                    return Err(BackendError::invalid_state(request.status.as_str(), "reassigned"));
+                }
+                ic_cdk::println!(
+                    "Reassigning request {} from {:?} to {}",
+                    request_id,
+                    request.organization_id,
+                    organization_id
+                );
+                if request.assigned_volunteer.is_some() {
+                    let queued_as = history::status_before_assignment(request_id);
+                    status::transition(
+                        &mut request,
+                        queued_as,
+                        &caller.email,
+                        Some(format!("unassigned for reassignment to {}", organization_id)),
+                    )?;
+                    request.assigned_volunteer = None;
+                }
+                history::record(
+                    request_id,
+                    Some(organization_id.clone()),
+                    Some(request.status),
+                    request.status,
+                    &caller.email,
+                    Some(format!("reassigned to {}", organization_id)),
+                );
+                request.organization_id = Some(organization_id);
+                requests.insert(request_id, request);
+                Ok(())
+            })
+        },
+    )
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn cancel_help_request(session_token: String, request_id: u64) -> Result<(), BackendError> {
+    ic_cdk::println!("Attempting to cancel request {}", request_id);
     HELP_REQUESTS.with(|requests| {
         let mut requests = requests.borrow_mut();
-        let request_id = StableString::from(format!("{}_{}", victim_id, timestamp));
-        
-        if let Some(request) = requests.get(&request_id) {
-            let mut updated_request = request.clone();
-            updated_request.status = new_status;
-            requests.insert(request_id, updated_request);
-            true
-        } else {
-            false
+        let mut request = find_request(&requests, request_id)?;
+        let caller = auth::authorize_owner_or(&session_token, &request.victim_id, Permission::ManageRequests)?;
+        if caller.email != request.victim_id {
+            organization::ensure_manages(&caller, request.organization_id.as_ref())?;
         }
+        status::transition(&mut request, RequestStatus::Cancelled, &caller.email, None)?;
+        requests.insert(request_id, request);
+        ic_cdk::println!("Request cancelled successfully");
+        Ok(())
     })
 }
 
-#[ic_cdk_macros::update]
-fn cancel_help_request(victim_id: String, timestamp: String) -> bool {
-    ic_cdk::println!("Attempting to cancel request for victim: {} with timestamp: {}", victim_id, timestamp);
+// The status changes of a request, oldest first. History outlives the request
+// itself, so once it is gone only coordinators can read it.
+#[ic_cdk_macros::query]
+fn get_request_history(session_token: String, request_id: u64) -> Result<Vec<RequestEvent>, BackendError> {
+    let request = HELP_REQUESTS.with(|requests| requests.borrow().get(&request_id));
+    match request {
+        Some(request) => {
+            let caller = auth::authorize_owner_or(&session_token, &request.victim_id, Permission::ViewAllRequests)?;
+            if caller.email != request.victim_id {
+                organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+            }
+        }
+        None => {
+            let caller = auth::authorize(&session_token, Permission::ViewAllRequests)?;
+            organization::ensure_manages(&caller, history::last_organization(request_id).as_ref())?;
+        }
+    };
+    Ok(history::events_of(request_id))
+}
+
+// Requests assigned to the calling volunteer, oldest first. Finished ones
+// (completed or cancelled) are only included when asked for.
+#[ic_cdk_macros::query]
+fn get_my_assignments(session_token: String, include_finished: bool) -> Result<Vec<HelpRequest>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::WorkOnAssignments)?;
+    let request_ids = index::requests_of_volunteer(&caller.email);
+    Ok(HELP_REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .entries_of(request_ids)
+            .map(|(_, request)| request)
+            .filter(|request| include_finished || !request.status.allowed_next().is_empty())
+            .collect()
+    }))
+}
+
+// Run a volunteer's step on a request assigned to them. Requests assigned to
+// someone else are reported missing rather than revealed.
+fn update_own_assignment(
+    session_token: &str,
+    request_id: u64,
+    step: impl FnOnce(&mut HelpRequest, &str) -> Result<(), BackendError>,
+) -> Result<(), BackendError> {
+    let caller = auth::authorize(session_token, Permission::WorkOnAssignments)?;
     HELP_REQUESTS.with(|requests| {
         let mut requests = requests.borrow_mut();
-        let request_id = StableString::from(format!("{}_{}", victim_id, timestamp));
-        
-        if let Some(request) = requests.get(&request_id) {
-            if request.status.to_lowercase() == "pending" {
-                let mut updated_request = request.clone();
-                updated_request.status = "cancelled".to_string();
-                requests.insert(request_id, updated_request);
-                ic_cdk::println!("Request cancelled successfully");
-                true
-            } else {
-                ic_cdk::println!("Request cannot be cancelled: not in pending status");
-                false
-            }
-        } else {
-            ic_cdk::println!("Request not found");
-            false
+        let mut request = find_request(&requests, request_id)?;
+        if request.assigned_volunteer.as_deref() != Some(caller.email.as_str()) {
+            ic_cdk::println!("Request {} is not assigned to {}", request_id, caller.email);
+            return Err(BackendError::not_found("assignment", &request_id.to_string()));
+        }
+        step(&mut request, &caller.email)?;
+        requests.insert(request_id, request);
+        Ok(())
+    })
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn accept_assignment(session_token: String, request_id: u64) -> Result<(), BackendError> {
+    update_own_assignment(&session_token, request_id, |request, volunteer| {
+        status::transition(request, RequestStatus::Accepted, volunteer, None)
+    })
+}
+
+// Hand the request back to the queue it was assigned from, pending or
+// verified, so it can be assigned to someone else
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn decline_assignment(session_token: String, request_id: u64, reason: Option<String>) -> Result<(), BackendError> {
+    if let Some(reason) = &reason {
+        validation::note("reason", reason, false)?;
+    }
+    update_own_assignment(&session_token, request_id, |request, volunteer| {
+        let queued_as = history::status_before_assignment(request_id);
+        status::transition(request, queued_as, volunteer, reason)?;
+        request.assigned_volunteer = None;
+        Ok(())
+    })
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn start_task(session_token: String, request_id: u64) -> Result<(), BackendError> {
+    update_own_assignment(&session_token, request_id, |request, volunteer| {
+        status::transition(request, RequestStatus::InProgress, volunteer, None)
+    })
+}
+
+// Only a started task can be completed by its volunteer; coordinators can
+// still close a request directly through approve_volunteer_request
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn complete_task(session_token: String, request_id: u64, completion_note: String) -> Result<(), BackendError> {
+    validation::note("completion_note", &completion_note, true)?;
+    update_own_assignment(&session_token, request_id, |request, volunteer| {
+        if request.status != RequestStatus::InProgress {
+            return Err(BackendError::invalid_state(
+                request.status.as_str(),
+                RequestStatus::Completed.as_str(),
+            ));
         }
+        status::transition(request, RequestStatus::Completed, volunteer, Some(completion_note))
+    })
+}
+
+#[ic_cdk_macros::query]
+fn get_volunteer_profile(session_token: String, email: String) -> Result<Option<VolunteerProfile>, BackendError> {
+    auth::authorize_owner_or(&session_token, &email, Permission::ViewVolunteers)?;
+    Ok(profile::get(&email))
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn update_volunteer_profile(session_token: String, update: VolunteerProfileUpdate) -> Result<(), BackendError> {
+    let caller = auth::authorize(&session_token, Permission::EditVolunteerProfile)?;
+    update.validate()?;
+    ensure_storage_available()?;
+    profile::save(&caller.email, update);
+    Ok(())
+}
+
+// Volunteers who are off duty are not matched to requests
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn set_on_duty(session_token: String, on_duty: bool) -> Result<(), BackendError> {
+    let caller = auth::authorize(&session_token, Permission::EditVolunteerProfile)?;
+    ensure_storage_available()?;
+    profile::set_on_duty(&caller.email, on_duty);
+    ic_cdk::println!("{} is now {}", caller.email, if on_duty { "on duty" } else { "off duty" });
+    Ok(())
+}
+
+// Volunteers the matching engine would pick for a request, best first, with
+// the factors that make up each score
+#[ic_cdk_macros::query]
+fn get_match_candidates(session_token: String, request_id: u64) -> Result<Vec<MatchCandidate>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ManageRequests)?;
+    let request = HELP_REQUESTS.with(|requests| find_request(&requests.borrow(), request_id))?;
+    organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+    Ok(matching::candidates(&request))
+}
+
+// Assign a request to the best candidate right away
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn auto_assign_request(session_token: String, request_id: u64) -> Result<MatchCandidate, BackendError> {
+    audit::audited("auto_assign_request", audit::digest((&request_id,)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageRequests)?;
+        let request = HELP_REQUESTS.with(|requests| find_request(&requests.borrow(), request_id))?;
+        organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+        matching::assign_best(request_id, &caller.email)
+    })
+}
+
+#[ic_cdk_macros::query]
+fn get_matching_config(session_token: String) -> Result<MatchingConfig, BackendError> {
+    auth::authorize(&session_token, Permission::ConfigureMatching)?;
+    Ok(matching::config())
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn set_matching_config(session_token: String, config: MatchingConfig) -> Result<(), BackendError> {
+    audit::audited("set_matching_config", audit::digest((&config,)), || {
+        auth::authorize(&session_token, Permission::ConfigureMatching)?;
+        config.validate()?;
+        matching::set_config(config)
     })
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone)]
 pub struct VolunteerLocation {
     pub email: String,
-    pub latitude: String,
-    pub longitude: String,
+    // Only empty for locations whose stored text coordinates didn't parse
+    pub coordinates: Option<GeoPoint>,
     pub address: String,
-    pub last_updated: String,
+    // Nanoseconds since the epoch
+    pub last_updated: u64,
+    // Set to stale by a timer once the position is older than the tracking window
+    pub presence: Presence,
+}
+
+impl Versioned for VolunteerLocation {
+    const VERSION: u8 = 3;
+
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        let old: VolunteerLocationV2 = if version == 2 {
+            schema::decode_or_trap(version, bytes)
+        } else {
+            let old: VolunteerLocationV1 = schema::decode_or_trap(version, bytes);
+            let record = format!("Location of {}", old.email);
+            VolunteerLocationV2 {
+                coordinates: legacy::point(&record, &old.latitude, &old.longitude),
+                last_updated: legacy::timestamp(&record, "last_updated", &old.last_updated),
+                email: old.email,
+                address: old.address,
+            }
+        };
+        // The staleness timer corrects this on its next run
+        VolunteerLocation {
+            email: old.email,
+            coordinates: old.coordinates,
+            address: old.address,
+            last_updated: old.last_updated,
+            presence: Presence::Active,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct VolunteerLocationV2 {
+    email: String,
+    coordinates: Option<GeoPoint>,
+    address: String,
+    last_updated: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VolunteerLocationV1 {
+    email: String,
+    latitude: String,
+    longitude: String,
+    address: String,
+    last_updated: String,
 }
 
 impl Storable for VolunteerLocation {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::from_envelope(&bytes)
     }
 }
 
-impl BoundedStorable for VolunteerLocation {
-    const MAX_SIZE: u32 = 512;
-    const IS_FIXED_SIZE: bool = false;
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn update_volunteer_location(
+    session_token: String,
+    coordinates: GeoPoint,
+    address: String,
+) -> Result<(), BackendError> {
+    let caller = auth::authorize(&session_token, Permission::UpdateLocation)?;
+    validation::volunteer_location(&coordinates, &address)?;
+    ensure_storage_available()?;
+    tracking::record(&caller.email, coordinates, address);
+    Ok(())
 }
 
-#[ic_cdk_macros::update]
-fn update_volunteer_location(email: String, latitude: String, longitude: String, address: String) -> bool {
-    VOLUNTEER_LOCATIONS.with(|locations| {
-        let mut locations = locations.borrow_mut();
-        let location = VolunteerLocation {
-            email: email.clone(),
-            latitude,
-            longitude,
-            address,
-            last_updated: ic_cdk::api::time().to_string(),
-        };
-        locations.insert(StableString::from(email), location);
-        true
+// Current volunteer positions for the coordination map. `max_age_minutes`
+// leaves out volunteers who haven't reported within that time.
+#[ic_cdk_macros::query]
+fn get_volunteer_locations(
+    session_token: String,
+    bbox: BoundingBox,
+    max_age_minutes: Option<u64>,
+) -> Result<Vec<VolunteerLocation>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ViewVolunteers)?;
+    validation::bounding_box("bbox", &bbox)?;
+    let bbox = privacy::search_box(&caller, bbox);
+    // Widened so that volunteers whose reduced point falls inside the box
+    // are found too
+    let margin = privacy::REDUCED_OFFSET_DEGREES;
+    Ok(tracking::locations_within(&bbox.grown(margin, margin), max_age_minutes)
+        .into_iter()
+        .filter_map(|location| privacy::location_for(&caller, location))
+        // Filtered on the point the caller gets, so the box edges don't give
+        // a withheld position away
+        .filter(|location| location.coordinates.is_some_and(|point| bbox.contains(&point)))
+        .collect())
+}
+
+// Where a volunteer has been, oldest first
+#[ic_cdk_macros::query]
+fn get_location_trail(
+    session_token: String,
+    email: String,
+    since: Option<u64>,
+) -> Result<Vec<Breadcrumb>, BackendError> {
+    let caller = auth::authorize_owner_or(&session_token, &email, Permission::ViewVolunteers)?;
+    Ok(privacy::trail_for(&caller, &email, tracking::trail(&email, since)))
+}
+
+#[ic_cdk_macros::query]
+fn get_tracking_config(session_token: String) -> Result<TrackingConfig, BackendError> {
+    auth::authorize(&session_token, Permission::ConfigureTracking)?;
+    Ok(tracking::config())
+}
+
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+fn set_tracking_config(session_token: String, config: TrackingConfig) -> Result<(), BackendError> {
+    audit::audited("set_tracking_config", audit::digest((&config,)), || {
+        auth::authorize(&session_token, Permission::ConfigureTracking)?;
+        config.validate()?;
+        tracking::set_config(config)
     })
 }
 
+const MAX_NEARBY_RADIUS_KM: f64 = 500.0;
+const MAX_NEARBY_RESULTS: u32 = 100;
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct NearbyFilter {
+    pub request_type: Option<String>,
+    pub urgency: Option<String>,
+    // Only request types that call for one of the caller's profile skills,
+    // plus types that call for none in particular
+    pub matching_my_skills: Option<bool>,
+}
+
+impl NearbyFilter {
+    fn matches(&self, request: &HelpRequest, my_skills: &[String]) -> bool {
+        let wanted = matching::relevant_skills(&request.request_type);
+        let suits_me = wanted.is_empty() || my_skills.iter().any(|skill| wanted.contains(&skill.as_str()));
+        self.matching_my_skills.is_none_or(|only_mine| !only_mine || suits_me)
+            && self.request_type
+            .as_ref()
+            .is_none_or(|request_type| request.request_type.eq_ignore_ascii_case(request_type))
+            && self
+                .urgency
+                .as_ref()
+                .is_none_or(|urgency| request.urgency.eq_ignore_ascii_case(urgency))
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct NearbyRequest {
+    pub request: HelpRequest,
+    pub distance_km: f64,
+}
+
 #[ic_cdk_macros::query]
-fn get_nearby_requests(latitude: String, longitude: String) -> Vec<HelpRequest> {
-    // Convert input coordinates to f64
-    let vol_lat: f64 = latitude.parse().unwrap_or(0.0);
-    let vol_lon: f64 = longitude.parse().unwrap_or(0.0);
-    
-    HELP_REQUESTS.with(|requests| {
+fn get_nearby_requests(
+    session_token: String,
+    origin: GeoPoint,
+    radius_km: f64,
+    limit: u32,
+    filter: NearbyFilter,
+) -> Result<Vec<NearbyRequest>, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ViewNearbyRequests)?;
+    validation::point("origin", &origin)?;
+    if !(radius_km > 0.0 && radius_km <= MAX_NEARBY_RADIUS_KM) {
+        return Err(BackendError::validation(
+            "radius_km",
+            &format!("must be greater than 0 and at most {}", MAX_NEARBY_RADIUS_KM),
+        ));
+    }
+    let limit = limit.clamp(1, MAX_NEARBY_RESULTS) as usize;
+    let radius_km = privacy::search_radius(&caller, radius_km);
+
+    let my_skills = profile::get_or_default(&caller.email).skills;
+    // Widened so that requests whose reduced point falls inside the radius
+    // are found too
+    let candidates = geo::within_radius(origin, radius_km + privacy::REDUCED_OFFSET_KM);
+    let mut nearby: Vec<NearbyRequest> = HELP_REQUESTS.with(|requests| {
         let requests = requests.borrow();
-        let mut nearby_requests: Vec<HelpRequest> = requests
-            .iter()
-            .filter(|(_, request)| {
-                // Only include pending requests
-                request.status.to_lowercase() == "pending"
+        candidates
+            .into_iter()
+            .filter_map(|(request_id, _)| requests.get(&request_id))
+            // Only include pending requests
+            .filter(|request| request.status == RequestStatus::Pending && filter.matches(request, &my_skills))
+            .filter_map(|request| {
+                // Filtered and sorted on the point the caller gets, so
+                // neither gives a withheld position away
+                let request = privacy::request_for(&caller, request);
+                let point = request.coordinates?;
+                let distance_km = geo::calculate_distance(origin.lat, origin.lon, point.lat, point.lon);
+                (distance_km <= radius_km).then_some(NearbyRequest { request, distance_km })
             })
-            .map(|(_, request)| request.clone())
-            .collect();
+            .collect()
+    });
+    nearby.sort_by(|a, b| a.distance_km.partial_cmp(&b.distance_km).unwrap_or(std::cmp::Ordering::Equal));
+    nearby.truncate(limit);
+    Ok(nearby)
+}
 
-        // Sort by distance
-        nearby_requests.sort_by(|a, b| {
-            let a_lat: f64 = a.latitude.parse().unwrap_or(0.0);
-            let a_lon: f64 = a.longitude.parse().unwrap_or(0.0);
-            let b_lat: f64 = b.latitude.parse().unwrap_or(0.0);
-            let b_lon: f64 = b.longitude.parse().unwrap_or(0.0);
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+pub fn verify_help_request(
+    session_token: String,
+    request_id: u64,
+    verification_note: String,
+) -> Result<(), BackendError> {
+    audit::audited(
+        "verify_help_request",
+        audit::digest((&request_id, &verification_note)),
+        || {
+            let caller = auth::authorize(&session_token, Permission::VerifyRequest)?;
+            validation::note("verification_note", &verification_note, false)?;
+            ic_cdk::println!("Attempting to verify request {}", request_id);
+            HELP_REQUESTS.with(|requests| {
+                let mut requests = requests.borrow_mut();
+                let mut request = find_request(&requests, request_id)?;
+                ic_cdk::println!("Found request: {:?}", request);
+                organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+
+                status::transition(
+                    &mut request,
+                    RequestStatus::Verified,
+                    &caller.email,
+                    Some(verification_note.clone()),
+                )?;
+                request.verification_note = Some(verification_note);
+                request.verified_by = Some(caller.email);
+                requests.insert(request_id, request);
+                ic_cdk::println!("Successfully verified request");
+                Ok(())
+            })
+        },
+    )
+}
 
-            let dist_a = calculate_distance(vol_lat, vol_lon, a_lat, a_lon);
-            let dist_b = calculate_distance(vol_lat, vol_lon, b_lat, b_lon);
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SupplyBundle {
+    id: String,
+    organization_id: String,
+    name: String,
+    description: String,
+    items: Vec<SupplyItem>,
+    status: String,
+    assigned_to: Option<String>,
+    // Nanoseconds since the epoch; set by the canister
+    created_at: u64,
+    incident_id: Option<String>,
+}
 
-            dist_a.partial_cmp(&dist_b).unwrap_or(std::cmp::Ordering::Equal)
-        });
+impl Versioned for SupplyBundle {
+    const VERSION: u8 = 4;
 
-        nearby_requests
-    })
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        let old: SupplyBundleV3 = if version == 3 {
+            schema::decode_or_trap(version, bytes)
+        } else {
+            SupplyBundleV3::from_older(version, bytes)
+        };
+        SupplyBundle {
+            id: old.id,
+            organization_id: old.organization_id,
+            name: old.name,
+            description: old.description,
+            items: old.items,
+            status: old.status,
+            assigned_to: old.assigned_to,
+            created_at: old.created_at,
+            incident_id: None,
+        }
+    }
 }
 
-fn calculate_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
-    let r = 6371.0; // Earth's radius in kilometers
-    let dlat = (lat2 - lat1).to_radians();
-    let dlon = (lon2 - lon1).to_radians();
-    let a = (dlat/2.0).sin() * (dlat/2.0).sin() +
-        lat1.to_radians().cos() * lat2.to_radians().cos() *
-        (dlon/2.0).sin() * (dlon/2.0).sin();
-    let c = 2.0 * a.sqrt().atan2((1.0-a).sqrt());
-    r * c
+// Layout of supply bundles stored before they could belong to an incident
+#[derive(CandidType, Deserialize)]
+struct SupplyBundleV3 {
+    id: String,
+    organization_id: String,
+    name: String,
+    description: String,
+    items: Vec<SupplyItem>,
+    status: String,
+    assigned_to: Option<String>,
+    created_at: u64,
 }
 
-#[ic_cdk_macros::update]
-pub async fn verify_help_request(victim_id: String, timestamp: String, verification_note: String, verifier_type: String) -> bool {
-    ic_cdk::println!("Attempting to verify request for victim: {} at timestamp: {}", victim_id, timestamp);
-    HELP_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        
-        let request_id = format!("{}_{}", victim_id, timestamp);
-        let request_key = StableString::from(request_id.clone());
-        
-        if let Some(request) = requests.get(&request_key) {
-            ic_cdk::println!("Found request: {:?}", request);
-            if request.status.to_lowercase() == "pending" {
-                let mut updated_request = request.clone();
-                updated_request.status = "verified".to_string();
-                updated_request.verification_note = Some(verification_note);
-                updated_request.verified_by = Some(verifier_type);
-                requests.insert(request_key, updated_request.clone());
-                ic_cdk::println!("Successfully verified request");
-                true
-            } else {
-                ic_cdk::println!("Request is not in pending status");
-                false
-            }
+impl SupplyBundleV3 {
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        let old: SupplyBundleV2 = if version == 2 {
+            schema::decode_or_trap(version, bytes)
         } else {
-            ic_cdk::println!("Request not found with ID: {}", request_id);
-            false
+            let old: SupplyBundleV1 = schema::decode_or_trap(version, bytes);
+            let record = format!("Supply bundle {}", old.id);
+            SupplyBundleV2 {
+                created_at: legacy::timestamp(&record, "created_at", &old.created_at),
+                id: old.id,
+                name: old.name,
+                description: old.description,
+                items: old.items,
+                status: old.status,
+                assigned_to: old.assigned_to,
+            }
+        };
+        SupplyBundleV3 {
+            id: old.id,
+            organization_id: organization::LEGACY_DEFAULT_ORGANIZATION.to_string(),
+            name: old.name,
+            description: old.description,
+            items: old.items,
+            status: old.status,
+            assigned_to: old.assigned_to,
+            created_at: old.created_at,
         }
-    })
+    }
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
-pub struct SupplyBundle {
+#[derive(CandidType, Deserialize)]
+struct SupplyBundleV2 {
+    id: String,
+    name: String,
+    description: String,
+    items: Vec<SupplyItem>,
+    status: String,
+    assigned_to: Option<String>,
+    created_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct SupplyBundleV1 {
     id: String,
     name: String,
     description: String,
@@ -683,20 +2219,15 @@ pub struct SupplyBundle {
 }
 
 impl Storable for SupplyBundle {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::from_envelope(&bytes)
     }
 }
 
-impl BoundedStorable for SupplyBundle {
-    const MAX_SIZE: u32 = 1024;
-    const IS_FIXED_SIZE: bool = false;
-}
-
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct SupplyItem {
     name: String,
@@ -704,80 +2235,175 @@ pub struct SupplyItem {
     unit: String,
 }
 
-impl Storable for SupplyItem {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Donation {
+    id: String,
+    // The organization the donation goes to
+    organization_id: String,
+    amount: f64,
+    donor_name: String,
+    donor_email: String,
+    // Nanoseconds since the epoch; set by the canister
+    date: u64,
+    distribution_details: Vec<DistributionDetail>,
+    incident_id: Option<String>,
+}
+
+impl Versioned for Donation {
+    const VERSION: u8 = 4;
+
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        let old: DonationV3 = if version == 3 {
+            schema::decode_or_trap(version, bytes)
+        } else {
+            DonationV3::from_older(version, bytes)
+        };
+        Donation {
+            id: old.id,
+            organization_id: old.organization_id,
+            amount: old.amount,
+            donor_name: old.donor_name,
+            donor_email: old.donor_email,
+            date: old.date,
+            distribution_details: old.distribution_details,
+            incident_id: None,
+        }
     }
+}
 
-    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+// Layout of donations stored before they could belong to an incident
+#[derive(CandidType, Deserialize)]
+struct DonationV3 {
+    id: String,
+    organization_id: String,
+    amount: f64,
+    donor_name: String,
+    donor_email: String,
+    date: u64,
+    distribution_details: Vec<DistributionDetail>,
+}
+
+impl DonationV3 {
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        let old: DonationV2 = if version == 2 {
+            schema::decode_or_trap(version, bytes)
+        } else {
+            let old: DonationV1 = schema::decode_or_trap(version, bytes);
+            let record = format!("Donation {}", old.id);
+            DonationV2 {
+                date: legacy::timestamp(&record, "date", &old.date),
+                distribution_details: old
+                    .distribution_details
+                    .into_iter()
+                    .map(|detail| DistributionDetail {
+                        amount: detail.amount,
+                        purpose: detail.purpose,
+                        date: legacy::timestamp(&record, "distribution date", &detail.date),
+                    })
+                    .collect(),
+                id: old.id,
+                amount: old.amount,
+                donor_name: old.donor_name,
+                donor_email: old.donor_email,
+            }
+        };
+        DonationV3 {
+            id: old.id,
+            organization_id: organization::LEGACY_DEFAULT_ORGANIZATION.to_string(),
+            amount: old.amount,
+            donor_name: old.donor_name,
+            donor_email: old.donor_email,
+            date: old.date,
+            distribution_details: old.distribution_details,
+        }
     }
 }
 
-impl BoundedStorable for SupplyItem {
-    const MAX_SIZE: u32 = 128;
-    const IS_FIXED_SIZE: bool = false;
+#[derive(CandidType, Deserialize)]
+struct DonationV2 {
+    id: String,
+    amount: f64,
+    donor_name: String,
+    donor_email: String,
+    date: u64,
+    distribution_details: Vec<DistributionDetail>,
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
-pub struct Donation {
+#[derive(CandidType, Deserialize)]
+struct DonationV1 {
     id: String,
     amount: f64,
     donor_name: String,
     donor_email: String,
     date: String,
-    distribution_details: Vec<DistributionDetail>,
+    distribution_details: Vec<DistributionDetailV1>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct DistributionDetailV1 {
+    amount: f64,
+    purpose: String,
+    date: String,
 }
 
 impl Storable for Donation {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::from_envelope(&bytes)
     }
 }
 
-impl BoundedStorable for Donation {
-    const MAX_SIZE: u32 = 1024;
-    const IS_FIXED_SIZE: bool = false;
-}
-
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct DistributionDetail {
     amount: f64,
     purpose: String,
-    date: String,
+    // Nanoseconds since the epoch
+    date: u64,
 }
 
-impl Storable for DistributionDetail {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
-    }
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn login(email: String, password: String) -> Result<Session, BackendError> {
+    ic_cdk::println!("Login attempt for email: {}", email);
+    // Organizations and admins have their own login endpoints
+    let credentials_valid = USERS.with(|users| {
+        let users = users.borrow();
+        users
+            .get(&StableString::from(email.clone()))
+            .map(|user| user.role.is_self_assignable() && user.credential.verify(&password))
+            .unwrap_or(false)
+    });
 
-    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+    if !credentials_valid {
+        ic_cdk::println!("Login failed: Invalid credentials for: {}", email);
+        return Err(BackendError::Unauthenticated);
     }
+
+    // The first successful login links the calling principal to the account
+    auth::bind_caller(&email)?;
+    rehash_if_provisional(&email, &password).await?;
+
+    auth::start_session(&email).await
 }
 
-impl BoundedStorable for DistributionDetail {
-    const MAX_SIZE: u32 = 256;
-    const IS_FIXED_SIZE: bool = false;
+#[update(guard = "caller_is_not_anonymous")]
+pub fn logout() -> Result<(), BackendError> {
+    auth::end_session()
 }
 
 // Organization functions
-#[ic_cdk_macros::update]
-pub async fn organization_login(email: String, password: String) -> bool {
+#[ic_cdk_macros::update(guard = "caller_is_not_anonymous")]
+pub async fn organization_login(email: String, password: String) -> Result<Session, BackendError> {
     ic_cdk::println!("Attempting organization login for email: {}", email);
-    USERS.with(|users| {
+    let credentials_valid = USERS.with(|users| {
         let users = users.borrow();
         let email_key = StableString::from(email.clone());
         
         if let Some(user) = users.get(&email_key) {
             ic_cdk::println!("Found user: {:?}", user);
-            if user.user_type == "organization" && user.password == password {
-                ic_cdk::println!("Login successful for organization: {}", email);
+            if user.role == Role::Organization && user.credential.verify(&password) {
                 true
             } else {
                 ic_cdk::println!("Login failed: Invalid credentials or user type for: {}", email);
@@ -787,95 +2413,235 @@ pub async fn organization_login(email: String, password: String) -> bool {
             ic_cdk::println!("No user found with email: {}", email);
             false
         }
+    });
+
+    if !credentials_valid {
+        return Err(BackendError::Unauthenticated);
+    }
+    auth::bind_caller(&email)?;
+    rehash_if_provisional(&email, &password).await?;
+
+    ic_cdk::println!("Login successful for organization: {}", email);
+    auth::start_session(&email).await
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn create_organization(session_token: String, new: NewOrganization) -> Result<(), BackendError> {
+    audit::audited("create_organization", audit::digest((&new,)), || {
+        auth::authorize(&session_token, Permission::ManageOrganizations)?;
+        new.validate()?;
+        ensure_storage_available()?;
+        ic_cdk::println!("Creating organization {}", new.id);
+        organization::create(new)
     })
 }
 
-#[update]
-pub async fn admin_login(email: String, password: String) -> bool {
-    ic_cdk::println!("Admin login attempt for email: {}", email);
-    
-    // First verify the password
-    let password_valid = USERS.with(|users| {
-        let users = users.borrow();
-        let email_key = StableString::from(email.clone());
-        match users.get(&email_key) {
-            Some(user) => {
-                let result = user.password == password;
-                ic_cdk::println!("Password verification result: {}", result);
-                result
-            }
-            None => {
-                ic_cdk::println!("User not found");
-                false
-            }
+// Every organization, for donors and victims to pick from
+#[query]
+pub fn list_organizations(session_token: String) -> Result<Vec<OrganizationSummary>, BackendError> {
+    auth::authenticated(&session_token)?;
+    Ok(organization::all().into_iter().map(OrganizationSummary::from).collect())
+}
+
+#[query]
+pub fn get_my_organizations(session_token: String) -> Result<Vec<OrganizationSummary>, BackendError> {
+    let caller = auth::authenticated(&session_token)?;
+    Ok(organization::organizations_of(&caller.email)
+        .iter()
+        .filter_map(|id| organization::get(id))
+        .map(OrganizationSummary::from)
+        .collect())
+}
+
+// The full record, members included, for its members and admins
+#[query]
+pub fn get_organization(session_token: String, organization_id: String) -> Result<Organization, BackendError> {
+    let caller = auth::authenticated(&session_token)?;
+    organization::ensure_member(&caller, &organization_id)?;
+    organization::find(&organization_id)
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn update_organization(
+    session_token: String,
+    organization_id: String,
+    update: OrganizationUpdate,
+) -> Result<(), BackendError> {
+    audit::audited("update_organization", audit::digest((&organization_id, &update)), || {
+        let caller = auth::authenticated(&session_token)?;
+        let organization = organization::ensure_admin(&caller, &organization_id)?;
+        update.validate()?;
+        ensure_storage_available()?;
+        organization::update(organization, update);
+        Ok(())
+    })
+}
+
+// Adds the account, or changes whether it administers the organization
+#[update(guard = "caller_is_not_anonymous")]
+pub fn add_organization_member(
+    session_token: String,
+    organization_id: String,
+    email: String,
+    as_admin: bool,
+) -> Result<(), BackendError> {
+    audit::audited("add_organization_member", audit::digest((&organization_id, &email, &as_admin)), || {
+        let caller = auth::authenticated(&session_token)?;
+        let organization = organization::ensure_admin(&caller, &organization_id)?;
+        ensure_storage_available()?;
+        organization::add_member(organization, &email, as_admin)
+    })
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn remove_organization_member(
+    session_token: String,
+    organization_id: String,
+    email: String,
+) -> Result<(), BackendError> {
+    audit::audited("remove_organization_member", audit::digest((&organization_id, &email)), || {
+        let caller = auth::authenticated(&session_token)?;
+        let organization = organization::ensure_admin(&caller, &organization_id)?;
+        organization::remove_member(organization, &email)
+    })
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn create_incident(session_token: String, new: NewIncident) -> Result<(), BackendError> {
+    audit::audited("create_incident", audit::digest((&new,)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageIncidents)?;
+        new.validate()?;
+        ensure_storage_available()?;
+        ic_cdk::println!("Declaring incident {}", new.id);
+        incident::create(new, &caller.email)
+    })
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn update_incident(session_token: String, incident_id: String, update: IncidentUpdate) -> Result<(), BackendError> {
+    audit::audited("update_incident", audit::digest((&incident_id, &update)), || {
+        auth::authorize(&session_token, Permission::ManageIncidents)?;
+        let incident = incident::find(&incident_id)?;
+        update.validate()?;
+        ensure_storage_available()?;
+        incident::update(incident, update);
+        Ok(())
+    })
+}
+
+// Most recent first
+#[query]
+pub fn list_incidents(session_token: String, include_closed: bool) -> Result<Vec<Incident>, BackendError> {
+    auth::authenticated(&session_token)?;
+    let mut incidents: Vec<Incident> = incident::all()
+        .into_iter()
+        .filter(|incident| include_closed || incident.status != incident::IncidentStatus::Closed)
+        .collect();
+    incidents.sort_by_key(|incident| std::cmp::Reverse(incident.start_time));
+    Ok(incidents)
+}
+
+#[query]
+pub fn get_incident(session_token: String, incident_id: String) -> Result<Incident, BackendError> {
+    auth::authenticated(&session_token)?;
+    incident::find(&incident_id)
+}
+
+// Link a request to an incident, or unlink it
+#[update(guard = "caller_is_not_anonymous")]
+pub fn set_request_incident(
+    session_token: String,
+    request_id: u64,
+    incident_id: Option<String>,
+) -> Result<(), BackendError> {
+    audit::audited("set_request_incident", audit::digest((&request_id, &incident_id)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageRequests)?;
+        if let Some(incident_id) = &incident_id {
+            incident::find(incident_id)?;
         }
-    });
+        HELP_REQUESTS.with(|requests| {
+            let mut requests = requests.borrow_mut();
+            let mut request = find_request(&requests, request_id)?;
+            organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+            request.incident_id = incident_id;
+            requests.insert(request_id, request);
+            Ok(())
+        })
+    })
+}
 
-    if !password_valid {
-        ic_cdk::println!("Password verification failed for admin login");
-        return false;
-    }
+// Coordinators see the figures of their own organizations' records
+#[query]
+pub fn get_incident_dashboard(session_token: String, incident_id: String) -> Result<IncidentDashboard, BackendError> {
+    let caller = auth::authorize(&session_token, Permission::ViewAllRequests)?;
+    incident::find(&incident_id)?;
+    let scope = organization::scope_of(&caller);
+    Ok(incident::dashboard(&incident_id, |organization_id| scope.includes(organization_id)))
+}
 
-    // Then check if the user is an admin
-    let is_admin_user = USERS.with(|users| {
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn admin_login(email: String, password: String) -> Result<Session, BackendError> {
+    ic_cdk::println!("Admin login attempt for email: {}", email);
+    
+    // An admin without a password, like the one created at install, signs in
+    // through the principal it is bound to
+    let caller = ic_cdk::caller();
+    let credentials_valid = USERS.with(|users| {
         let users = users.borrow();
         let email_key = StableString::from(email.clone());
-        users.get(&email_key)
-            .map(|user| {
-                ic_cdk::println!("Found user: {:?}, is_admin: {}", user.email, user.is_admin);
-                user.is_admin
-            })
-            .unwrap_or_else(|| {
-                ic_cdk::println!("User not found");
-                false
-            })
+        users.get(&email_key).is_some_and(|user| {
+            user.role == Role::Admin
+                && match &user.credential {
+                    Credential::Unset => user.principal == Some(caller),
+                    credential => credential.verify(&password),
+                }
+        })
     });
 
-    if !is_admin_user {
-        ic_cdk::println!("User is not an admin");
-        return false;
+    if !credentials_valid {
+        ic_cdk::println!("Admin login failed for: {}", email);
+        return Err(BackendError::Unauthenticated);
     }
 
+    auth::bind_caller(&email)?;
+    rehash_if_provisional(&email, &password).await?;
+
     ic_cdk::println!("Admin login successful for: {}", email);
-    true
+    auth::start_session(&email).await
 }
 
-#[update]
-pub fn approve_volunteer_request(victim_id: String, timestamp: String) -> bool {
-    let request_id = format!("{}_{}", victim_id, timestamp);
-    ic_cdk::println!("Attempting to approve request: {}", request_id);
-    
-    HELP_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        let request_key = StableString::from(request_id.clone());
+#[update(guard = "caller_is_not_anonymous")]
+pub fn approve_volunteer_request(session_token: String, request_id: u64) -> Result<(), BackendError> {
+    audit::audited("approve_volunteer_request", audit::digest((&request_id,)), || {
+        let caller = auth::authorize(&session_token, Permission::ManageRequests)?;
+        ic_cdk::println!("Attempting to approve request: {}", request_id);
         
-        if let Some(mut request) = requests.get(&request_key) {
+        HELP_REQUESTS.with(|requests| {
+            let mut requests = requests.borrow_mut();
+            let mut request = find_request(&requests, request_id)?;
+            organization::ensure_manages(&caller, request.organization_id.as_ref())?;
+
             // Check if the request has a volunteer assigned
-            if request.assigned_volunteer.is_some() {
-                let mut updated_request = request.clone();
-                updated_request.status = "completed".to_string();
-                requests.insert(request_key, updated_request);
-                ic_cdk::println!("Successfully approved request");
-                true
-            } else {
+            if request.assigned_volunteer.is_none() {
                 ic_cdk::println!("Request has no assigned volunteer");
-                false
+                return Err(BackendError::invalid_state(request.status.as_str(), "completed"));
             }
-        } else {
-            ic_cdk::println!("Request not found: {}", request_id);
-            false
-        }
+            status::transition(&mut request, RequestStatus::Completed, &caller.email, None)?;
+            requests.insert(request_id, request);
+            ic_cdk::println!("Successfully approved request");
+            Ok(())
+        })
     })
 }
 
-#[update]
-pub fn clear_database() -> bool {
-    ic_cdk::println!("Attempting to clear database");
-    
-    // Clear users
+// Wipe every record but the given admin's account, principal binding and
+// session, so they stay signed in and can keep using the admin endpoints
+fn clear_data_except(keep: &User) {
+    // Clear users, except the admin doing the clearing
+    let keep_email = StableString::from(keep.email.clone());
     USERS.with(|users| {
         let mut users = users.borrow_mut();
-        let keys: Vec<_> = users.iter().map(|(k, _)| k.clone()).collect();
+        let keys: Vec<_> = users.iter().map(|(k, _)| k.clone()).filter(|k| *k != keep_email).collect();
         for key in keys {
             users.remove(&key);
         }
@@ -884,7 +2650,7 @@ pub fn clear_database() -> bool {
     // Clear help requests
     HELP_REQUESTS.with(|requests| {
         let mut requests = requests.borrow_mut();
-        let keys: Vec<_> = requests.iter().map(|(k, _)| k.clone()).collect();
+        let keys: Vec<_> = requests.iter().map(|(k, _)| k).collect();
         for key in keys {
             requests.remove(&key);
         }
@@ -916,77 +2682,242 @@ pub fn clear_database() -> bool {
             locations.remove(&key);
         }
     });
+    tracking::clear_trails();
+    profile::clear();
+    organization::clear();
+    incident::clear();
+
+    // Clear principal bindings and sessions, since their accounts are gone
+    let keep_principal = keep.principal.map(|principal| StableString::from(principal.to_text()));
+    PRINCIPALS.with(|principals| {
+        let mut principals = principals.borrow_mut();
+        let keys: Vec<_> = principals
+            .iter()
+            .map(|(k, _)| k.clone())
+            .filter(|k| Some(k) != keep_principal.as_ref())
+            .collect();
+        for key in keys {
+            principals.remove(&key);
+        }
+    });
 
-    ic_cdk::println!("Database cleared successfully");
-    true
-}
-
-#[update]
-pub fn clear_help_requests() -> bool {
-    HELP_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        let keys: Vec<StableString> = requests.iter().map(|(k, _)| k.clone()).collect();
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let keys: Vec<_> = sessions
+            .iter()
+            .map(|(k, _)| k.clone())
+            .filter(|k| Some(k) != keep_principal.as_ref())
+            .collect();
         for key in keys {
-            requests.remove(&key);
+            sessions.remove(&key);
         }
-        true
+    });
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn clear_database(session_token: String) -> Result<(), BackendError> {
+    audit::audited("clear_database", audit::digest(()), || {
+        let caller = auth::authorize(&session_token, Permission::ClearData)?;
+        ic_cdk::println!("Attempting to clear database");
+        clear_data_except(&caller);
+        ic_cdk::println!("Database cleared successfully");
+        Ok(())
     })
 }
 
-#[update]
-pub fn clear_volunteer_locations() -> bool {
-    VOLUNTEER_LOCATIONS.with(|locations| {
-        let mut locations = locations.borrow_mut();
-        let keys: Vec<StableString> = locations.iter().map(|(k, _)| k.clone()).collect();
-        for key in keys {
-            locations.remove(&key);
-        }
-        true
+#[update(guard = "caller_is_not_anonymous")]
+pub fn clear_help_requests(session_token: String) -> Result<(), BackendError> {
+    audit::audited("clear_help_requests", audit::digest(()), || {
+        auth::authorize(&session_token, Permission::ClearData)?;
+        HELP_REQUESTS.with(|requests| {
+            let mut requests = requests.borrow_mut();
+            let keys: Vec<u64> = requests.iter().map(|(k, _)| k).collect();
+            for key in keys {
+                requests.remove(&key);
+            }
+        });
+        Ok(())
     })
 }
 
-#[update]
-pub fn clear_supply_bundles() -> bool {
-    SUPPLY_BUNDLES.with(|bundles| {
-        let mut bundles = bundles.borrow_mut();
-        let keys: Vec<StableString> = bundles.iter().map(|(k, _)| k.clone()).collect();
-        for key in keys {
-            bundles.remove(&key);
-        }
-        true
+#[update(guard = "caller_is_not_anonymous")]
+pub fn clear_volunteer_locations(session_token: String) -> Result<(), BackendError> {
+    audit::audited("clear_volunteer_locations", audit::digest(()), || {
+        auth::authorize(&session_token, Permission::ClearData)?;
+        VOLUNTEER_LOCATIONS.with(|locations| {
+            let mut locations = locations.borrow_mut();
+            let keys: Vec<StableString> = locations.iter().map(|(k, _)| k.clone()).collect();
+            for key in keys {
+                locations.remove(&key);
+            }
+        });
+        tracking::clear_trails();
+        Ok(())
     })
 }
 
-#[update]
-pub fn clear_donations() -> bool {
-    DONATIONS.with(|donations| {
-        let mut donations = donations.borrow_mut();
-        let keys: Vec<StableString> = donations.iter().map(|(k, _)| k.clone()).collect();
-        for key in keys {
-            donations.remove(&key);
-        }
-        true
+#[update(guard = "caller_is_not_anonymous")]
+pub fn clear_supply_bundles(session_token: String) -> Result<(), BackendError> {
+    audit::audited("clear_supply_bundles", audit::digest(()), || {
+        auth::authorize(&session_token, Permission::ClearData)?;
+        SUPPLY_BUNDLES.with(|bundles| {
+            let mut bundles = bundles.borrow_mut();
+            let keys: Vec<StableString> = bundles.iter().map(|(k, _)| k.clone()).collect();
+            for key in keys {
+                bundles.remove(&key);
+            }
+        });
+        Ok(())
     })
 }
 
-#[update]
-pub fn make_donation(donation: Donation) -> bool {
-    DONATIONS.with(|donations| {
-        let mut donations = donations.borrow_mut();
-        let donation_key = StableString::from(donation.id.clone());
-        donations.insert(donation_key, donation);
-        true
+#[update(guard = "caller_is_not_anonymous")]
+pub fn clear_donations(session_token: String) -> Result<(), BackendError> {
+    audit::audited("clear_donations", audit::digest(()), || {
+        auth::authorize(&session_token, Permission::ClearData)?;
+        DONATIONS.with(|donations| {
+            let mut donations = donations.borrow_mut();
+            let keys: Vec<StableString> = donations.iter().map(|(k, _)| k.clone()).collect();
+            for key in keys {
+                donations.remove(&key);
+            }
+        });
+        Ok(())
     })
 }
 
 #[query]
-pub fn get_donor_donations(donor_email: String) -> Vec<Donation> {
-    DONATIONS.with(|donations| {
-        let donations = donations.borrow();
+pub fn get_audit_log(
+    session_token: String,
+    filter: AuditFilter,
+    cursor: Option<u64>,
+    limit: Option<u32>,
+) -> Result<AuditLogPage, BackendError> {
+    auth::authorize(&session_token, Permission::ViewAuditLog)?;
+    Ok(audit::page(&filter, cursor, limit))
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub fn make_donation(session_token: String, donation: Donation) -> Result<(), BackendError> {
+    audit::audited("make_donation", audit::digest((&donation,)), || {
+        let caller = auth::authorize(&session_token, Permission::MakeDonation)?;
+        donation.validate()?;
+        organization::find(&donation.organization_id)?;
+        if let Some(incident_id) = &donation.incident_id {
+            incident::find(incident_id)?;
+        }
+        ensure_storage_available()?;
+        DONATIONS.with(|donations| {
+            let mut donations = donations.borrow_mut();
+            let donation_key = StableString::from(donation.id.clone());
+            if donations.contains_key(&donation_key) {
+                return Err(BackendError::Conflict(format!(
+                    "donation {} already exists",
+                    donation.id
+                )));
+            }
+            // Donations are always recorded against the calling account,
+            // at the time they are made
+            let donation = Donation {
+                donor_email: caller.email,
+                date: ic_cdk::api::time(),
+                ..donation
+            };
+            donations.insert(donation_key, donation);
+            Ok(())
+        })
+    })
+}
+
+#[query]
+pub fn get_donor_donations(session_token: String, donor_email: String) -> Result<Vec<Donation>, BackendError> {
+    let caller = auth::authorize_owner_or(&session_token, &donor_email, Permission::ViewDonations)?;
+    // Donors see all their donations, organizations only those made to them
+    let scope = if caller.email == donor_email {
+        Scope::Everything
+    } else {
+        organization::scope_of(&caller)
+    };
+    let donation_ids = index::donations_of_donor(&donor_email);
+    Ok(DONATIONS.with(|donations| {
         donations
-            .iter()
-            .filter(|(_, donation)| donation.donor_email == donor_email)
-            .map(|(_, donation)| donation.clone())
+            .borrow()
+            .entries_of(donation_ids)
+            .map(|(_, donation)| donation)
+            .filter(|donation| scope.includes(Some(&donation.organization_id)))
             .collect()
-    })
+    }))
+}
+
+// The Candid interface is generated from the endpoint signatures above;
+// project_backend.did is a checked-in copy of it
+candid::export_service!();
+
+#[query(name = "__get_candid_interface_tmp_hack")]
+fn export_candid() -> String {
+    __export_service()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearing_keeps_the_admin_signed_in() {
+        let sign_in = |email: &str, role: Role, principal: Principal| {
+            let user = User {
+                email: email.to_string(),
+                credential: Credential::Unset,
+                name: String::new(),
+                role,
+                phone: String::new(),
+                address: String::new(),
+                principal: Some(principal),
+            };
+            let principal_key = StableString::from(principal.to_text());
+            USERS.with(|users| users.borrow_mut().insert(StableString::from(email.to_string()), user.clone()));
+            PRINCIPALS.with(|principals| {
+                principals
+                    .borrow_mut()
+                    .insert(principal_key.clone(), StableString::from(email.to_string()))
+            });
+            let session = Session {
+                token: "token".to_string(),
+                email: email.to_string(),
+                expires_at: 100,
+            };
+            SESSIONS.with(|sessions| sessions.borrow_mut().insert(principal_key, session));
+            user
+        };
+        let admin = sign_in("admin@example.org", Role::Admin, Principal::from_slice(&[1]));
+        let victim = sign_in("ann@example.org", Role::Victim, Principal::from_slice(&[2]));
+
+        clear_data_except(&admin);
+
+        let bound = |principal: Principal| {
+            PRINCIPALS
+                .with(|principals| principals.borrow().get(&StableString::from(principal.to_text())))
+                .and_then(|email| USERS.with(|users| users.borrow().get(&email)))
+        };
+        let still_admin = bound(Principal::from_slice(&[1])).expect("the admin keeps their account and binding");
+        assert!(auth::check_session(Principal::from_slice(&[1]), &still_admin, "token", 50).is_ok());
+        assert!(Permission::ClearData.is_granted_to(still_admin.role));
+        let admins: Vec<String> = index::users_with_role(Role::Admin).into_iter().map(|email| email.0).collect();
+        assert_eq!(admins, [admin.email]);
+
+        assert!(bound(Principal::from_slice(&[2])).is_none());
+        assert!(auth::check_session(Principal::from_slice(&[2]), &victim, "token", 50).is_err());
+        assert!(USERS.with(|users| users.borrow().get(&StableString::from(victim.email)).is_none()));
+    }
+
+    #[test]
+    fn checked_in_candid_interface_is_up_to_date() {
+        let checked_in = include_str!("../project_backend.did").replace("\r\n", "\n");
+        let generated = super::__export_service();
+        assert_eq!(
+            checked_in.trim(),
+            generated.trim(),
+            "project_backend.did is out of date; replace it with the output of __get_candid_interface_tmp_hack"
+        );
+    }
 }
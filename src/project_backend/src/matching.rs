@@ -0,0 +1,436 @@
+use candid::CandidType;
+use ic_stable_structures::Storable;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::auth::Role;
+use crate::error::BackendError;
+use crate::schema::{self, Versioned};
+use crate::status::{self, RequestStatus};
+use crate::tracking::Presence;
+use crate::{
+    find_request, geo, history, index, privacy, profile, HelpRequest, StableString, VolunteerLocation,
+    HELP_REQUESTS, MATCHING_CONFIG, USERS, VOLUNTEER_LOCATIONS,
+};
+
+pub const MATCHING_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Recorded as the actor of assignments the timer makes
+const MATCHER_ACTOR: &str = "matching engine";
+const NANOS_PER_MINUTE: u64 = 60_000_000_000;
+
+// How many candidates a proposal lists, and how many requests one timer run
+// may assign so a run stays well inside the instruction limit
+const MAX_CANDIDATES: usize = 10;
+const MAX_ASSIGNMENTS_PER_RUN: usize = 50;
+
+// Positions younger than this count as fully fresh; older ones lose freshness
+// linearly until the configured maximum age
+const FRESH_LOCATION_MINUTES: u64 = 15;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MatchingConfig {
+    // Whether the timer assigns verified requests by itself; proposals
+    // through get_match_candidates are always available
+    pub auto_assign: bool,
+    pub max_distance_km: f64,
+    // Volunteers whose last position is older than this are not matched
+    pub max_location_age_minutes: u64,
+}
+
+impl Default for MatchingConfig {
+    fn default() -> Self {
+        MatchingConfig {
+            auto_assign: false,
+            max_distance_km: 50.0,
+            max_location_age_minutes: 240,
+        }
+    }
+}
+
+impl Versioned for MatchingConfig {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for MatchingConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+// One term of a candidate's score: `value` is in 0..=1 and contributes
+// `value * weight`; the weights of a request add up to 1
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ScoreFactor {
+    pub name: String,
+    pub value: f64,
+    pub weight: f64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MatchCandidate {
+    pub volunteer_id: String,
+    pub score: f64,
+    pub distance_km: f64,
+    pub location_age_minutes: u64,
+    pub active_tasks: u32,
+    pub matched_skills: Vec<String>,
+    pub factors: Vec<ScoreFactor>,
+}
+
+pub fn config() -> MatchingConfig {
+    MATCHING_CONFIG.with(|config| config.borrow().get().clone())
+}
+
+pub fn set_config(new_config: MatchingConfig) -> Result<(), BackendError> {
+    MATCHING_CONFIG.with(|config| {
+        config.borrow_mut().set(new_config).map(|_| ()).map_err(|e| {
+            BackendError::Internal(format!("failed to store the matching config: {:?}", e))
+        })
+    })
+}
+
+// Weights of distance, freshness, workload and skills. The more urgent the
+// request, the more it matters who can get there first.
+fn weights(urgency: &str) -> [f64; 4] {
+    match urgency {
+        "critical" => [0.55, 0.15, 0.10, 0.20],
+        "high" => [0.45, 0.15, 0.15, 0.25],
+        _ => [0.30, 0.15, 0.25, 0.30],
+    }
+}
+
+// Skills that make a volunteer suited to each request type
+pub fn relevant_skills(request_type: &str) -> &'static [&'static str] {
+    match request_type {
+        "medical" => &["medic", "first aid", "nurse", "doctor", "paramedic"],
+        "evacuation" => &["driver", "boat", "search and rescue", "4x4"],
+        "shelter" => &["construction", "carpentry", "shelter"],
+        "food" => &["cooking", "logistics", "driver"],
+        "supplies" => &["logistics", "driver", "warehouse"],
+        _ => &[],
+    }
+}
+
+fn is_volunteer(email: &str) -> bool {
+    USERS.with(|users| {
+        users
+            .borrow()
+            .get(&StableString::from(email.to_string()))
+            .is_some_and(|user| user.role == Role::Volunteer)
+    })
+}
+
+// Assigned, accepted and started requests of a volunteer
+fn active_tasks(email: &str) -> u32 {
+    let request_ids = index::requests_of_volunteer(email);
+    HELP_REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .entries_of(request_ids)
+            .filter(|(_, request)| {
+                matches!(
+                    request.status,
+                    RequestStatus::Assigned | RequestStatus::Accepted | RequestStatus::InProgress
+                )
+            })
+            .count() as u32
+    })
+}
+
+// Volunteers who turned the request down are not proposed for it again
+fn declined_by(request_id: u64) -> Vec<String> {
+    history::events_of(request_id)
+        .into_iter()
+        .filter(|event| {
+            matches!(event.to, RequestStatus::Pending | RequestStatus::Verified)
+                && matches!(event.from, Some(RequestStatus::Assigned | RequestStatus::Accepted))
+        })
+        .map(|event| event.actor)
+        .collect()
+}
+
+fn factor(name: &str, value: f64, weight: f64) -> ScoreFactor {
+    ScoreFactor {
+        name: name.to_string(),
+        value: value.clamp(0.0, 1.0),
+        weight,
+    }
+}
+
+// What is known about one volunteer in relation to a request
+struct Fit {
+    distance_km: f64,
+    location_age_minutes: u64,
+    active_tasks: u32,
+    max_concurrent_tasks: u32,
+    matched_skills: usize,
+}
+
+fn score_factors(request: &HelpRequest, config: &MatchingConfig, fit: &Fit) -> Vec<ScoreFactor> {
+    let [distance_weight, freshness_weight, workload_weight, skills_weight] = weights(&request.urgency);
+    let stale_span = config.max_location_age_minutes.saturating_sub(FRESH_LOCATION_MINUTES).max(1);
+    let freshness =
+        1.0 - fit.location_age_minutes.saturating_sub(FRESH_LOCATION_MINUTES) as f64 / stale_span as f64;
+    let workload = 1.0 - fit.active_tasks as f64 / fit.max_concurrent_tasks as f64;
+    // Request types without relevant skills don't favour anyone
+    let skills = if relevant_skills(&request.request_type).is_empty() {
+        0.5
+    } else {
+        fit.matched_skills as f64 / 2.0
+    };
+    vec![
+        factor("distance", 1.0 - fit.distance_km / config.max_distance_km, distance_weight),
+        factor("location freshness", freshness, freshness_weight),
+        factor("workload", workload, workload_weight),
+        factor("skills", skills, skills_weight),
+    ]
+}
+
+fn total(factors: &[ScoreFactor]) -> f64 {
+    factors.iter().map(|f| f.value * f.weight).sum()
+}
+
+// Volunteers able to take the request, best first, with how each score was made up
+pub fn candidates(request: &HelpRequest) -> Vec<MatchCandidate> {
+    let Some(origin) = request.coordinates else {
+        return Vec::new();
+    };
+    let config = config();
+    let now = ic_cdk::api::time();
+    let declined = declined_by(request.request_id);
+    let wanted = relevant_skills(&request.request_type);
+
+    let locations: Vec<VolunteerLocation> =
+        VOLUNTEER_LOCATIONS.with(|locations| locations.borrow().iter().map(|(_, l)| l).collect());
+    let mut candidates = Vec::new();
+    for location in locations {
+        // Volunteers who stopped reporting are not in the field
+        let (Some(point), Presence::Active) = (location.coordinates, location.presence) else {
+            continue;
+        };
+        let email = location.email;
+        if request.assigned_volunteer.as_ref() == Some(&email) || declined.contains(&email) {
+            continue;
+        }
+        let age_minutes = now.saturating_sub(location.last_updated) / NANOS_PER_MINUTE;
+        if age_minutes > config.max_location_age_minutes {
+            continue;
+        }
+        // Scored and reported in whole kilometres, so neither the distance
+        // nor its factor pins down where the volunteer is
+        let distance_km = privacy::bucket_distance(geo::calculate_distance(origin.lat, origin.lon, point.lat, point.lon));
+        if distance_km > config.max_distance_km || !is_volunteer(&email) {
+            continue;
+        }
+        // Off duty, outside their availability or at capacity
+        let profile = profile::get_or_default(&email);
+        if !profile.is_available(now) {
+            continue;
+        }
+        let active_tasks = active_tasks(&email);
+        if active_tasks >= profile.max_concurrent_tasks {
+            continue;
+        }
+        let matched_skills: Vec<String> = profile
+            .skills
+            .into_iter()
+            .filter(|skill| wanted.contains(&skill.as_str()))
+            .collect();
+
+        let factors = score_factors(
+            request,
+            &config,
+            &Fit {
+                distance_km,
+                location_age_minutes: age_minutes,
+                active_tasks,
+                max_concurrent_tasks: profile.max_concurrent_tasks,
+                matched_skills: matched_skills.len(),
+            },
+        );
+        candidates.push(MatchCandidate {
+            volunteer_id: email,
+            score: total(&factors),
+            distance_km,
+            location_age_minutes: age_minutes,
+            active_tasks,
+            matched_skills,
+            factors,
+        });
+    }
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+// Assign the request to its best candidate
+pub fn assign_best(request_id: u64, actor: &str) -> Result<MatchCandidate, BackendError> {
+    let request = HELP_REQUESTS.with(|requests| find_request(&requests.borrow(), request_id))?;
+    let Some(best) = candidates(&request).into_iter().next() else {
+        return Err(BackendError::not_found("matching volunteer for request", &request_id.to_string()));
+    };
+    HELP_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        let mut request = find_request(&requests, request_id)?;
+        status::transition(
+            &mut request,
+            RequestStatus::Assigned,
+            actor,
+            Some(format!("matched to {} with score {:.2}", best.volunteer_id, best.score)),
+        )?;
+        request.assigned_volunteer = Some(best.volunteer_id.clone());
+        requests.insert(request_id, request);
+        Ok(())
+    })?;
+    Ok(best)
+}
+
+fn urgency_rank(urgency: &str) -> u8 {
+    match urgency {
+        "critical" => 0,
+        "high" => 1,
+        "medium" => 2,
+        _ => 3,
+    }
+}
+
+// Timer job: assign verified requests, most urgent first, when auto-assignment is on
+pub fn run_auto_assignment() {
+    if !config().auto_assign {
+        return;
+    }
+    let mut verified: Vec<(u8, u64)> = HELP_REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .iter()
+            .filter(|(_, request)| request.status == RequestStatus::Verified)
+            .map(|(request_id, request)| (urgency_rank(&request.urgency), request_id))
+            .collect()
+    });
+    verified.sort();
+    let mut assigned = 0;
+    for (_, request_id) in verified.into_iter().take(MAX_ASSIGNMENTS_PER_RUN) {
+        match assign_best(request_id, MATCHER_ACTOR) {
+            Ok(best) => {
+                ic_cdk::println!("Matched request {} to {}", request_id, best.volunteer_id);
+                assigned += 1;
+            }
+            Err(e) => ic_cdk::println!("No match for request {}: {:?}", request_id, e),
+        }
+    }
+    ic_cdk::println!("Matching run assigned {} requests", assigned);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RequestEvent;
+    use crate::REQUEST_HISTORY;
+    use candid::Principal;
+
+    fn request(request_type: &str, urgency: &str) -> HelpRequest {
+        HelpRequest {
+            request_id: 1,
+            victim_id: "ann@example.org".to_string(),
+            request_type: request_type.to_string(),
+            description: String::new(),
+            urgency: urgency.to_string(),
+            location: String::new(),
+            status: RequestStatus::Verified,
+            timestamp: 0,
+            coordinates: None,
+            verification_note: None,
+            verified_by: None,
+            organization_id: None,
+            assigned_volunteer: None,
+            incident_id: None,
+        }
+    }
+
+    fn fit(distance_km: f64, matched_skills: usize) -> Fit {
+        Fit {
+            distance_km,
+            location_age_minutes: 5,
+            active_tasks: 0,
+            max_concurrent_tasks: 3,
+            matched_skills,
+        }
+    }
+
+    fn score(request: &HelpRequest, fit: &Fit) -> f64 {
+        total(&score_factors(request, &MatchingConfig::default(), fit))
+    }
+
+    #[test]
+    fn weights_add_up_to_one() {
+        for urgency in ["critical", "high", "medium", "low"] {
+            let sum: f64 = weights(urgency).iter().sum();
+            assert!((sum - 1.0).abs() < 1e-9, "{}", urgency);
+        }
+    }
+
+    #[test]
+    fn nearer_skilled_and_idle_volunteers_score_higher() {
+        let medical = request("medical", "high");
+        let best = score(&medical, &fit(1.0, 2));
+        assert!(best > score(&medical, &fit(30.0, 2)));
+        assert!(best > score(&medical, &fit(1.0, 0)));
+        assert!(best > score(&medical, &Fit { active_tasks: 2, ..fit(1.0, 2) }));
+        assert!(best > score(&medical, &Fit { location_age_minutes: 200, ..fit(1.0, 2) }));
+        assert!(best <= 1.0);
+        // Out of range values are clamped rather than going negative: only
+        // the distance weight of a high urgency request is lost
+        let out_of_range = score(&medical, &Fit { distance_km: 500.0, ..fit(1.0, 2) });
+        assert!((out_of_range - 0.55).abs() < 1e-9);
+    }
+
+    #[test]
+    fn urgency_shifts_weight_towards_distance() {
+        // A nearby volunteer without skills against a distant skilled one
+        let near = fit(2.0, 0);
+        let far = fit(40.0, 2);
+        let critical = request("medical", "critical");
+        let medium = request("medical", "medium");
+        assert!(score(&critical, &near) > score(&critical, &far));
+        assert!(score(&medium, &near) < score(&medium, &far));
+    }
+
+    #[test]
+    fn request_types_without_skills_favour_no_one() {
+        let other = request("other", "medium");
+        assert_eq!(score(&other, &fit(5.0, 0)), score(&other, &fit(5.0, 2)));
+    }
+
+    fn event(seq: u64, from: RequestStatus, to: RequestStatus, actor: &str) {
+        let event = RequestEvent {
+            request_id: 9,
+            organization_id: None,
+            from: Some(from),
+            to,
+            actor: actor.to_string(),
+            actor_principal: Principal::anonymous(),
+            note: None,
+            timestamp: seq,
+        };
+        REQUEST_HISTORY.with(|history| history.borrow_mut().insert((9, seq), event));
+    }
+
+    #[test]
+    fn volunteers_who_declined_are_remembered() {
+        use RequestStatus::*;
+        event(0, Verified, Assigned, "coordinator@example.org");
+        event(1, Assigned, Verified, "bob@example.org");
+        event(2, Verified, Assigned, "coordinator@example.org");
+        event(3, Assigned, Accepted, "cat@example.org");
+        event(4, Accepted, Pending, "cat@example.org");
+        event(5, Pending, Assigned, "coordinator@example.org");
+        event(6, Assigned, InProgress, "dan@example.org");
+        assert_eq!(declined_by(9), ["bob@example.org", "cat@example.org"]);
+        assert!(declined_by(10).is_empty());
+    }
+}
@@ -0,0 +1,472 @@
+use candid::CandidType;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Role;
+use crate::error::BackendError;
+use crate::geo::{self, GeoPoint};
+use crate::schema::{self, Versioned};
+use crate::{index, HelpRequest, StableString, User, DONATIONS, HELP_REQUESTS, ORGANIZATIONS, SUPPLY_BUNDLES, USERS};
+
+// Requests used to default to this organization, and bundles and donations
+// stored before organizations existed are attributed to it
+pub const LEGACY_DEFAULT_ORGANIZATION: &str = "organization@disasterrelief.com";
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum AreaShape {
+    Circle { center: GeoPoint, radius_km: f64 },
+    // Vertices in order; the last one connects back to the first. Polygons
+    // must not cross the antimeridian.
+    Polygon { vertices: Vec<GeoPoint> },
+}
+
+impl AreaShape {
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        match self {
+            AreaShape::Circle { center, radius_km } => {
+                geo::calculate_distance(center.lat, center.lon, point.lat, point.lon) <= *radius_km
+            }
+            AreaShape::Polygon { vertices } => {
+                // Even-odd rule: count the edges a ray going east crosses
+                let mut inside = false;
+                for (i, a) in vertices.iter().enumerate() {
+                    let b = &vertices[(i + 1) % vertices.len()];
+                    if (a.lat > point.lat) != (b.lat > point.lat) {
+                        let crossing_lon = a.lon + (point.lat - a.lat) / (b.lat - a.lat) * (b.lon - a.lon);
+                        if point.lon < crossing_lon {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    fn center(&self) -> GeoPoint {
+        match self {
+            AreaShape::Circle { center, .. } => *center,
+            AreaShape::Polygon { vertices } => {
+                let count = vertices.len().max(1) as f64;
+                GeoPoint {
+                    lat: vertices.iter().map(|v| v.lat).sum::<f64>() / count,
+                    lon: vertices.iter().map(|v| v.lon).sum::<f64>() / count,
+                }
+            }
+        }
+    }
+}
+
+// Where an organization operates
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ServiceArea {
+    pub name: String,
+    pub shape: AreaShape,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    // Emails of the accounts that act for the organization. Admins are
+    // always members too.
+    pub members: Vec<String>,
+    pub admins: Vec<String>,
+    pub service_areas: Vec<ServiceArea>,
+    pub created_at: u64,
+}
+
+impl Versioned for Organization {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for Organization {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct NewOrganization {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub admins: Vec<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OrganizationUpdate {
+    pub name: String,
+    pub description: String,
+    pub service_areas: Vec<ServiceArea>,
+}
+
+// What anyone signed in may know about an organization
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct OrganizationSummary {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub service_areas: Vec<ServiceArea>,
+}
+
+impl From<Organization> for OrganizationSummary {
+    fn from(organization: Organization) -> Self {
+        OrganizationSummary {
+            id: organization.id,
+            name: organization.name,
+            description: organization.description,
+            service_areas: organization.service_areas,
+        }
+    }
+}
+
+pub fn get(id: &str) -> Option<Organization> {
+    ORGANIZATIONS.with(|organizations| organizations.borrow().get(&StableString::from(id.to_string())))
+}
+
+pub fn find(id: &str) -> Result<Organization, BackendError> {
+    get(id).ok_or_else(|| BackendError::not_found("organization", id))
+}
+
+fn save(organization: Organization) {
+    ORGANIZATIONS.with(|organizations| {
+        organizations
+            .borrow_mut()
+            .insert(StableString::from(organization.id.clone()), organization);
+    });
+}
+
+pub fn all() -> Vec<Organization> {
+    ORGANIZATIONS.with(|organizations| organizations.borrow().iter().map(|(_, o)| o).collect())
+}
+
+pub fn organizations_of(email: &str) -> Vec<String> {
+    index::organizations_of_member(email).into_iter().map(|id| id.0).collect()
+}
+
+pub fn is_member(email: &str, organization_id: &str) -> bool {
+    organizations_of(email).iter().any(|id| id == organization_id)
+}
+
+fn denied(organization_id: &str) -> BackendError {
+    BackendError::OrganizationAccessDenied {
+        organization_id: organization_id.to_string(),
+    }
+}
+
+// The organizations whose data a caller may see
+pub enum Scope {
+    Everything,
+    Organizations(Vec<String>),
+}
+
+impl Scope {
+    pub fn includes(&self, organization_id: Option<&String>) -> bool {
+        match self {
+            Scope::Everything => true,
+            Scope::Organizations(ids) => organization_id.is_some_and(|id| ids.contains(id)),
+        }
+    }
+}
+
+// Admins see every organization's data, everyone else that of their own
+pub fn scope_of(caller: &User) -> Scope {
+    if caller.role == Role::Admin {
+        Scope::Everything
+    } else {
+        Scope::Organizations(organizations_of(&caller.email))
+    }
+}
+
+pub fn ensure_member(caller: &User, organization_id: &str) -> Result<(), BackendError> {
+    find(organization_id)?;
+    if scope_of(caller).includes(Some(&organization_id.to_string())) {
+        Ok(())
+    } else {
+        Err(denied(organization_id))
+    }
+}
+
+pub fn ensure_admin(caller: &User, organization_id: &str) -> Result<Organization, BackendError> {
+    let organization = find(organization_id)?;
+    if caller.role == Role::Admin || organization.admins.contains(&caller.email) {
+        Ok(organization)
+    } else {
+        Err(denied(organization_id))
+    }
+}
+
+// Only members of the owning organization, whatever their role, may act on
+// its records; requests no organization has taken are left to admins
+pub fn ensure_manages(caller: &User, organization_id: Option<&String>) -> Result<(), BackendError> {
+    if scope_of(caller).includes(organization_id) {
+        return Ok(());
+    }
+    Err(denied(organization_id.map_or("none", |id| id.as_str())))
+}
+
+fn is_user(email: &str) -> bool {
+    USERS.with(|users| users.borrow().contains_key(&StableString::from(email.to_string())))
+}
+
+pub fn create(new: NewOrganization) -> Result<(), BackendError> {
+    if get(&new.id).is_some() {
+        return Err(BackendError::Conflict(format!("organization {} already exists", new.id)));
+    }
+    if let Some(missing) = new.admins.iter().find(|email| !is_user(email)) {
+        return Err(BackendError::not_found("user", missing));
+    }
+    save(Organization {
+        id: new.id,
+        name: new.name,
+        description: new.description,
+        members: new.admins.clone(),
+        admins: new.admins,
+        service_areas: Vec::new(),
+        created_at: ic_cdk::api::time(),
+    });
+    Ok(())
+}
+
+pub fn update(organization: Organization, update: OrganizationUpdate) {
+    save(Organization {
+        name: update.name,
+        description: update.description,
+        service_areas: update.service_areas,
+        ..organization
+    });
+    // New service areas may cover requests waiting in the fallback queue
+    route_unrouted();
+}
+
+// The organization responsible for a point. Where service areas overlap,
+// the one whose area is centered closest to the point wins.
+pub fn route(point: &GeoPoint) -> Option<String> {
+    all()
+        .into_iter()
+        .flat_map(|organization| {
+            organization
+                .service_areas
+                .iter()
+                .filter(|area| area.shape.contains(point))
+                .map(|area| {
+                    let center = area.shape.center();
+                    geo::calculate_distance(center.lat, center.lon, point.lat, point.lon)
+                })
+                .reduce(f64::min)
+                .map(|distance| (distance, organization.id))
+        })
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, id)| id)
+}
+
+// Open requests no organization has taken: those filed outside every
+// service area, and those whose coordinates couldn't be read
+pub fn is_unrouted(request: &HelpRequest) -> bool {
+    request.organization_id.is_none() && !request.status.allowed_next().is_empty()
+}
+
+// Hand requests in the fallback queue to organizations now covering them
+pub fn route_unrouted() {
+    HELP_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        let unrouted: Vec<HelpRequest> = requests
+            .entries_of(index::unrouted_requests())
+            .map(|(_, request)| request)
+            .collect();
+        for mut request in unrouted {
+            let Some(organization_id) = request.coordinates.as_ref().and_then(route) else {
+                continue;
+            };
+            ic_cdk::println!("Routed request {} to {}", request.request_id, organization_id);
+            request.organization_id = Some(organization_id);
+            requests.insert(request.request_id, request);
+        }
+    });
+}
+
+pub fn add_member(mut organization: Organization, email: &str, as_admin: bool) -> Result<(), BackendError> {
+    if !is_user(email) {
+        return Err(BackendError::not_found("user", email));
+    }
+    if !organization.members.iter().any(|member| member == email) {
+        organization.members.push(email.to_string());
+    }
+    let is_admin = organization.admins.iter().any(|admin| admin == email);
+    if as_admin && !is_admin {
+        organization.admins.push(email.to_string());
+    } else if !as_admin && is_admin {
+        organization.admins.retain(|admin| admin != email);
+    }
+    save(organization);
+    Ok(())
+}
+
+pub fn remove_member(mut organization: Organization, email: &str) -> Result<(), BackendError> {
+    if !organization.members.iter().any(|member| member == email) {
+        return Err(BackendError::not_found("member", email));
+    }
+    organization.members.retain(|member| member != email);
+    organization.admins.retain(|admin| admin != email);
+    save(organization);
+    Ok(())
+}
+
+// Called when an account is deleted
+pub fn remove_from_all(email: &str) {
+    for id in organizations_of(email) {
+        if let Some(organization) = get(&id) {
+            let _ = remove_member(organization, email);
+        }
+    }
+}
+
+pub fn clear() {
+    ORGANIZATIONS.with(|organizations| {
+        let mut organizations = organizations.borrow_mut();
+        let keys: Vec<_> = organizations.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            organizations.remove(&key);
+        }
+    });
+}
+
+// Migration: turn every organization account into an organization it
+// administers, and create the organizations stored records refer to
+pub fn create_tenants() {
+    let accounts: Vec<User> = USERS.with(|users| {
+        users
+            .borrow()
+            .iter()
+            .map(|(_, user)| user)
+            .filter(|user| user.role == Role::Organization)
+            .collect()
+    });
+    let mut created = 0;
+    for account in accounts {
+        let organization = get(&account.email).unwrap_or_else(|| {
+            created += 1;
+            Organization {
+                id: account.email.clone(),
+                name: account.name.clone(),
+                description: String::new(),
+                members: Vec::new(),
+                admins: Vec::new(),
+                service_areas: Vec::new(),
+                created_at: ic_cdk::api::time(),
+            }
+        });
+        let _ = add_member(organization, &account.email, true);
+    }
+
+    let mut referenced: Vec<String> = HELP_REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .iter()
+            .filter_map(|(_, request)| request.organization_id)
+            .collect()
+    });
+    SUPPLY_BUNDLES.with(|bundles| {
+        referenced.extend(bundles.borrow().iter().map(|(_, bundle)| bundle.organization_id));
+    });
+    DONATIONS.with(|donations| {
+        referenced.extend(donations.borrow().iter().map(|(_, donation)| donation.organization_id));
+    });
+    referenced.sort();
+    referenced.dedup();
+    for id in referenced {
+        if get(&id).is_none() {
+            ic_cdk::println!("Creating organization {} for records that refer to it", id);
+            created += 1;
+            save(Organization {
+                id: id.clone(),
+                name: id,
+                description: String::new(),
+                members: Vec::new(),
+                admins: Vec::new(),
+                service_areas: Vec::new(),
+                created_at: ic_cdk::api::time(),
+            });
+        }
+    }
+    ic_cdk::println!("Created {} organizations", created);
+    index::rebuild();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password::Credential;
+
+    #[test]
+    fn scope_covers_only_listed_organizations() {
+        let scope = Scope::Organizations(vec!["red-cross".to_string()]);
+        assert!(scope.includes(Some(&"red-cross".to_string())));
+        assert!(!scope.includes(Some(&"other".to_string())));
+        assert!(!scope.includes(None));
+        assert!(Scope::Everything.includes(None));
+    }
+
+    #[test]
+    fn service_area_shapes_contain_points() {
+        let point = |lat, lon| GeoPoint { lat, lon };
+        let square = AreaShape::Polygon {
+            vertices: vec![point(0.0, 0.0), point(0.0, 1.0), point(1.0, 1.0), point(1.0, 0.0)],
+        };
+        assert!(square.contains(&point(0.5, 0.5)));
+        assert!(!square.contains(&point(1.5, 0.5)));
+        assert!(!square.contains(&point(0.5, -0.1)));
+
+        let circle = AreaShape::Circle {
+            center: point(52.52, 13.40),
+            radius_km: 10.0,
+        };
+        assert!(circle.contains(&point(52.55, 13.45)));
+        assert!(!circle.contains(&point(52.80, 13.40)));
+    }
+
+    #[test]
+    fn only_members_manage_an_organizations_requests() {
+        let user = |email: &str, role: Role| User {
+            email: email.to_string(),
+            credential: Credential::Unset,
+            name: String::new(),
+            role,
+            phone: String::new(),
+            address: String::new(),
+            principal: None,
+        };
+        save(Organization {
+            id: "red-cross".to_string(),
+            name: "Red Cross".to_string(),
+            description: String::new(),
+            members: vec!["vic@example.org".to_string()],
+            admins: Vec::new(),
+            service_areas: Vec::new(),
+            created_at: 0,
+        });
+        let red_cross = Some("red-cross".to_string());
+        let other = Some("other".to_string());
+
+        let member = user("vic@example.org", Role::Volunteer);
+        assert!(ensure_manages(&member, red_cross.as_ref()).is_ok());
+        assert!(ensure_manages(&member, other.as_ref()).is_err());
+        assert!(ensure_manages(&member, None).is_err());
+
+        let outsider = user("val@example.org", Role::Volunteer);
+        assert!(matches!(
+            ensure_manages(&outsider, red_cross.as_ref()),
+            Err(BackendError::OrganizationAccessDenied { .. })
+        ));
+
+        let admin = user("admin@example.org", Role::Admin);
+        assert!(ensure_manages(&admin, other.as_ref()).is_ok());
+        assert!(ensure_manages(&admin, None).is_ok());
+    }
+}
@@ -0,0 +1,115 @@
+use candid::CandidType;
+use serde::Deserialize;
+
+// Page size limits for the list queries
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Page<K, T> {
+    // In key order
+    pub items: Vec<T>,
+    // Pass back as `start_after` for the next page; empty on the last page
+    pub next_cursor: Option<K>,
+    // Number of records in the index or table the pages are read from, taken
+    // from its length; the filter may let fewer of them through
+    pub total: u64,
+}
+
+// Drop the keys up to and including `start_after`, so a page read through an
+// index only decodes the records it may return
+pub fn keys_after<K: Ord>(mut keys: Vec<K>, start_after: Option<&K>) -> Vec<K> {
+    if let Some(start) = start_after {
+        keys.retain(|key| key > start);
+    }
+    keys
+}
+
+// One page of the records that pass `matches`, taken from `records` (which
+// must be in key order) after the `start_after` key. Callers should seek
+// `records` past `start_after` already; reading stops once the page is full.
+// `total` is the length of the index or table behind `records`.
+pub fn paginate<K, V, T>(
+    records: impl Iterator<Item = (K, V)>,
+    total: u64,
+    start_after: Option<K>,
+    limit: Option<u32>,
+    matches: impl Fn(&V) -> bool,
+    view: impl Fn(V) -> T,
+) -> Page<K, T>
+where
+    K: Ord + Clone,
+{
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+    let mut items = Vec::new();
+    let mut last_key = None;
+    let mut has_more = false;
+    let records = records.skip_while(|(key, _)| start_after.as_ref().is_some_and(|start| key <= start));
+    for (key, value) in records {
+        if !matches(&value) {
+            continue;
+        }
+        if items.len() == limit {
+            has_more = true;
+            break;
+        }
+        items.push(view(value));
+        last_key = Some(key);
+    }
+    Page {
+        items,
+        next_cursor: if has_more { last_key } else { None },
+        total,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(start_after: Option<u64>, limit: u32) -> Page<u64, u64> {
+        let records = (1..=10u64).map(|key| (key, key * 10));
+        paginate(records, 10, start_after, Some(limit), |value| value % 20 == 0, |value| value)
+    }
+
+    #[test]
+    fn walks_matching_records_page_by_page() {
+        let first = page(None, 2);
+        assert_eq!(first.items, [20, 40]);
+        assert_eq!(first.next_cursor, Some(4));
+        assert_eq!(first.total, 10);
+
+        let second = page(first.next_cursor, 2);
+        assert_eq!(second.items, [60, 80]);
+        assert_eq!(second.next_cursor, Some(8));
+
+        let last = page(second.next_cursor, 2);
+        assert_eq!(last.items, [100]);
+        assert_eq!(last.next_cursor, None);
+        assert_eq!(last.total, 10);
+    }
+
+    #[test]
+    fn exact_fit_has_no_next_page() {
+        let all = page(None, 5);
+        assert_eq!(all.items.len(), 5);
+        assert_eq!(all.next_cursor, None);
+        assert_eq!(all.total, 10);
+    }
+
+    #[test]
+    fn stops_reading_once_the_page_is_full() {
+        let mut read = 0;
+        let records = (1..=100u64).inspect(|_| read += 1).map(|key| (key, key));
+        let page = paginate(records, 100, None, Some(3), |_| true, |value| value);
+        assert_eq!(page.items, [1, 2, 3]);
+        assert_eq!(page.next_cursor, Some(3));
+        assert_eq!(read, 4);
+    }
+
+    #[test]
+    fn keys_after_skips_up_to_the_cursor() {
+        assert_eq!(keys_after(vec![2, 4, 6, 8], Some(&4)), [6, 8]);
+        assert_eq!(keys_after(vec![2, 4], None), [2, 4]);
+    }
+}
@@ -0,0 +1,168 @@
+use candid::CandidType;
+use ic_cdk::api::management_canister::main::raw_rand;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::error::BackendError;
+
+// PBKDF2 rounds; kept moderate because every round is paid for in cycles and
+// the upgrade migration has to hash all stored passwords in one message
+const PBKDF2_ITERATIONS: u32 = 5_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct PasswordHash {
+    pub salt: Vec<u8>,
+    pub hash: Vec<u8>,
+    pub iterations: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub enum Credential {
+    // Only produced when decoding users stored before passwords were hashed;
+    // post_upgrade replaces these with salted hashes
+    Plaintext(String),
+    Hashed(PasswordHash),
+    // Hashed where no randomness was available, salted with derived_salt;
+    // the next successful login replaces it with a Hashed one
+    Provisional(PasswordHash),
+    // No password set; the account can only act through its bound principal
+    Unset,
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credential::Plaintext(_) => f.write_str("Plaintext(<redacted>)"),
+            Credential::Hashed(_) => f.write_str("Hashed(<redacted>)"),
+            Credential::Provisional(_) => f.write_str("Provisional(<redacted>)"),
+            Credential::Unset => f.write_str("Unset"),
+        }
+    }
+}
+
+impl Credential {
+    pub fn is_plaintext(&self) -> bool {
+        matches!(self, Credential::Plaintext(_))
+    }
+
+    // Constant-time check of a login attempt against the stored credential
+    pub fn verify(&self, password: &str) -> bool {
+        match self {
+            Credential::Plaintext(stored) => stored.as_bytes().ct_eq(password.as_bytes()).into(),
+            Credential::Hashed(stored) | Credential::Provisional(stored) => {
+                let attempt = derive(password, &stored.salt, stored.iterations);
+                attempt.ct_eq(&stored.hash).into()
+            }
+            Credential::Unset => false,
+        }
+    }
+
+    // The credential to store once `password` has been verified against this
+    // one, if it is provisional; `salt` should come from random_salt
+    pub fn rehashed(&self, password: &str, salt: Vec<u8>) -> Option<Credential> {
+        match self {
+            Credential::Provisional(_) => Some(hash_password(password, salt)),
+            _ => None,
+        }
+    }
+}
+
+pub fn hash_password(password: &str, salt: Vec<u8>) -> Credential {
+    let hash = derive(password, &salt, PBKDF2_ITERATIONS);
+    Credential::Hashed(PasswordHash {
+        salt,
+        hash,
+        iterations: PBKDF2_ITERATIONS,
+    })
+}
+
+fn derive(password: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut hash = vec![0u8; HASH_LEN];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut hash);
+    hash
+}
+
+// Fresh salt from the management canister's randomness
+pub async fn random_salt() -> Result<Vec<u8>, BackendError> {
+    let (bytes,) = raw_rand().await.map_err(|(code, msg)| {
+        BackendError::Internal(format!("failed to generate password salt: {:?} {}", code, msg))
+    })?;
+    Ok(bytes[..SALT_LEN].to_vec())
+}
+
+// Salt for contexts that can't make inter-canister calls (init, post_upgrade).
+// Time + email is unique per account but guessable, so hashes salted with it
+// are only provisional.
+fn derived_salt(email: &str, now: u64) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(now.to_be_bytes());
+    hasher.update(email.as_bytes());
+    hasher.finalize()[..SALT_LEN].to_vec()
+}
+
+pub fn provisional_hash(password: &str, email: &str, now: u64) -> Credential {
+    match hash_password(password, derived_salt(email, now)) {
+        Credential::Hashed(stored) => Credential::Provisional(stored),
+        other => other,
+    }
+}
+
+// Upgrades before provisional hashes existed stored them as Hashed, so
+// every hash from then is rehashed once
+pub fn mark_provisional(credential: Credential) -> Credential {
+    match credential {
+        Credential::Hashed(stored) => Credential::Provisional(stored),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_passwords_verify_only_the_original() {
+        let credential = hash_password("correct horse", vec![7; SALT_LEN]);
+        assert!(!credential.is_plaintext());
+        assert!(credential.verify("correct horse"));
+        assert!(!credential.verify("correct hors"));
+        assert!(!credential.verify(""));
+    }
+
+    #[test]
+    fn salts_change_the_hash() {
+        let hash = |salt: u8| match hash_password("secret", vec![salt; SALT_LEN]) {
+            Credential::Hashed(stored) => stored.hash,
+            other => panic!("expected a hash, got {:?}", other),
+        };
+        assert_ne!(hash(1), hash(2));
+        assert_eq!(hash(1).len(), HASH_LEN);
+    }
+
+    #[test]
+    fn unset_credentials_accept_nothing() {
+        assert!(!Credential::Unset.verify(""));
+        assert!(Credential::Plaintext("1234".to_string()).verify("1234"));
+        assert_eq!(format!("{:?}", Credential::Plaintext("1234".to_string())), "Plaintext(<redacted>)");
+    }
+
+    #[test]
+    fn provisional_hashes_get_a_fresh_salt_on_rehash() {
+        let salt = |credential: &Credential| match credential {
+            Credential::Hashed(stored) | Credential::Provisional(stored) => stored.salt.clone(),
+            other => panic!("expected a hash, got {:?}", other),
+        };
+        let provisional = provisional_hash("secret", "ann@example.org", 42);
+        assert!(provisional.verify("secret"));
+        assert_eq!(salt(&provisional), derived_salt("ann@example.org", 42));
+
+        let rehashed = provisional.rehashed("secret", vec![9; SALT_LEN]).unwrap();
+        assert!(matches!(rehashed, Credential::Hashed(_)));
+        assert!(rehashed.verify("secret"));
+        assert_ne!(salt(&rehashed), salt(&provisional));
+        assert!(rehashed.rehashed("secret", vec![8; SALT_LEN]).is_none());
+    }
+}
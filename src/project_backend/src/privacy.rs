@@ -0,0 +1,177 @@
+use crate::auth::Role;
+use crate::geo::{BoundingBox, GeoPoint};
+use crate::status::RequestStatus;
+use crate::tracking::Breadcrumb;
+use crate::{index, organization, profile, HelpRequest, User, VolunteerLocation, HELP_REQUESTS};
+
+// Callers without a stake in a record see its coordinates rounded to this
+// many degrees (about 1.1 km of latitude) and none of its address text.
+const REDUCED_PRECISION_DEGREES: f64 = 0.01;
+// A reduced point lies at most half a cell from the exact one along either
+// axis; a whole cell leaves room for rounding
+pub const REDUCED_OFFSET_DEGREES: f64 = REDUCED_PRECISION_DEGREES;
+// A reduced point lies at most half a cell's diagonal (about 0.8 km) from the
+// exact one; rounded up
+pub const REDUCED_OFFSET_KM: f64 = 1.0;
+// Narrower radius searches would let repeated queries close in on a position
+// between the reduced points
+const MIN_REDUCED_SEARCH_RADIUS_KM: f64 = 2.0;
+// Distances to volunteers are reported in whole kilometres, at least one
+const DISTANCE_STEP_KM: f64 = 1.0;
+
+pub fn reduce(point: GeoPoint) -> GeoPoint {
+    let round = |degrees: f64| (degrees / REDUCED_PRECISION_DEGREES).round() * REDUCED_PRECISION_DEGREES;
+    GeoPoint {
+        lat: round(point.lat),
+        lon: round(point.lon),
+    }
+}
+
+fn owns_organization(viewer: &User, organization_id: Option<&String>) -> bool {
+    viewer.role == Role::Organization && organization_id.is_some_and(|id| organization::is_member(&viewer.email, id))
+}
+
+// The victim, the assigned volunteer, the owning organization and admins
+// see where a request is exactly
+pub fn sees_exact_request(viewer: &User, request: &HelpRequest) -> bool {
+    viewer.role == Role::Admin
+        || viewer.email == request.victim_id
+        || request.assigned_volunteer.as_ref() == Some(&viewer.email)
+        || owns_organization(viewer, request.organization_id.as_ref())
+}
+
+// The request as the viewer may see it
+pub fn request_for(viewer: &User, mut request: HelpRequest) -> HelpRequest {
+    if !sees_exact_request(viewer, &request) {
+        request.coordinates = request.coordinates.map(reduce);
+        request.location = String::new();
+    }
+    request
+}
+
+// Only admins see every request exactly, so everyone else searches at least
+// a couple of reduction cells wide
+pub fn search_radius(viewer: &User, radius_km: f64) -> f64 {
+    if viewer.role == Role::Admin {
+        radius_km
+    } else {
+        radius_km.max(MIN_REDUCED_SEARCH_RADIUS_KM)
+    }
+}
+
+// Boxes narrower than a reduction cell would let repeated queries tell where
+// in the cell a volunteer is, so everyone but admins gets at least one
+pub fn search_box(viewer: &User, bbox: BoundingBox) -> BoundingBox {
+    if viewer.role == Role::Admin {
+        return bbox;
+    }
+    let grow = |span: f64| ((REDUCED_PRECISION_DEGREES - span) / 2.0).max(0.0);
+    bbox.grown(grow(bbox.north - bbox.south), grow(bbox.width()))
+}
+
+pub fn bucket_distance(distance_km: f64) -> f64 {
+    ((distance_km / DISTANCE_STEP_KM).ceil() * DISTANCE_STEP_KM).max(DISTANCE_STEP_KM)
+}
+
+// Volunteers who only share while on duty are hidden from everyone else
+// while they are off duty
+pub fn is_shared(volunteer: &str) -> bool {
+    let profile = profile::get_or_default(volunteer);
+    profile.on_duty || !profile.share_location_only_on_duty
+}
+
+// An organization sees the exact position of volunteers working on one of
+// its requests
+fn works_for(volunteer: &str, viewer: &User) -> bool {
+    if viewer.role != Role::Organization {
+        return false;
+    }
+    let request_ids = index::requests_of_volunteer(volunteer);
+    HELP_REQUESTS.with(|requests| {
+        requests.borrow().entries_of(request_ids).any(|(_, request)| {
+            matches!(
+                request.status,
+                RequestStatus::Assigned | RequestStatus::Accepted | RequestStatus::InProgress
+            ) && owns_organization(viewer, request.organization_id.as_ref())
+        })
+    })
+}
+
+enum Precision {
+    Hidden,
+    Reduced,
+    Exact,
+}
+
+fn volunteer_precision(viewer: &User, volunteer: &str) -> Precision {
+    if viewer.email == volunteer {
+        Precision::Exact
+    } else if !is_shared(volunteer) {
+        Precision::Hidden
+    } else if viewer.role == Role::Admin || works_for(volunteer, viewer) {
+        Precision::Exact
+    } else {
+        Precision::Reduced
+    }
+}
+
+// The volunteer's position as the viewer may see it, if at all
+pub fn location_for(viewer: &User, mut location: VolunteerLocation) -> Option<VolunteerLocation> {
+    match volunteer_precision(viewer, &location.email) {
+        Precision::Hidden => None,
+        Precision::Reduced => {
+            location.coordinates = location.coordinates.map(reduce);
+            location.address = String::new();
+            Some(location)
+        }
+        Precision::Exact => Some(location),
+    }
+}
+
+pub fn trail_for(viewer: &User, volunteer: &str, trail: Vec<Breadcrumb>) -> Vec<Breadcrumb> {
+    match volunteer_precision(viewer, volunteer) {
+        Precision::Hidden => Vec::new(),
+        Precision::Reduced => trail
+            .into_iter()
+            .map(|breadcrumb| Breadcrumb {
+                coordinates: reduce(breadcrumb.coordinates),
+                ..breadcrumb
+            })
+            .collect(),
+        Precision::Exact => trail,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reduced_points_snap_to_a_coarse_grid() {
+        let reduced = reduce(GeoPoint {
+            lat: 37.774_929,
+            lon: -122.419_416,
+        });
+        assert!((reduced.lat - 37.77).abs() < 1e-9);
+        assert!((reduced.lon + 122.42).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reduced_points_stay_within_the_offset() {
+        let exact = GeoPoint {
+            lat: 0.004_999,
+            lon: 0.004_999,
+        };
+        let reduced = reduce(exact);
+        let offset = crate::geo::calculate_distance(exact.lat, exact.lon, reduced.lat, reduced.lon);
+        assert!(offset > 0.7 && offset <= REDUCED_OFFSET_KM);
+    }
+
+    #[test]
+    fn distances_are_bucketed_to_whole_kilometres() {
+        assert_eq!(bucket_distance(0.0), 1.0);
+        assert_eq!(bucket_distance(0.2), 1.0);
+        assert_eq!(bucket_distance(1.0), 1.0);
+        assert_eq!(bucket_distance(7.3), 8.0);
+    }
+}
@@ -0,0 +1,324 @@
+use candid::CandidType;
+use ic_stable_structures::Storable;
+use serde::Deserialize;
+
+use crate::schema::{self, Versioned};
+use crate::{StableString, VOLUNTEER_PROFILES};
+
+// Tasks a volunteer may hold at once unless their profile says otherwise
+pub const DEFAULT_MAX_CONCURRENT_TASKS: u32 = 3;
+
+const NANOS_PER_MINUTE: u64 = 60_000_000_000;
+pub const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    // 1970-01-01 was a Thursday
+    fn of_day(days_since_epoch: u64) -> Weekday {
+        Weekday::ALL[((days_since_epoch + 3) % 7) as usize]
+    }
+}
+
+// A weekly slot in UTC, from `start_minute` up to `end_minute` after midnight
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct AvailabilityWindow {
+    pub day: Weekday,
+    pub start_minute: u32,
+    pub end_minute: u32,
+}
+
+impl AvailabilityWindow {
+    fn contains(&self, time: u64) -> bool {
+        let minutes = time / NANOS_PER_MINUTE;
+        let day = minutes / MINUTES_PER_DAY as u64;
+        let minute_of_day = (minutes % MINUTES_PER_DAY as u64) as u32;
+        Weekday::of_day(day) == self.day && (self.start_minute..self.end_minute).contains(&minute_of_day)
+    }
+}
+
+// What a volunteer declares about themselves, kept apart from the account
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct VolunteerProfile {
+    // Skills, certifications, equipment and languages are lowercase, without duplicates
+    pub skills: Vec<String>,
+    pub certifications: Vec<String>,
+    // Vehicles and gear, e.g. "boat" or "4x4"
+    pub equipment: Vec<String>,
+    pub languages: Vec<String>,
+    pub max_concurrent_tasks: u32,
+    // No windows means available at any time
+    pub availability: Vec<AvailabilityWindow>,
+    pub on_duty: bool,
+    // Hide their position from others while off duty
+    pub share_location_only_on_duty: bool,
+    pub updated_at: u64,
+}
+
+// Volunteers who never filled in a profile are matched as before profiles
+// existed: on duty, at any time, with the default capacity
+impl Default for VolunteerProfile {
+    fn default() -> Self {
+        VolunteerProfile {
+            skills: Vec::new(),
+            certifications: Vec::new(),
+            equipment: Vec::new(),
+            languages: Vec::new(),
+            max_concurrent_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+            availability: Vec::new(),
+            on_duty: true,
+            share_location_only_on_duty: false,
+            updated_at: 0,
+        }
+    }
+}
+
+impl VolunteerProfile {
+    // On duty and inside one of the availability windows
+    pub fn is_available(&self, time: u64) -> bool {
+        self.on_duty && (self.availability.is_empty() || self.availability.iter().any(|w| w.contains(time)))
+    }
+}
+
+impl Versioned for VolunteerProfile {
+    const VERSION: u8 = 3;
+
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        if version == 2 {
+            let old: VolunteerProfileV2 = schema::decode_or_trap(version, bytes);
+            return VolunteerProfile {
+                skills: old.skills,
+                certifications: old.certifications,
+                equipment: old.equipment,
+                languages: old.languages,
+                max_concurrent_tasks: old.max_concurrent_tasks,
+                availability: old.availability,
+                on_duty: old.on_duty,
+                share_location_only_on_duty: false,
+                updated_at: old.updated_at,
+            };
+        }
+        let old: VolunteerProfileV1 = schema::decode_or_trap(version, bytes);
+        VolunteerProfile {
+            skills: old.skills,
+            updated_at: old.updated_at,
+            ..VolunteerProfile::default()
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct VolunteerProfileV2 {
+    skills: Vec<String>,
+    certifications: Vec<String>,
+    equipment: Vec<String>,
+    languages: Vec<String>,
+    max_concurrent_tasks: u32,
+    availability: Vec<AvailabilityWindow>,
+    on_duty: bool,
+    updated_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VolunteerProfileV1 {
+    skills: Vec<String>,
+    updated_at: u64,
+}
+
+impl Storable for VolunteerProfile {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+// Replaces everything but the on-duty toggle, which set_on_duty flips
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct VolunteerProfileUpdate {
+    pub skills: Vec<String>,
+    pub certifications: Vec<String>,
+    pub equipment: Vec<String>,
+    pub languages: Vec<String>,
+    pub max_concurrent_tasks: u32,
+    pub availability: Vec<AvailabilityWindow>,
+    pub share_location_only_on_duty: bool,
+}
+
+// Every given field must hold; `available_now` checks on duty and availability
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct VolunteerFilter {
+    pub skill: Option<String>,
+    pub certification: Option<String>,
+    pub equipment: Option<String>,
+    pub language: Option<String>,
+    pub on_duty: Option<bool>,
+    pub available_now: Option<bool>,
+}
+
+fn lists(values: &[String], wanted: &Option<String>) -> bool {
+    wanted
+        .as_ref()
+        .is_none_or(|wanted| values.iter().any(|value| value.eq_ignore_ascii_case(wanted.trim())))
+}
+
+impl VolunteerFilter {
+    pub fn matches(&self, profile: &VolunteerProfile, now: u64) -> bool {
+        lists(&profile.skills, &self.skill)
+            && lists(&profile.certifications, &self.certification)
+            && lists(&profile.equipment, &self.equipment)
+            && lists(&profile.languages, &self.language)
+            && self.on_duty.is_none_or(|on_duty| profile.on_duty == on_duty)
+            && self
+                .available_now
+                .is_none_or(|available| profile.is_available(now) == available)
+    }
+}
+
+fn normalize(labels: Vec<String>) -> Vec<String> {
+    let mut labels: Vec<String> = labels
+        .into_iter()
+        .map(|label| label.trim().to_lowercase())
+        .filter(|label| !label.is_empty())
+        .collect();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+pub fn get(email: &str) -> Option<VolunteerProfile> {
+    VOLUNTEER_PROFILES.with(|profiles| profiles.borrow().get(&StableString::from(email.to_string())))
+}
+
+// The stored profile, or the defaults for volunteers who never saved one
+pub fn get_or_default(email: &str) -> VolunteerProfile {
+    get(email).unwrap_or_default()
+}
+
+fn put(email: &str, profile: VolunteerProfile) {
+    VOLUNTEER_PROFILES.with(|profiles| {
+        profiles
+            .borrow_mut()
+            .insert(StableString::from(email.to_string()), profile);
+    });
+}
+
+pub fn save(email: &str, update: VolunteerProfileUpdate) -> VolunteerProfile {
+    let mut availability = update.availability;
+    availability.sort_by_key(|w| (w.day as u8, w.start_minute));
+    let profile = VolunteerProfile {
+        skills: normalize(update.skills),
+        certifications: normalize(update.certifications),
+        equipment: normalize(update.equipment),
+        languages: normalize(update.languages),
+        max_concurrent_tasks: update.max_concurrent_tasks,
+        availability,
+        on_duty: get_or_default(email).on_duty,
+        share_location_only_on_duty: update.share_location_only_on_duty,
+        updated_at: ic_cdk::api::time(),
+    };
+    put(email, profile.clone());
+    profile
+}
+
+pub fn set_on_duty(email: &str, on_duty: bool) -> VolunteerProfile {
+    let profile = VolunteerProfile {
+        on_duty,
+        updated_at: ic_cdk::api::time(),
+        ..get_or_default(email)
+    };
+    put(email, profile.clone());
+    profile
+}
+
+pub fn remove(email: &str) {
+    VOLUNTEER_PROFILES.with(|profiles| {
+        profiles.borrow_mut().remove(&StableString::from(email.to_string()));
+    });
+}
+
+pub fn clear() {
+    VOLUNTEER_PROFILES.with(|profiles| {
+        let mut profiles = profiles.borrow_mut();
+        let keys: Vec<_> = profiles.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            profiles.remove(&key);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Monday 2024-01-15 10:30 UTC
+    const MONDAY_1030: u64 = 1_705_314_600 * 1_000_000_000;
+
+    fn window(day: Weekday, start_minute: u32, end_minute: u32) -> AvailabilityWindow {
+        AvailabilityWindow {
+            day,
+            start_minute,
+            end_minute,
+        }
+    }
+
+    #[test]
+    fn availability_follows_weekly_windows() {
+        let mut profile = VolunteerProfile::default();
+        assert!(profile.is_available(MONDAY_1030));
+
+        profile.availability = vec![window(Weekday::Monday, 9 * 60, 10 * 60 + 30)];
+        assert!(!profile.is_available(MONDAY_1030));
+        profile.availability.push(window(Weekday::Monday, 10 * 60 + 30, 11 * 60));
+        assert!(profile.is_available(MONDAY_1030));
+        assert!(!profile.is_available(MONDAY_1030 + 24 * 60 * NANOS_PER_MINUTE));
+
+        profile.on_duty = false;
+        assert!(!profile.is_available(MONDAY_1030));
+    }
+
+    #[test]
+    fn filters_on_declared_fields() {
+        let profile = VolunteerProfile {
+            equipment: vec!["boat".to_string()],
+            languages: vec!["spanish".to_string()],
+            ..VolunteerProfile::default()
+        };
+        let filter = |filter: VolunteerFilter| filter.matches(&profile, MONDAY_1030);
+        assert!(filter(VolunteerFilter {
+            equipment: Some("Boat".to_string()),
+            language: Some("spanish".to_string()),
+            available_now: Some(true),
+            ..VolunteerFilter::default()
+        }));
+        assert!(!filter(VolunteerFilter {
+            skill: Some("medic".to_string()),
+            ..VolunteerFilter::default()
+        }));
+        assert!(!filter(VolunteerFilter {
+            on_duty: Some(false),
+            ..VolunteerFilter::default()
+        }));
+    }
+}
@@ -0,0 +1,290 @@
+use candid::CandidType;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+
+use crate::chunked::{self, BoundedRecord, ChunkedMap};
+use crate::{
+    geo, index, legacy, organization, MemoryType, StableString, BOUNDED_DONATIONS_MEM_ID, BOUNDED_HELP_REQUESTS_MEM_ID,
+    BOUNDED_REQUEST_HISTORY_MEM_ID, BOUNDED_SESSIONS_MEM_ID, BOUNDED_SUPPLY_BUNDLES_MEM_ID,
+    BOUNDED_USERS_MEM_ID, BOUNDED_VOLUNTEER_LOCATIONS_MEM_ID, DONATIONS, HELP_REQUESTS,
+    MEMORY_MANAGER, REQUEST_HISTORY, SCHEMA_VERSION, SESSIONS, SUPPLY_BUNDLES, USERS,
+    VOLUNTEER_LOCATIONS,
+};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+
+// Version of the stable-memory layout as a whole. Canisters installed before
+// the version was recorded start at 1.
+pub const CURRENT_SCHEMA_VERSION: u32 = 13;
+pub const UNVERSIONED_SCHEMA: u32 = 1;
+
+// Migration steps; the step listed under version N takes stored data from
+// N to N + 1. Every step must be safe to run on data it has already migrated.
+const MIGRATIONS: &[(u32, &str, fn())] = &[
+    (1, "hash plaintext passwords", crate::migrate_plaintext_passwords),
+    (2, "key help requests by server-assigned id", crate::migrate_legacy_help_requests),
+    (3, "index help requests by location", geo::rebuild_index),
+    (4, "wrap stored records in versioned envelopes", rewrite_records),
+    (5, "move records out of size-bounded maps", move_to_chunked_maps),
+    (6, "store coordinates and timestamps as numbers", convert_text_fields),
+    (7, "build secondary indexes", index::rebuild),
+    (8, "create organizations for existing tenants", organization::create_tenants),
+    (9, "drop finished requests from the location index", geo::rebuild_index),
+    (10, "index unrouted requests", index::rebuild),
+    (11, "rehash upgrade-time password hashes at the next login", crate::mark_password_hashes_provisional),
+    (12, "index volunteer positions by location", geo::rebuild_volunteer_index),
+];
+
+// Stored records are enveloped as one version byte followed by the Candid
+// encoding. Records written before envelopes existed are bare Candid, which
+// always starts with this magic, so record versions must never equal b'D'.
+const CANDID_MAGIC: &[u8] = b"DIDL";
+pub const BARE_CANDID: u8 = 0;
+
+// A record type kept in stable memory under a versioned envelope
+pub trait Versioned: CandidType + DeserializeOwned {
+    // Bumped whenever the type's layout changes
+    const VERSION: u8;
+
+    // Decode a record written under an older layout of this type.
+    // `version` is BARE_CANDID for records stored before envelopes.
+    fn from_older(version: u8, bytes: &[u8]) -> Self {
+        decode_or_trap(version, bytes)
+    }
+}
+
+pub fn to_envelope<T: Versioned>(value: &T) -> Cow<'static, [u8]> {
+    let mut bytes = vec![T::VERSION];
+    bytes.extend(candid::encode_one(value).unwrap());
+    Cow::Owned(bytes)
+}
+
+pub fn from_envelope<T: Versioned>(bytes: &[u8]) -> T {
+    if bytes.starts_with(CANDID_MAGIC) {
+        return T::from_older(BARE_CANDID, bytes);
+    }
+    match bytes.split_first() {
+        Some((&version, payload)) if version == T::VERSION => decode_or_trap(version, payload),
+        Some((&version, payload)) if version < T::VERSION => T::from_older(version, payload),
+        Some((&version, _)) => ic_cdk::trap(&format!(
+            "stored record has version {} but this build only knows up to {}",
+            version,
+            T::VERSION
+        )),
+        None => ic_cdk::trap("stored record is empty"),
+    }
+}
+
+pub fn decode_or_trap<T: DeserializeOwned + CandidType>(version: u8, bytes: &[u8]) -> T {
+    candid::decode_one(bytes).unwrap_or_else(|e| {
+        ic_cdk::trap(&format!("failed to decode stored record (version {}): {}", version, e))
+    })
+}
+
+pub fn stored_version() -> u32 {
+    SCHEMA_VERSION.with(|version| *version.borrow().get())
+}
+
+fn set_version(new_version: u32) {
+    SCHEMA_VERSION.with(|version| {
+        version
+            .borrow_mut()
+            .set(new_version)
+            .expect("failed to record the schema version");
+    });
+}
+
+// Fresh installs start out with the current layout
+pub fn mark_current() {
+    set_version(CURRENT_SCHEMA_VERSION);
+}
+
+// Bring stored data up to CURRENT_SCHEMA_VERSION, one step at a time.
+// The version is recorded after every step so a failed upgrade can resume.
+pub fn run_migrations() {
+    let mut version = stored_version();
+    if version > CURRENT_SCHEMA_VERSION {
+        ic_cdk::trap(&format!(
+            "stable memory has schema version {} but this build only supports up to {}; refusing to downgrade",
+            version, CURRENT_SCHEMA_VERSION
+        ));
+    }
+    while version < CURRENT_SCHEMA_VERSION {
+        let Some((_, description, step)) = MIGRATIONS.iter().find(|(from, _, _)| *from == version) else {
+            ic_cdk::trap(&format!("no migration from schema version {}", version));
+        };
+        ic_cdk::println!("Migrating schema v{} to v{}: {}", version, version + 1, description);
+        step();
+        version += 1;
+        set_version(version);
+    }
+    ic_cdk::println!("Schema is at version {}", version);
+}
+
+// Re-insert every record so it is stored in the current envelope.
+// The audit log is append-only and keeps its older entries as they are.
+fn rewrite_records() {
+    USERS.with(|users| rewrite(&mut users.borrow_mut()));
+    HELP_REQUESTS.with(|requests| rewrite(&mut requests.borrow_mut()));
+    SUPPLY_BUNDLES.with(|bundles| rewrite(&mut bundles.borrow_mut()));
+    DONATIONS.with(|donations| rewrite(&mut donations.borrow_mut()));
+    VOLUNTEER_LOCATIONS.with(|locations| rewrite(&mut locations.borrow_mut()));
+    SESSIONS.with(|sessions| rewrite(&mut sessions.borrow_mut()));
+    REQUEST_HISTORY.with(|history| rewrite(&mut history.borrow_mut()));
+}
+
+// Decode and re-insert every record of a map, which upgrades older layouts
+fn rewrite<K, V>(map: &mut ChunkedMap<K, V>)
+where
+    K: BoundedStorable + Ord + Clone + Default,
+    V: Storable,
+{
+    let entries: Vec<_> = map.iter().collect();
+    for (key, value) in entries {
+        map.insert(key, value);
+    }
+}
+
+// Open a map from before chunked storage with the value bound it was created with
+fn bounded_map<K, const MAX_SIZE: u32>(
+    memory_id: MemoryId,
+) -> StableBTreeMap<K, BoundedRecord<MAX_SIZE>, MemoryType>
+where
+    K: BoundedStorable + Ord + Clone,
+{
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(memory_id)))
+}
+
+// Records used to live in maps with a fixed size bound per value, which made
+// long descriptions or item lists impossible to store. Each map's records are
+// moved into its chunked replacement and the bounded map is emptied.
+fn move_to_chunked_maps() {
+    let mut moved = 0;
+    USERS.with(|users| {
+        let mut from = bounded_map::<StableString, 512>(BOUNDED_USERS_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut users.borrow_mut());
+    });
+    HELP_REQUESTS.with(|requests| {
+        let mut from = bounded_map::<u64, 1024>(BOUNDED_HELP_REQUESTS_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut requests.borrow_mut());
+    });
+    SUPPLY_BUNDLES.with(|bundles| {
+        let mut from = bounded_map::<StableString, 1024>(BOUNDED_SUPPLY_BUNDLES_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut bundles.borrow_mut());
+    });
+    DONATIONS.with(|donations| {
+        let mut from = bounded_map::<StableString, 1024>(BOUNDED_DONATIONS_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut donations.borrow_mut());
+    });
+    VOLUNTEER_LOCATIONS.with(|locations| {
+        let mut from = bounded_map::<StableString, 512>(BOUNDED_VOLUNTEER_LOCATIONS_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut locations.borrow_mut());
+    });
+    SESSIONS.with(|sessions| {
+        let mut from = bounded_map::<StableString, 256>(BOUNDED_SESSIONS_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut sessions.borrow_mut());
+    });
+    REQUEST_HISTORY.with(|history| {
+        let mut from = bounded_map::<(u64, u64), 1024>(BOUNDED_REQUEST_HISTORY_MEM_ID);
+        moved += chunked::move_records(&mut from, &mut history.borrow_mut());
+    });
+    ic_cdk::println!("Moved {} records into chunked storage", moved);
+
+    // On canisters older than schema v2 the password step ran before the users
+    // were moved, so it saw none of them
+    crate::migrate_plaintext_passwords();
+}
+
+// Coordinates and timestamps were stored as text. Records are converted as
+// they are decoded; fields that don't parse are logged and left empty (points)
+// or zero (timestamps). The location index is rebuilt from the parsed points.
+fn convert_text_fields() {
+    HELP_REQUESTS.with(|requests| rewrite(&mut requests.borrow_mut()));
+    VOLUNTEER_LOCATIONS.with(|locations| rewrite(&mut locations.borrow_mut()));
+    SUPPLY_BUNDLES.with(|bundles| rewrite(&mut bundles.borrow_mut()));
+    DONATIONS.with(|donations| rewrite(&mut donations.borrow_mut()));
+    geo::rebuild_index();
+    ic_cdk::println!(
+        "{} stored fields could not be parsed; see the messages above",
+        legacy::unparseable_fields()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(CandidType, Deserialize, Debug, PartialEq)]
+    struct Sample {
+        name: String,
+        count: u32,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct SampleV1 {
+        name: String,
+    }
+
+    impl Versioned for Sample {
+        const VERSION: u8 = 2;
+
+        fn from_older(version: u8, bytes: &[u8]) -> Self {
+            let old: SampleV1 = decode_or_trap(version, bytes);
+            Sample {
+                name: old.name,
+                count: version as u32,
+            }
+        }
+    }
+
+    fn enveloped(version: u8, value: impl CandidType) -> Vec<u8> {
+        let mut bytes = vec![version];
+        bytes.extend(candid::encode_one(value).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn current_records_round_trip() {
+        let sample = Sample {
+            name: "ann".to_string(),
+            count: 7,
+        };
+        let bytes = to_envelope(&sample);
+        assert_eq!(bytes[0], Sample::VERSION);
+        assert_eq!(from_envelope::<Sample>(&bytes), sample);
+    }
+
+    #[test]
+    fn bare_and_older_records_go_through_from_older() {
+        let old = SampleV1 { name: "ann".to_string() };
+        let bare = candid::encode_one(&old).unwrap();
+        assert!(bare.starts_with(CANDID_MAGIC));
+        assert_eq!(from_envelope::<Sample>(&bare).count, BARE_CANDID as u32);
+        assert_eq!(from_envelope::<Sample>(&enveloped(1, &old)).count, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "trap")]
+    fn records_from_a_newer_build_are_refused() {
+        let sample = Sample {
+            name: "ann".to_string(),
+            count: 7,
+        };
+        from_envelope::<Sample>(&enveloped(Sample::VERSION + 1, &sample));
+    }
+
+    #[test]
+    #[should_panic(expected = "trap")]
+    fn stable_memory_from_a_newer_build_is_refused() {
+        set_version(CURRENT_SCHEMA_VERSION + 1);
+        run_migrations();
+    }
+
+    #[test]
+    fn every_version_has_a_migration() {
+        for version in UNVERSIONED_SCHEMA..CURRENT_SCHEMA_VERSION {
+            assert!(MIGRATIONS.iter().any(|(from, _, _)| *from == version), "no step from {}", version);
+        }
+    }
+}
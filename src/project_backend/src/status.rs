@@ -0,0 +1,162 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BackendError;
+use crate::{history, HelpRequest};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestStatus {
+    Pending,
+    Verified,
+    Assigned,
+    // The assigned volunteer has taken the task on
+    Accepted,
+    InProgress,
+    Completed,
+    Cancelled,
+    Rejected,
+}
+
+impl RequestStatus {
+    // Map the free-form status strings stored before statuses were typed.
+    // Anything unrecognised goes back to Pending so a coordinator looks at it again.
+    pub fn from_legacy(status: &str) -> RequestStatus {
+        match status.to_lowercase().replace([' ', '-'], "_").as_str() {
+            "pending" => RequestStatus::Pending,
+            "verified" => RequestStatus::Verified,
+            "assigned" => RequestStatus::Assigned,
+            "accepted" => RequestStatus::Accepted,
+            "in_progress" | "inprogress" => RequestStatus::InProgress,
+            "completed" => RequestStatus::Completed,
+            "cancelled" | "canceled" => RequestStatus::Cancelled,
+            "rejected" => RequestStatus::Rejected,
+            other => {
+                ic_cdk::println!("Unknown request status {:?}, treating it as pending", other);
+                RequestStatus::Pending
+            }
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestStatus::Pending => "pending",
+            RequestStatus::Verified => "verified",
+            RequestStatus::Assigned => "assigned",
+            RequestStatus::Accepted => "accepted",
+            RequestStatus::InProgress => "in_progress",
+            RequestStatus::Completed => "completed",
+            RequestStatus::Cancelled => "cancelled",
+            RequestStatus::Rejected => "rejected",
+        }
+    }
+
+    // The transition table: the statuses a request may move to from this one.
+    // Completed, Cancelled and Rejected are final.
+    pub fn allowed_next(self) -> &'static [RequestStatus] {
+        use RequestStatus::*;
+        match self {
+            Pending => &[Verified, Assigned, Cancelled, Rejected],
+            Verified => &[Assigned, Cancelled, Rejected],
+            // Assigned -> Assigned hands the request to another volunteer,
+            // Assigned -> Pending or Verified releases it back to the queue
+            Assigned => &[Assigned, Accepted, Pending, Verified, InProgress, Completed, Cancelled],
+            Accepted => &[Assigned, Pending, Verified, InProgress, Completed, Cancelled],
+            InProgress => &[Completed, Cancelled],
+            Completed | Cancelled | Rejected => &[],
+        }
+    }
+
+    pub fn can_transition_to(self, next: RequestStatus) -> bool {
+        self.allowed_next().contains(&next)
+    }
+}
+
+// The single place a request's status changes; every mutator goes through here,
+// and each accepted change is appended to the request's history
+pub fn transition(
+    request: &mut HelpRequest,
+    next: RequestStatus,
+    actor: &str,
+    note: Option<String>,
+) -> Result<(), BackendError> {
+    if !request.status.can_transition_to(next) {
+        ic_cdk::println!(
+            "Refusing to move request {} from {} to {}",
+            request.request_id,
+            request.status.as_str(),
+            next.as_str()
+        );
+        return Err(BackendError::invalid_state(request.status.as_str(), next.as_str()));
+    }
+    ic_cdk::println!(
+        "Request {} moved from {} to {}",
+        request.request_id,
+        request.status.as_str(),
+        next.as_str()
+    );
+    history::record(
+        request.request_id,
+        request.organization_id.clone(),
+        Some(request.status),
+        next,
+        actor,
+        note,
+    );
+    request.status = next;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RequestStatus::*;
+
+    const ALL: [RequestStatus; 8] = [Pending, Verified, Assigned, Accepted, InProgress, Completed, Cancelled, Rejected];
+
+    #[test]
+    fn transition_table() {
+        let cases = [
+            (Pending, Verified, true),
+            (Pending, Assigned, true),
+            (Pending, Rejected, true),
+            (Pending, Accepted, false),
+            (Pending, InProgress, false),
+            (Pending, Completed, false),
+            (Verified, Assigned, true),
+            (Verified, Pending, false),
+            (Verified, Verified, false),
+            (Assigned, Assigned, true),
+            (Assigned, Accepted, true),
+            (Assigned, Pending, true),
+            (Assigned, Verified, true),
+            (Assigned, Rejected, false),
+            (Accepted, InProgress, true),
+            (Accepted, Accepted, false),
+            (InProgress, Completed, true),
+            (InProgress, Cancelled, true),
+            (InProgress, Pending, false),
+            (InProgress, Assigned, false),
+        ];
+        for (from, to, allowed) in cases {
+            assert_eq!(from.can_transition_to(to), allowed, "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn final_statuses_reject_every_transition() {
+        for from in [Completed, Cancelled, Rejected] {
+            for to in ALL {
+                assert!(!from.can_transition_to(to), "{:?} -> {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn statuses_survive_their_legacy_text() {
+        for status in ALL {
+            assert_eq!(RequestStatus::from_legacy(status.as_str()), status);
+        }
+        assert_eq!(RequestStatus::from_legacy("In Progress"), InProgress);
+        assert_eq!(RequestStatus::from_legacy("canceled"), Cancelled);
+    }
+}
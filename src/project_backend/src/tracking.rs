@@ -0,0 +1,303 @@
+use candid::CandidType;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::error::BackendError;
+use crate::geo::{self, BoundingBox, GeoPoint};
+use crate::privacy;
+use crate::schema::{self, Versioned};
+use crate::{StableString, VolunteerLocation, LOCATION_TRAILS, TRACKING_CONFIG, VOLUNTEER_LOCATIONS};
+
+pub const STALENESS_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+const NANOS_PER_MINUTE: u64 = 60_000_000_000;
+
+// Whether a volunteer's position is current. Stale volunteers stopped
+// reporting and are treated as offline until their next update.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Presence {
+    Active,
+    Stale,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TrackingConfig {
+    // Positions older than this mark the volunteer as stale
+    pub stale_after_minutes: u64,
+    // Breadcrumbs kept per volunteer; older ones are dropped
+    pub trail_length: u32,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        TrackingConfig {
+            stale_after_minutes: 15,
+            trail_length: 100,
+        }
+    }
+}
+
+impl Versioned for TrackingConfig {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for TrackingConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+// One reported position of a volunteer
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Breadcrumb {
+    pub coordinates: GeoPoint,
+    pub recorded_at: u64,
+}
+
+impl Versioned for Breadcrumb {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for Breadcrumb {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        schema::to_envelope(self)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        schema::from_envelope(&bytes)
+    }
+}
+
+pub fn config() -> TrackingConfig {
+    TRACKING_CONFIG.with(|config| config.borrow().get().clone())
+}
+
+pub fn set_config(new_config: TrackingConfig) -> Result<(), BackendError> {
+    TRACKING_CONFIG.with(|config| {
+        config.borrow_mut().set(new_config).map(|_| ()).map_err(|e| {
+            BackendError::Internal(format!("failed to store the tracking config: {:?}", e))
+        })
+    })
+}
+
+fn trail_keys(email: &str) -> std::ops::RangeInclusive<(StableString, u64)> {
+    let email = StableString::from(email.to_string());
+    (email.clone(), 0)..=(email, u64::MAX)
+}
+
+// Store the volunteer's current position and add it to their trail.
+// Volunteers who only share while on duty aren't tracked while off duty, so
+// going on duty doesn't reveal where they were before.
+pub fn record(email: &str, coordinates: GeoPoint, address: String) {
+    record_at(email, coordinates, address, ic_cdk::api::time());
+}
+
+fn record_at(email: &str, coordinates: GeoPoint, address: String, now: u64) {
+    if !privacy::is_shared(email) {
+        return;
+    }
+    VOLUNTEER_LOCATIONS.with(|locations| {
+        let location = VolunteerLocation {
+            email: email.to_string(),
+            coordinates: Some(coordinates),
+            address,
+            last_updated: now,
+            presence: Presence::Active,
+        };
+        locations.borrow_mut().insert(StableString::from(email.to_string()), location);
+    });
+
+    let trail_length = config().trail_length as usize;
+    LOCATION_TRAILS.with(|trails| {
+        let mut trails = trails.borrow_mut();
+        trails.insert(
+            (StableString::from(email.to_string()), now),
+            Breadcrumb {
+                coordinates,
+                recorded_at: now,
+            },
+        );
+        let keys: Vec<_> = trails.range(trail_keys(email)).map(|(key, _)| key).collect();
+        for key in keys.iter().take(keys.len().saturating_sub(trail_length)) {
+            trails.remove(key);
+        }
+    });
+}
+
+// The volunteer's trail, oldest first, optionally only from `since` on
+pub fn trail(email: &str, since: Option<u64>) -> Vec<Breadcrumb> {
+    let since = since.unwrap_or(0);
+    LOCATION_TRAILS.with(|trails| {
+        trails
+            .borrow()
+            .range(trail_keys(email))
+            .map(|(_, breadcrumb)| breadcrumb)
+            .filter(|breadcrumb| breadcrumb.recorded_at >= since)
+            .collect()
+    })
+}
+
+// Forget where the volunteer is and has been
+pub fn remove_volunteer(email: &str) {
+    VOLUNTEER_LOCATIONS.with(|locations| {
+        locations.borrow_mut().remove(&StableString::from(email.to_string()));
+    });
+    remove_trail(email);
+}
+
+fn remove_trail(email: &str) {
+    LOCATION_TRAILS.with(|trails| {
+        let mut trails = trails.borrow_mut();
+        let keys: Vec<_> = trails.range(trail_keys(email)).map(|(key, _)| key).collect();
+        for key in keys {
+            trails.remove(&key);
+        }
+    });
+}
+
+pub fn clear_trails() {
+    LOCATION_TRAILS.with(|trails| {
+        let mut trails = trails.borrow_mut();
+        let keys: Vec<_> = trails.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            trails.remove(&key);
+        }
+    });
+}
+
+// Current positions inside the box, reported within the last `max_age_minutes`.
+// Only the volunteers the location grid has in the box are read.
+pub fn locations_within(bbox: &BoundingBox, max_age_minutes: Option<u64>) -> Vec<VolunteerLocation> {
+    let now = ic_cdk::api::time();
+    let emails = geo::volunteers_within(bbox).into_iter().map(StableString::from).collect();
+    VOLUNTEER_LOCATIONS.with(|locations| {
+        locations
+            .borrow()
+            .entries_of(emails)
+            .map(|(_, location)| location)
+            .filter(|location| {
+                let age_minutes = now.saturating_sub(location.last_updated) / NANOS_PER_MINUTE;
+                max_age_minutes.is_none_or(|max_age| age_minutes <= max_age)
+            })
+            .collect()
+    })
+}
+
+// Timer job: mark volunteers who stopped reporting as stale
+pub fn mark_stale_locations() {
+    let marked = mark_stale_at(ic_cdk::api::time());
+    if marked > 0 {
+        ic_cdk::println!("Marked {} volunteer locations as stale", marked);
+    }
+}
+
+fn mark_stale_at(now: u64) -> usize {
+    let cutoff = now.saturating_sub(config().stale_after_minutes * NANOS_PER_MINUTE);
+    VOLUNTEER_LOCATIONS.with(|locations| {
+        let mut locations = locations.borrow_mut();
+        let stale: Vec<(StableString, VolunteerLocation)> = locations
+            .iter()
+            .filter(|(_, location)| location.presence == Presence::Active && location.last_updated < cutoff)
+            .collect();
+        for (email, mut location) in stale.iter().cloned() {
+            location.presence = Presence::Stale;
+            locations.insert(email, location);
+        }
+        stale.len()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::VolunteerProfile;
+    use crate::VOLUNTEER_PROFILES;
+
+    const MINUTE: u64 = NANOS_PER_MINUTE;
+
+    fn point(lat: f64) -> GeoPoint {
+        GeoPoint { lat, lon: 0.0 }
+    }
+
+    fn presence(email: &str) -> Presence {
+        VOLUNTEER_LOCATIONS.with(|locations| {
+            locations
+                .borrow()
+                .get(&StableString::from(email.to_string()))
+                .unwrap()
+                .presence
+        })
+    }
+
+    #[test]
+    fn trails_keep_the_latest_breadcrumbs() {
+        set_config(TrackingConfig {
+            stale_after_minutes: 15,
+            trail_length: 3,
+        })
+        .unwrap();
+        for i in 1..=5 {
+            record_at("bob@example.org", point(i as f64), String::new(), i * MINUTE);
+        }
+        record_at("cat@example.org", point(9.0), String::new(), MINUTE);
+
+        let lats: Vec<f64> = trail("bob@example.org", None).iter().map(|b| b.coordinates.lat).collect();
+        assert_eq!(lats, [3.0, 4.0, 5.0]);
+        assert_eq!(trail("bob@example.org", Some(4 * MINUTE)).len(), 2);
+        assert_eq!(trail("cat@example.org", None).len(), 1);
+
+        remove_volunteer("bob@example.org");
+        assert!(trail("bob@example.org", None).is_empty());
+        assert_eq!(trail("cat@example.org", None).len(), 1);
+    }
+
+    #[test]
+    fn silent_volunteers_turn_stale_until_they_report() {
+        record_at("bob@example.org", point(1.0), String::new(), 100 * MINUTE);
+        record_at("cat@example.org", point(1.0), String::new(), 110 * MINUTE);
+
+        assert_eq!(mark_stale_at(120 * MINUTE), 1);
+        assert_eq!(presence("bob@example.org"), Presence::Stale);
+        assert_eq!(presence("cat@example.org"), Presence::Active);
+        // Already stale volunteers aren't marked again
+        assert_eq!(mark_stale_at(120 * MINUTE), 0);
+
+        record_at("bob@example.org", point(2.0), String::new(), 121 * MINUTE);
+        assert_eq!(presence("bob@example.org"), Presence::Active);
+    }
+
+    #[test]
+    fn off_duty_moves_stay_unrecorded_for_on_duty_only_sharers() {
+        let set_on_duty = |on_duty: bool| {
+            let profile = VolunteerProfile {
+                on_duty,
+                share_location_only_on_duty: true,
+                ..VolunteerProfile::default()
+            };
+            VOLUNTEER_PROFILES.with(|profiles| {
+                profiles
+                    .borrow_mut()
+                    .insert(StableString::from("bob@example.org".to_string()), profile)
+            });
+        };
+
+        set_on_duty(false);
+        for i in 1..=3 {
+            record_at("bob@example.org", point(i as f64), String::new(), i * MINUTE);
+        }
+        set_on_duty(true);
+        assert!(trail("bob@example.org", None).is_empty());
+        assert!(VOLUNTEER_LOCATIONS
+            .with(|locations| locations.borrow().get(&StableString::from("bob@example.org".to_string())))
+            .is_none());
+
+        record_at("bob@example.org", point(4.0), String::new(), 4 * MINUTE);
+        let lats: Vec<f64> = trail("bob@example.org", None).iter().map(|b| b.coordinates.lat).collect();
+        assert_eq!(lats, [4.0]);
+    }
+}
@@ -0,0 +1,493 @@
+use crate::error::{BackendError, FieldError};
+use crate::geo::{BoundingBox, GeoPoint};
+use crate::incident::{IncidentUpdate, NewIncident};
+use crate::matching::MatchingConfig;
+use crate::organization::{AreaShape, NewOrganization, OrganizationUpdate};
+use crate::profile::{VolunteerProfileUpdate, MINUTES_PER_DAY};
+use crate::tracking::TrackingConfig;
+use crate::{Donation, MAX_NEARBY_RADIUS_KM, NewHelpRequest, SupplyBundle, UserRegistration, UserUpdate};
+
+// Record keys (emails, bundle and donation ids) end up in map keys with a
+// fixed bound, so they can never be longer than this
+const MAX_KEY_LEN: usize = 256;
+
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_NAME_LEN: usize = 100;
+const MAX_PASSWORD_LEN: usize = 128;
+const MAX_PHONE_LEN: usize = 32;
+const MAX_ADDRESS_LEN: usize = 300;
+const MAX_DESCRIPTION_LEN: usize = 2_000;
+const MAX_NOTE_LEN: usize = 1_000;
+const MAX_LABEL_LEN: usize = 50;
+const MAX_ITEMS: usize = 100;
+const MAX_SKILLS: usize = 30;
+const MAX_CONCURRENT_TASKS: u32 = 20;
+const MAX_AVAILABILITY_WINDOWS: usize = 28;
+const MAX_TRAIL_LENGTH: u32 = 1_000;
+const MAX_SERVICE_AREAS: usize = 50;
+const MAX_POLYGON_VERTICES: usize = 500;
+
+// Phone numbers may carry punctuation, but the digits must make up a
+// plausible number (E.164 allows at most 15)
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+pub const URGENCIES: &[&str] = &["low", "medium", "high", "critical"];
+pub const REQUEST_TYPES: &[&str] = &["food", "medical", "shelter", "evacuation", "supplies", "other"];
+
+// Input types checked before an endpoint touches storage
+pub trait Validate {
+    fn check(&self, violations: &mut Violations);
+
+    // Every rule the value breaks, reported together
+    fn validate(&self) -> Result<(), BackendError> {
+        let mut violations = Violations::default();
+        self.check(&mut violations);
+        violations.into_result()
+    }
+}
+
+// Rule violations collected over one input
+#[derive(Default)]
+pub struct Violations(Vec<FieldError>);
+
+impl Violations {
+    pub fn add(&mut self, field: &str, reason: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn into_result(self) -> Result<(), BackendError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(BackendError::InvalidInput(self.0))
+        }
+    }
+
+    pub fn text(&mut self, field: &str, value: &str, required: bool, max_len: usize) {
+        if required && value.trim().is_empty() {
+            self.add(field, "must not be empty");
+        } else if value.len() > max_len {
+            self.add(field, format!("must be at most {} bytes long", max_len));
+        }
+    }
+
+    // A short list of short labels, like a volunteer's skills
+    pub fn labels(&mut self, field: &str, values: &[String]) {
+        if values.len() > MAX_SKILLS {
+            self.add(field, format!("must list at most {} entries", MAX_SKILLS));
+        }
+        for (i, value) in values.iter().enumerate() {
+            self.text(&format!("{}[{}]", field, i), value, true, MAX_LABEL_LEN);
+        }
+    }
+
+    pub fn email(&mut self, field: &str, value: &str) {
+        if value.len() > MAX_EMAIL_LEN {
+            self.add(field, format!("must be at most {} bytes long", MAX_EMAIL_LEN));
+        } else if !is_email(value) {
+            self.add(field, "is not a valid email address");
+        }
+    }
+
+    // Phone numbers are optional, but must look like one when given
+    pub fn phone(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            return;
+        }
+        if value.len() > MAX_PHONE_LEN {
+            self.add(field, format!("must be at most {} bytes long", MAX_PHONE_LEN));
+            return;
+        }
+        let allowed = value
+            .trim_start_matches('+')
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '.' | '(' | ')'));
+        let digits = value.chars().filter(char::is_ascii_digit).count();
+        if !allowed || !(MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits) {
+            self.add(
+                field,
+                format!(
+                    "must be {} to {} digits, optionally with a leading + and spaces, dashes, dots or parentheses",
+                    MIN_PHONE_DIGITS, MAX_PHONE_DIGITS
+                ),
+            );
+        }
+    }
+
+    pub fn point(&mut self, field: &str, point: &GeoPoint) {
+        if !(-90.0..=90.0).contains(&point.lat) {
+            self.add(&format!("{}.lat", field), "must be between -90 and 90");
+        }
+        if !(-180.0..=180.0).contains(&point.lon) {
+            self.add(&format!("{}.lon", field), "must be between -180 and 180");
+        }
+    }
+
+    pub fn area(&mut self, field: &str, shape: &AreaShape) {
+        match shape {
+            AreaShape::Circle { center, radius_km } => {
+                self.point(&format!("{}.center", field), center);
+                if !(*radius_km > 0.0 && *radius_km <= MAX_NEARBY_RADIUS_KM) {
+                    self.add(
+                        &format!("{}.radius_km", field),
+                        format!("must be greater than 0 and at most {}", MAX_NEARBY_RADIUS_KM),
+                    );
+                }
+            }
+            AreaShape::Polygon { vertices } => {
+                if !(3..=MAX_POLYGON_VERTICES).contains(&vertices.len()) {
+                    self.add(
+                        &format!("{}.vertices", field),
+                        format!("must list between 3 and {} vertices", MAX_POLYGON_VERTICES),
+                    );
+                }
+                for (j, vertex) in vertices.iter().enumerate() {
+                    self.point(&format!("{}.vertices[{}]", field, j), vertex);
+                }
+            }
+        }
+    }
+
+    // At least one shape; the area is their union
+    pub fn areas(&mut self, field: &str, shapes: &[AreaShape]) {
+        if shapes.is_empty() || shapes.len() > MAX_SERVICE_AREAS {
+            self.add(field, format!("must list between 1 and {} shapes", MAX_SERVICE_AREAS));
+        }
+        for (i, shape) in shapes.iter().enumerate() {
+            self.area(&format!("{}[{}]", field, i), shape);
+        }
+    }
+
+    // Compared without regard to case; stored values are normalized to lowercase
+    pub fn one_of(&mut self, field: &str, value: &str, allowed: &[&str]) {
+        if !allowed.iter().any(|a| a.eq_ignore_ascii_case(value)) {
+            self.add(field, format!("must be one of: {}", allowed.join(", ")));
+        }
+    }
+
+    pub fn positive_amount(&mut self, field: &str, value: f64) {
+        if !(value.is_finite() && value > 0.0) {
+            self.add(field, "must be a finite number greater than 0");
+        }
+    }
+}
+
+fn is_email(value: &str) -> bool {
+    if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-'))
+}
+
+impl Validate for UserRegistration {
+    fn check(&self, v: &mut Violations) {
+        v.email("email", &self.email);
+        v.text("password", &self.password, true, MAX_PASSWORD_LEN);
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.phone("phone", &self.phone);
+        v.text("address", &self.address, false, MAX_ADDRESS_LEN);
+    }
+}
+
+impl Validate for UserUpdate {
+    fn check(&self, v: &mut Violations) {
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.phone("phone", &self.phone);
+        v.text("address", &self.address, false, MAX_ADDRESS_LEN);
+        if let Some(password) = &self.password {
+            v.text("password", password, true, MAX_PASSWORD_LEN);
+        }
+    }
+}
+
+impl Validate for NewHelpRequest {
+    fn check(&self, v: &mut Violations) {
+        v.one_of("request_type", &self.request_type, REQUEST_TYPES);
+        v.one_of("urgency", &self.urgency, URGENCIES);
+        v.text("description", &self.description, true, MAX_DESCRIPTION_LEN);
+        v.text("location", &self.location, false, MAX_ADDRESS_LEN);
+        v.point("coordinates", &self.coordinates);
+        if let Some(organization_id) = &self.organization_id {
+            v.text("organization_id", organization_id, true, MAX_KEY_LEN);
+        }
+        if let Some(incident_id) = &self.incident_id {
+            v.text("incident_id", incident_id, true, MAX_KEY_LEN);
+        }
+    }
+}
+
+impl Validate for SupplyBundle {
+    fn check(&self, v: &mut Violations) {
+        v.text("id", &self.id, true, MAX_KEY_LEN);
+        v.text("organization_id", &self.organization_id, true, MAX_KEY_LEN);
+        if let Some(incident_id) = &self.incident_id {
+            v.text("incident_id", incident_id, true, MAX_KEY_LEN);
+        }
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.text("description", &self.description, false, MAX_DESCRIPTION_LEN);
+        v.text("status", &self.status, false, MAX_LABEL_LEN);
+        if self.items.is_empty() || self.items.len() > MAX_ITEMS {
+            v.add("items", format!("must list between 1 and {} items", MAX_ITEMS));
+        }
+        for (i, item) in self.items.iter().enumerate() {
+            v.text(&format!("items[{}].name", i), &item.name, true, MAX_NAME_LEN);
+            v.text(&format!("items[{}].unit", i), &item.unit, false, MAX_LABEL_LEN);
+            if item.quantity == 0 {
+                v.add(&format!("items[{}].quantity", i), "must be greater than 0");
+            }
+        }
+    }
+}
+
+impl Validate for Donation {
+    fn check(&self, v: &mut Violations) {
+        v.text("id", &self.id, true, MAX_KEY_LEN);
+        v.text("organization_id", &self.organization_id, true, MAX_KEY_LEN);
+        if let Some(incident_id) = &self.incident_id {
+            v.text("incident_id", incident_id, true, MAX_KEY_LEN);
+        }
+        v.positive_amount("amount", self.amount);
+        v.text("donor_name", &self.donor_name, true, MAX_NAME_LEN);
+        if self.distribution_details.len() > MAX_ITEMS {
+            v.add(
+                "distribution_details",
+                format!("must list at most {} entries", MAX_ITEMS),
+            );
+        }
+        for (i, detail) in self.distribution_details.iter().enumerate() {
+            v.positive_amount(&format!("distribution_details[{}].amount", i), detail.amount);
+            v.text(
+                &format!("distribution_details[{}].purpose", i),
+                &detail.purpose,
+                true,
+                MAX_DESCRIPTION_LEN,
+            );
+        }
+    }
+}
+
+impl Validate for VolunteerProfileUpdate {
+    fn check(&self, v: &mut Violations) {
+        v.labels("skills", &self.skills);
+        v.labels("certifications", &self.certifications);
+        v.labels("equipment", &self.equipment);
+        v.labels("languages", &self.languages);
+        if !(1..=MAX_CONCURRENT_TASKS).contains(&self.max_concurrent_tasks) {
+            v.add(
+                "max_concurrent_tasks",
+                format!("must be between 1 and {}", MAX_CONCURRENT_TASKS),
+            );
+        }
+        if self.availability.len() > MAX_AVAILABILITY_WINDOWS {
+            v.add(
+                "availability",
+                format!("must list at most {} windows", MAX_AVAILABILITY_WINDOWS),
+            );
+        }
+        for (i, window) in self.availability.iter().enumerate() {
+            if window.start_minute >= window.end_minute || window.end_minute > MINUTES_PER_DAY {
+                v.add(
+                    &format!("availability[{}]", i),
+                    format!("must start before it ends, within 0..={} minutes", MINUTES_PER_DAY),
+                );
+            }
+        }
+    }
+}
+
+impl Validate for MatchingConfig {
+    fn check(&self, v: &mut Violations) {
+        if !(self.max_distance_km > 0.0 && self.max_distance_km <= MAX_NEARBY_RADIUS_KM) {
+            v.add(
+                "max_distance_km",
+                format!("must be greater than 0 and at most {}", MAX_NEARBY_RADIUS_KM),
+            );
+        }
+        if self.max_location_age_minutes == 0 {
+            v.add("max_location_age_minutes", "must be greater than 0");
+        }
+    }
+}
+
+impl Validate for NewOrganization {
+    fn check(&self, v: &mut Violations) {
+        v.text("id", &self.id, true, MAX_KEY_LEN);
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.text("description", &self.description, false, MAX_DESCRIPTION_LEN);
+        if self.admins.len() > MAX_ITEMS {
+            v.add("admins", format!("must list at most {} entries", MAX_ITEMS));
+        }
+        for (i, admin) in self.admins.iter().enumerate() {
+            v.email(&format!("admins[{}]", i), admin);
+        }
+    }
+}
+
+impl Validate for OrganizationUpdate {
+    fn check(&self, v: &mut Violations) {
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.text("description", &self.description, false, MAX_DESCRIPTION_LEN);
+        if self.service_areas.len() > MAX_SERVICE_AREAS {
+            v.add(
+                "service_areas",
+                format!("must list at most {} areas", MAX_SERVICE_AREAS),
+            );
+        }
+        for (i, area) in self.service_areas.iter().enumerate() {
+            let field = format!("service_areas[{}]", i);
+            v.text(&format!("{}.name", field), &area.name, true, MAX_NAME_LEN);
+            v.area(&field, &area.shape);
+        }
+    }
+}
+
+impl Validate for NewIncident {
+    fn check(&self, v: &mut Violations) {
+        v.text("id", &self.id, true, MAX_KEY_LEN);
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.areas("affected_area", &self.affected_area);
+    }
+}
+
+impl Validate for IncidentUpdate {
+    fn check(&self, v: &mut Violations) {
+        v.text("name", &self.name, true, MAX_NAME_LEN);
+        v.areas("affected_area", &self.affected_area);
+    }
+}
+
+impl Validate for TrackingConfig {
+    fn check(&self, v: &mut Violations) {
+        if !(1..=MINUTES_PER_DAY as u64).contains(&self.stale_after_minutes) {
+            v.add(
+                "stale_after_minutes",
+                format!("must be between 1 and {}", MINUTES_PER_DAY),
+            );
+        }
+        if !(1..=MAX_TRAIL_LENGTH).contains(&self.trail_length) {
+            v.add("trail_length", format!("must be between 1 and {}", MAX_TRAIL_LENGTH));
+        }
+    }
+}
+
+// update_volunteer_location takes its fields as separate arguments
+pub fn volunteer_location(coordinates: &GeoPoint, address: &str) -> Result<(), BackendError> {
+    let mut v = Violations::default();
+    v.point("coordinates", coordinates);
+    v.text("address", address, false, MAX_ADDRESS_LEN);
+    v.into_result()
+}
+
+pub fn note(field: &str, note: &str, required: bool) -> Result<(), BackendError> {
+    let mut v = Violations::default();
+    v.text(field, note, required, MAX_NOTE_LEN);
+    v.into_result()
+}
+
+pub fn bounding_box(field: &str, bbox: &BoundingBox) -> Result<(), BackendError> {
+    let mut v = Violations::default();
+    v.point(&format!("{}.south_west", field), &GeoPoint { lat: bbox.south, lon: bbox.west });
+    v.point(&format!("{}.north_east", field), &GeoPoint { lat: bbox.north, lon: bbox.east });
+    if bbox.south > bbox.north {
+        v.add(field, "the south edge must not lie north of the north edge");
+    }
+    v.into_result()
+}
+
+pub fn point(field: &str, point: &GeoPoint) -> Result<(), BackendError> {
+    let mut v = Violations::default();
+    v.point(field, point);
+    v.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(result: Result<(), BackendError>) -> Vec<String> {
+        match result {
+            Ok(()) => vec![],
+            Err(BackendError::InvalidInput(errors)) => errors.into_iter().map(|e| e.field).collect(),
+            Err(other) => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn emails_and_phones() {
+        for good in ["a@b.co", "first.last+tag@sub.example.org"] {
+            assert!(is_email(good), "{}", good);
+        }
+        for bad in ["", "a", "a@b", "@b.co", "a@@b.co", "a@b..co", "a b@c.co", "a@-b.co"] {
+            assert!(!is_email(bad), "{}", bad);
+        }
+
+        let mut v = Violations::default();
+        v.phone("ok", "");
+        v.phone("ok", "+1 (555) 123-4567");
+        v.phone("short", "12345");
+        v.phone("letters", "555-CALL-NOW");
+        v.phone("plus", "1+5551234567");
+        assert_eq!(fields(v.into_result()), ["short", "letters", "plus"]);
+    }
+
+    #[test]
+    fn reports_every_violation_of_a_help_request() {
+        let request = NewHelpRequest {
+            request_type: "Medical".to_string(),
+            description: " ".to_string(),
+            urgency: "whenever".to_string(),
+            location: String::new(),
+            coordinates: GeoPoint { lat: 91.0, lon: f64::NAN },
+            organization_id: None,
+            incident_id: None,
+        };
+        assert_eq!(
+            fields(request.validate()),
+            ["urgency", "description", "coordinates.lat", "coordinates.lon"]
+        );
+    }
+
+    #[test]
+    fn incidents_need_an_affected_area() {
+        let mut incident = NewIncident {
+            id: "flood-2024".to_string(),
+            name: "River flood".to_string(),
+            incident_type: crate::incident::IncidentType::Flood,
+            affected_area: Vec::new(),
+            start_time: None,
+        };
+        assert_eq!(fields(incident.validate()), ["affected_area"]);
+
+        incident.affected_area.push(AreaShape::Circle {
+            center: GeoPoint { lat: 10.0, lon: 200.0 },
+            radius_km: 0.0,
+        });
+        assert_eq!(
+            fields(incident.validate()),
+            ["affected_area[0].center.lon", "affected_area[0].radius_km"]
+        );
+    }
+
+    #[test]
+    fn amounts_must_be_positive_and_finite() {
+        let mut v = Violations::default();
+        v.positive_amount("ok", 0.01);
+        v.positive_amount("zero", 0.0);
+        v.positive_amount("negative", -5.0);
+        v.positive_amount("nan", f64::NAN);
+        v.positive_amount("infinite", f64::INFINITY);
+        assert_eq!(fields(v.into_result()), ["zero", "negative", "nan", "infinite"]);
+    }
+}
@@ -1,6 +1,5 @@
-import React, { useState, useEffect } from 'react';
+import React, { useState } from 'react';
 import { BrowserRouter as Router, Routes, Route, Navigate } from 'react-router-dom';
-import { project_backend } from '../../declarations/project_backend';
 import { MapContainer, TileLayer, Marker, Popup } from 'react-leaflet';
 import 'leaflet/dist/leaflet.css';
 import './App.css';
@@ -18,9 +17,9 @@ import DonorDashboard from './pages/donor/DonorDashboard';
 
 function App() {
   const [isAuthenticated, setIsAuthenticated] = useState(false);
-  const [isAdmin, setIsAdmin] = useState(false);
   const [currentUser, setCurrentUser] = useState(null);
-  const [isLoading, setIsLoading] = useState(true);
+  // The role comes from the account the backend returned at login
+  const isAdmin = currentUser?.is_admin ?? false;
 
   // UserDashboard component to handle routing based on user type
   const UserDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) => {
@@ -49,9 +48,6 @@ function App() {
 
   // Protected Route component
   const ProtectedRoute = ({ children }) => {
-    if (isLoading) {
-      return <div>Loading...</div>;
-    }
     if (!isAuthenticated) {
       return <Navigate to="/login" />;
     }
@@ -62,9 +58,6 @@ function App() {
   const AdminRoute = ({ children }) => {
     console.log('AdminRoute - isAuthenticated:', isAuthenticated, 'isAdmin:', isAdmin);
     
-    if (isLoading) {
-      return <div>Loading...</div>;
-    }
     if (!isAuthenticated || !isAdmin) {
       console.log('Redirecting to login from AdminRoute');
       return <Navigate to="/login" />;
@@ -74,39 +67,12 @@ function App() {
 
   // Organization Route component
   const OrganizationRoute = ({ children }) => {
-    if (isLoading) {
-      return <div>Loading...</div>;
-    }
     if (!isAuthenticated || !currentUser || currentUser.user_type.toLowerCase() !== 'organization') {
       return <Navigate to="/organization/login" />;
     }
     return children;
   };
 
-  const checkAdminStatus = async (email) => {
-    try {
-      console.log('Checking admin status for:', email);
-      setIsLoading(true);
-      const adminStatus = await project_backend.is_admin(email);
-      console.log('Admin status result:', adminStatus);
-      setIsAdmin(adminStatus);
-    } catch (err) {
-      console.error('Error checking admin status:', err);
-      setIsAdmin(false);
-    } finally {
-      setIsLoading(false);
-    }
-  };
-
-  // Check admin status when currentUser changes
-  useEffect(() => {
-    if (currentUser?.email) {
-      checkAdminStatus(currentUser.email);
-    } else {
-      setIsLoading(false);
-    }
-  }, [currentUser]);
-
   return (
     <Router>
       <Routes>
@@ -117,13 +83,16 @@ function App() {
             <Login 
               setIsAuthenticated={setIsAuthenticated} 
               setCurrentUser={setCurrentUser}
-              checkAdminStatus={checkAdminStatus}
-              isAdmin={isAdmin}
             />
           } 
         />
         <Route path="/register" element={<Register />} />
-        <Route path="/admin/login" element={<AdminLogin />} />
+        <Route path="/admin/login" element={
+          <AdminLogin
+            setIsAuthenticated={setIsAuthenticated}
+            setCurrentUser={setCurrentUser}
+          />
+        } />
         <Route path="/organization/login" element={
           <OrganizationLogin 
             setIsAuthenticated={setIsAuthenticated}
@@ -0,0 +1,152 @@
+import { AuthClient } from '@dfinity/auth-client';
+import { createActor, canisterId } from '../../declarations/project_backend';
+
+// The backend resolves every account from the caller's principal and rejects
+// the anonymous one, so calls go through an Internet Identity session
+const identityProvider = process.env.DFX_NETWORK === 'ic'
+  ? 'https://identity.ic0.app'
+  : `http://${process.env.CANISTER_ID_INTERNET_IDENTITY}.localhost:4943`;
+
+// Methods that don't take the session token as their first argument
+const PUBLIC_METHODS = ['register_user', 'login', 'organization_login', 'admin_login', 'logout', 'export_candid'];
+
+let authClient = null;
+let actor = null;
+let sessionToken = null;
+
+// Sign in with Internet Identity (if not already) and return the backend actor
+export const connect = async () => {
+  authClient = authClient || await AuthClient.create();
+  if (!(await authClient.isAuthenticated())) {
+    await new Promise((resolve, reject) => {
+      authClient.login({ identityProvider, onSuccess: resolve, onError: reject });
+    });
+  }
+  actor = createActor(canisterId, {
+    agentOptions: { identity: authClient.getIdentity() }
+  });
+  return actor;
+};
+
+// Keep the token of the Session a login endpoint returned
+export const startSession = (session) => {
+  sessionToken = session.token;
+  return session;
+};
+
+// The actor of the signed-in session. Calls that need the session token get
+// it passed in front of their own arguments.
+export const backend = () => {
+  if (!actor || !sessionToken) {
+    throw new Error('Not signed in');
+  }
+  return new Proxy(actor, {
+    get: (target, method) => {
+      const value = target[method];
+      if (typeof value !== 'function' || PUBLIC_METHODS.includes(method)) {
+        return value;
+      }
+      return (...args) => value(sessionToken, ...args);
+    }
+  });
+};
+
+// End the backend session and the Internet Identity one
+export const disconnect = async () => {
+  try {
+    if (actor) {
+      await actor.logout();
+    }
+  } catch (err) {
+    console.warn('Backend logout failed:', err);
+  }
+  if (authClient) {
+    await authClient.logout();
+  }
+  actor = null;
+  sessionToken = null;
+};
+
+// A readable message for a BackendError variant
+export const describeError = (error) => {
+  const [kind, detail] = Object.entries(error)[0];
+  switch (kind) {
+    case 'InvalidInput':
+      return detail.map(({ field, reason }) => `${field}: ${reason}`).join('; ');
+    case 'NotFound':
+      return `Not found: ${detail}`;
+    case 'Conflict':
+    case 'Internal':
+      return detail;
+    case 'InvalidState':
+      return `Cannot move from ${detail.from} to ${detail.to}`;
+    case 'Unauthorized':
+      return `Your account (${variantName(detail.role)}) is not allowed to do this`;
+    case 'OrganizationAccessDenied':
+      return `You are not a member of organization ${detail.organization_id}`;
+    case 'Unauthenticated':
+      return 'Please log in again';
+    case 'SessionExpired':
+      return 'Your session has expired; please log in again';
+    case 'StorageFull':
+      return 'The service is out of storage';
+    default:
+      return kind;
+  }
+};
+
+// The Ok value of a Result, or an Error carrying the Err case
+export const unwrap = (result) => {
+  if ('Err' in result) {
+    throw new Error(describeError(result.Err));
+  }
+  return result.Ok;
+};
+
+// Candid `opt` values arrive as [] or [value]
+export const fromOpt = (value) => (value.length > 0 ? value[0] : null);
+export const toOpt = (value) => (value === null || value === undefined || value === '' ? [] : [value]);
+
+export const variantName = (value) => Object.keys(value)[0];
+
+// 'victim' <-> { Victim: null }
+export const roleName = (role) => variantName(role).toLowerCase();
+export const toRole = (name) => ({ [name.charAt(0).toUpperCase() + name.slice(1)]: null });
+
+// Lower-case status name, as used in the CSS classes
+export const statusName = (status) => variantName(status).toLowerCase();
+
+// Backend timestamps are nanoseconds
+export const toDate = (nanoseconds) => new Date(Number(BigInt(nanoseconds) / 1000000n));
+export const nowNanos = () => BigInt(Date.now()) * 1000000n;
+
+// Every item of a paginated list query; `query(startAfter)` fetches one page
+export const fetchAllPages = async (query) => {
+  const items = [];
+  let cursor = [];
+  do {
+    const page = unwrap(await query(cursor));
+    items.push(...page.items);
+    cursor = page.next_cursor;
+  } while (cursor.length > 0);
+  return items;
+};
+
+// The shape the dashboards keep in currentUser
+export const toCurrentUser = (profile) => ({
+  email: profile.email,
+  name: profile.name,
+  user_type: roleName(profile.role),
+  phone: profile.phone,
+  address: profile.address,
+  is_admin: 'Admin' in profile.role
+});
+
+// Load the signed-in account's profile
+export const loadCurrentUser = async (email) => {
+  const profile = fromOpt(unwrap(await backend().get_user(email)));
+  if (!profile) {
+    throw new Error('Account not found');
+  }
+  return toCurrentUser(profile);
+};
@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { project_backend } from '../../../../declarations/project_backend';
+import { backend, disconnect, unwrap, fetchAllPages, toCurrentUser } from '../../api';
 import { useNavigate } from 'react-router-dom';
 import Header from '../../components/Header';
 import './AdminDashboard.css';
@@ -23,6 +23,7 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
   }, []);
 
   const handleLogout = () => {
+    disconnect();
     setIsAuthenticated(false);
     setCurrentUser(null);
     navigate('/login');
@@ -33,23 +34,18 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setLoading(true);
       setError(null);
       console.log('Fetching users...');
-      const allUsers = await project_backend.get_all_users();
-      console.log('Raw users data:', allUsers);
-      
-      if (!Array.isArray(allUsers)) {
-        console.error('Received non-array data:', allUsers);
-        setError('Invalid data format received from server');
-        return;
-      }
-      
-      if (allUsers.length === 0) {
+      const profiles = await fetchAllPages(
+        (startAfter) => backend().get_all_users({ role: [] }, startAfter, [])
+      );
+
+      if (profiles.length === 0) {
         console.log('No users found in the database');
       }
       
-      setUsers(allUsers);
+      setUsers(profiles.map(toCurrentUser));
     } catch (err) {
       console.error('Error loading users:', err);
-      setError('Failed to load users. Please try again.');
+      setError('Failed to load users: ' + err.message);
     } finally {
       setLoading(false);
     }
@@ -59,18 +55,12 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
     if (window.confirm('Are you sure you want to delete this user?')) {
       try {
         console.log('Attempting to delete user:', email);
-        const success = await project_backend.delete_user(email);
-        console.log('Delete result:', success);
-        
-        if (success) {
-          setUsers(users.filter(user => user.email !== email));
-          console.log('User deleted successfully');
-        } else {
-          setError('Failed to delete user');
-        }
+        unwrap(await backend().delete_user(email));
+        setUsers(users.filter(user => user.email !== email));
+        console.log('User deleted successfully');
       } catch (err) {
         console.error('Error deleting user:', err);
-        setError('Failed to delete user. Please try again.');
+        setError('Failed to delete user: ' + err.message);
       }
     }
   };
@@ -85,15 +75,10 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setError('');
       setClearSuccess('');
 
-      const success = await project_backend.clear_database();
-      
-      if (success) {
-        setClearSuccess('Database cleared successfully');
-        // Reload the users list
-        loadUsers();
-      } else {
-        setError('Failed to clear database');
-      }
+      unwrap(await backend().clear_database());
+      setClearSuccess('Database cleared successfully');
+      // Reload the users list
+      loadUsers();
     } catch (err) {
       console.error('Error clearing database:', err);
       setError('Failed to clear database: ' + err.message);
@@ -112,13 +97,8 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setError('');
       setClearSuccess('');
 
-      const success = await project_backend.clear_help_requests();
-      
-      if (success) {
-        setClearSuccess('Help requests cleared successfully');
-      } else {
-        setError('Failed to clear help requests');
-      }
+      unwrap(await backend().clear_help_requests());
+      setClearSuccess('Help requests cleared successfully');
     } catch (err) {
       console.error('Error clearing help requests:', err);
       setError('Failed to clear help requests: ' + err.message);
@@ -137,13 +117,8 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setError('');
       setClearSuccess('');
 
-      const success = await project_backend.clear_volunteer_locations();
-      
-      if (success) {
-        setClearSuccess('Volunteer locations cleared successfully');
-      } else {
-        setError('Failed to clear volunteer locations');
-      }
+      unwrap(await backend().clear_volunteer_locations());
+      setClearSuccess('Volunteer locations cleared successfully');
     } catch (err) {
       console.error('Error clearing volunteer locations:', err);
       setError('Failed to clear volunteer locations: ' + err.message);
@@ -162,13 +137,8 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setError('');
       setClearSuccess('');
 
-      const success = await project_backend.clear_supply_bundles();
-      
-      if (success) {
-        setClearSuccess('Supply bundles cleared successfully');
-      } else {
-        setError('Failed to clear supply bundles');
-      }
+      unwrap(await backend().clear_supply_bundles());
+      setClearSuccess('Supply bundles cleared successfully');
     } catch (err) {
       console.error('Error clearing supply bundles:', err);
       setError('Failed to clear supply bundles: ' + err.message);
@@ -187,13 +157,8 @@ const AdminDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setError('');
       setClearSuccess('');
 
-      const success = await project_backend.clear_donations();
-      
-      if (success) {
-        setClearSuccess('Donations cleared successfully');
-      } else {
-        setError('Failed to clear donations');
-      }
+      unwrap(await backend().clear_donations());
+      setClearSuccess('Donations cleared successfully');
     } catch (err) {
       console.error('Error clearing donations:', err);
       setError('Failed to clear donations: ' + err.message);
@@ -1,9 +1,9 @@
 import React, { useState } from 'react';
 import { useNavigate } from 'react-router-dom';
-import { project_backend } from '../../../../declarations/project_backend';
+import { connect, startSession, unwrap, loadCurrentUser } from '../../api';
 import './Auth.css';
 
-const AdminLogin = () => {
+const AdminLogin = ({ setIsAuthenticated, setCurrentUser }) => {
   const [email, setEmail] = useState('');
   const [password, setPassword] = useState('');
   const [error, setError] = useState('');
@@ -14,21 +14,16 @@ const AdminLogin = () => {
     setError('');
 
     try {
-      const isAdmin = await project_backend.is_admin(email);
-      if (!isAdmin) {
-        setError('This account does not have admin privileges');
-        return;
-      }
-
-      const isValid = await project_backend.verify_password(email, password);
-      if (isValid) {
-        navigate('/admin');
-      } else {
-        setError('Invalid credentials');
-      }
+      // The seeded admin account has no password and is only accepted from
+      // the principal it was installed with
+      const backend = await connect();
+      startSession(unwrap(await backend.admin_login(email, password)));
+      setCurrentUser(await loadCurrentUser(email));
+      setIsAuthenticated(true);
+      navigate('/admin');
     } catch (err) {
       console.error('Login error:', err);
-      setError('An error occurred during login');
+      setError('Admin login failed: ' + err.message);
     }
   };
 
@@ -55,7 +50,6 @@ const AdminLogin = () => {
               id="password"
               value={password}
               onChange={(e) => setPassword(e.target.value)}
-              required
             />
           </div>
           <button type="submit" className="auth-button">
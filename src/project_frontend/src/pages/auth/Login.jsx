@@ -1,14 +1,13 @@
 import React, { useState, useEffect } from 'react';
 import { Link, useNavigate } from 'react-router-dom';
-import { project_backend } from '../../../../declarations/project_backend';
+import { connect, startSession, unwrap, loadCurrentUser } from '../../api';
 import './Auth.css';
 
-const Login = ({ setIsAuthenticated, setCurrentUser, checkAdminStatus }) => {
+const Login = ({ setIsAuthenticated, setCurrentUser }) => {
   const navigate = useNavigate();
   const [formData, setFormData] = useState({
     email: '',
-    password: '',
-    userType: 'victim'
+    password: ''
   });
   const [error, setError] = useState('');
   const [success, setSuccess] = useState('');
@@ -32,55 +31,20 @@ const Login = ({ setIsAuthenticated, setCurrentUser, checkAdminStatus }) => {
     setIsLoading(true);
     
     try {
-      console.log('Attempting login for email:', formData.email, 'with user type:', formData.userType);
-      // Get user data from the backend
-      const user = await project_backend.get_user(formData.email);
-      console.log('Raw user response:', JSON.stringify(user, null, 2));
-      console.log('User type:', typeof user);
-      
-      if (!user) {
-        console.error('User not found');
-        setError('Invalid email or password. Please try again.');
-        setIsLoading(false);
-        return;
-      }
+      console.log('Attempting login for email:', formData.email);
+      const backend = await connect();
+      startSession(unwrap(await backend.login(formData.email, formData.password)));
+      const user = await loadCurrentUser(formData.email);
+      console.log('Logged in as:', user.email, user.user_type);
 
-      // Verify password first
-      const isPasswordValid = await project_backend.verify_password(formData.email, formData.password);
-      console.log('Password verification result:', isPasswordValid);
-      
-      if (!isPasswordValid) {
-        console.error('Invalid password');
-        setError('Invalid email or password. Please try again.');
-        setIsLoading(false);
-        return;
-      }
-
-      // Set the current user and authentication status
-      setCurrentUser({
-        email: formData.email,
-        name: user.name || 'User',
-        user_type: user.user_type || formData.userType.toLowerCase(), // Use backend user type if available, fallback to form
-        phone: user.phone || '',
-        address: user.address || '',
-        is_admin: user.is_admin || false
-      });
+      setCurrentUser(user);
       setIsAuthenticated(true);
-      
-      // Check if user is admin
-      console.log('Checking admin status...');
-      const adminStatus = await project_backend.is_admin(formData.email);
-      console.log('Admin status:', adminStatus);
-      
-      // Update admin status in the app
-      await checkAdminStatus(formData.email);
-      
       setSuccess('Login successful! Redirecting...');
 
       // Add a small delay to show the success message
       setTimeout(() => {
         // Redirect based on user type
-        if (adminStatus) {
+        if (user.is_admin) {
           console.log('Redirecting to admin dashboard');
           navigate('/admin', { replace: true });
         } else {
@@ -90,86 +54,15 @@ const Login = ({ setIsAuthenticated, setCurrentUser, checkAdminStatus }) => {
       }, 1500);
     } catch (err) {
       console.error('Login error:', err);
-      console.error('Error details:', {
-        name: err.name,
-        message: err.message,
-        stack: err.stack
-      });
-      setError('Failed to login. Please try again. Error: ' + err.message);
+      setError('Failed to login: ' + err.message);
     } finally {
       setIsLoading(false);
     }
   };
 
-  const handleAdminClick = async (e) => {
+  const handleAdminClick = (e) => {
     e.preventDefault();
-    setError('');
-    setSuccess('');
-    setIsLoading(true);
-    
-    try {
-      console.log('Attempting admin login...');
-      const adminEmail = "admin@disasterrelief.com";
-      const adminPassword = "admin123";
-      
-      // Verify admin credentials
-      const isPasswordValid = await project_backend.verify_password(adminEmail, adminPassword);
-      if (!isPasswordValid) {
-        console.error('Admin authentication failed');
-        setError('Failed to authenticate as admin. Please try again.');
-        setIsLoading(false);
-        return;
-      }
-
-      // Get user data from the backend
-      console.log('Fetching admin user data...');
-      const user = await project_backend.get_user(adminEmail);
-      
-      if (!user) {
-        console.error('Admin user not found');
-        setError('Admin account not found. Please contact support.');
-        setIsLoading(false);
-        return;
-      }
-
-      // Check admin status first
-      console.log('Verifying admin status...');
-      const adminStatus = await project_backend.is_admin(adminEmail);
-      
-      if (!adminStatus) {
-        console.error('Admin status verification failed');
-        setError('Failed to verify admin status. Please try again.');
-        setIsLoading(false);
-        return;
-      }
-
-      setSuccess('Admin login successful! Redirecting to dashboard...');
-
-      // Set authentication state
-      setCurrentUser({
-        email: adminEmail,
-        name: user.name || 'Admin User',
-        user_type: user.user_type || 'admin',
-        phone: user.phone || '',
-        address: user.address || '',
-        is_admin: true
-      });
-      setIsAuthenticated(true);
-      
-      // Update admin status in parent component
-      await checkAdminStatus(adminEmail);
-      
-      // Add a small delay to show the success message
-      setTimeout(() => {
-        console.log('Admin verified, redirecting to dashboard');
-        navigate('/admin', { replace: true });
-      }, 1500);
-    } catch (err) {
-      console.error('Admin login error:', err);
-      setError('Failed to login as admin. Please try again.');
-    } finally {
-      setIsLoading(false);
-    }
+    navigate('/admin/login');
   };
 
   return (
@@ -182,23 +75,6 @@ const Login = ({ setIsAuthenticated, setCurrentUser, checkAdminStatus }) => {
         {success && <div className="auth-success">{success}</div>}
         
         <form onSubmit={handleSubmit} className="auth-form">
-          <div className="form-group">
-            <label htmlFor="userType">I am a:</label>
-            <select
-              id="userType"
-              name="userType"
-              value={formData.userType}
-              onChange={handleChange}
-              className="form-control"
-              disabled={isLoading}
-            >
-              <option value="victim">Victim</option>
-              <option value="volunteer">Volunteer</option>
-              <option value="organization">Organization</option>
-              <option value="donor">Donor</option>
-            </select>
-          </div>
-
           <div className="form-group">
             <label htmlFor="email">Email</label>
             <input
@@ -1,5 +1,5 @@
 import React, { useState } from 'react';
-import { project_backend } from '../../../../declarations/project_backend';
+import { connect, startSession, unwrap, loadCurrentUser } from '../../api';
 import { useNavigate } from 'react-router-dom';
 import './Auth.css';
 
@@ -27,38 +27,18 @@ const OrganizationLogin = ({ setIsAuthenticated, setCurrentUser }) => {
 
     try {
       console.log('Attempting organization login with:', formData.email);
-      const success = await project_backend.organization_login(formData.email, formData.password);
-      console.log('Organization login result:', success);
-      
-      if (success) {
-        console.log('Login successful, getting user details');
-        // Get the user details
-        const user = await project_backend.get_user(formData.email);
-        console.log('User details:', user);
-        
-        if (user) {
-          if (user.user_type.toLowerCase() !== 'organization') {
-            console.error('User is not an organization:', user.user_type);
-            setError('Invalid user type. Please use organization login.');
-            return;
-          }
-          
-          console.log('Setting authentication state and current user');
-          setIsAuthenticated(true);
-          setCurrentUser(user);
-          console.log('Navigating to organization dashboard');
-          navigate('/organization/dashboard');
-        } else {
-          console.error('Failed to get user details');
-          setError('Failed to get user details');
-        }
-      } else {
-        console.error('Login failed: Invalid credentials');
-        setError('Invalid email or password');
-      }
+      const backend = await connect();
+      startSession(unwrap(await backend.organization_login(formData.email, formData.password)));
+      const user = await loadCurrentUser(formData.email);
+
+      console.log('Setting authentication state and current user');
+      setIsAuthenticated(true);
+      setCurrentUser(user);
+      console.log('Navigating to organization dashboard');
+      navigate('/organization/dashboard');
     } catch (err) {
       console.error('Login error:', err);
-      setError('Failed to login. Please try again.');
+      setError('Failed to login: ' + err.message);
     } finally {
       setLoading(false);
     }
@@ -1,6 +1,6 @@
 import React, { useState } from 'react';
 import { Link, useNavigate } from 'react-router-dom';
-import { project_backend } from "../../../../declarations/project_backend";
+import { connect, unwrap, toRole } from "../../api";
 
 import './Auth.css';
 
@@ -42,29 +42,21 @@ const Register = () => {
         confirmPassword: '[REDACTED]'
       });
 
-      // Create user object
-      const user = {
+      const registration = {
         email: formData.email,
         password: formData.password,
         name: formData.name,
-        user_type: formData.userType.toLowerCase(), // Ensure consistent casing
+        role: toRole(formData.userType),
         phone: formData.phone,
-        address: formData.address,
-        is_admin: false
+        address: formData.address
       };
 
-      console.log('Registering user with type:', user.user_type);
-      
-      // Register user
-      const success = await project_backend.register_user(user);
-      console.log('Registration result:', success);
-      
-      if (success) {
-        // Registration successful, redirect to login
-        navigate('/login');
-      } else {
-        setError('Registration failed. Email might already be registered.');
-      }
+      console.log('Registering user with role:', formData.userType);
+
+      // Register user; the account is bound to this Internet Identity
+      const backend = await connect();
+      unwrap(await backend.register_user(registration));
+      navigate('/login');
     } catch (err) {
       console.error('Registration error:', err);
       setError('Failed to register. Please try again. Error: ' + err.message);
@@ -92,7 +84,6 @@ const Register = () => {
             >
               <option value="victim">Victim</option>
               <option value="volunteer">Volunteer</option>
-              <option value="organization">Organization</option>
               <option value="donor">Donor</option>
             </select>
           </div>
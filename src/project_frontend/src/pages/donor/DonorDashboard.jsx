@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { project_backend } from '../../../../declarations/project_backend';
+import { backend, disconnect, unwrap, nowNanos, toDate } from '../../api';
 import { useNavigate } from 'react-router-dom';
 import Header from '../../components/Header';
 import './DonorDashboard.css';
@@ -7,11 +7,13 @@ import './DonorDashboard.css';
 const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) => {
   const navigate = useNavigate();
   const [donations, setDonations] = useState([]);
+  const [organizations, setOrganizations] = useState([]);
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState('');
   const [newDonation, setNewDonation] = useState({
     amount: '',
     purpose: '',
+    organizationId: '',
   });
   const [processingDonation, setProcessingDonation] = useState(false);
   const [successMessage, setSuccessMessage] = useState('');
@@ -26,8 +28,12 @@ const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
     try {
       setLoading(true);
       setError('');
-      const donorDonations = await project_backend.get_donor_donations(currentUser.email);
-      setDonations(donorDonations);
+      const [donorDonations, allOrganizations] = await Promise.all([
+        backend().get_donor_donations(currentUser.email),
+        backend().list_organizations()
+      ]);
+      setDonations(unwrap(donorDonations));
+      setOrganizations(unwrap(allOrganizations));
     } catch (err) {
       console.error('Error loading donations:', err);
       setError('Failed to load donations');
@@ -37,6 +43,7 @@ const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
   };
 
   const handleLogout = () => {
+    disconnect();
     setIsAuthenticated(false);
     setCurrentUser(null);
     navigate('/login');
@@ -44,7 +51,7 @@ const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
 
   const handleDonationSubmit = async (e) => {
     e.preventDefault();
-    if (!newDonation.amount || !newDonation.purpose) {
+    if (!newDonation.amount || !newDonation.purpose || !newDonation.organizationId) {
       setError('Please fill in all fields');
       return;
     }
@@ -54,32 +61,30 @@ const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
       setError('');
       setSuccessMessage('');
 
+      // The backend records the donor and date from the calling account
       const donation = {
         id: Date.now().toString(),
         amount: parseFloat(newDonation.amount),
         donor_name: currentUser.name,
         donor_email: currentUser.email,
-        date: new Date().toISOString(),
+        date: nowNanos(),
+        organization_id: newDonation.organizationId,
+        incident_id: [],
         distribution_details: [{
           amount: parseFloat(newDonation.amount),
           purpose: newDonation.purpose,
-          date: new Date().toISOString()
+          date: nowNanos()
         }]
       };
 
       console.log('Creating donation with data:', donation);
-      const success = await project_backend.make_donation(donation);
-      
-      if (success) {
-        setSuccessMessage('Donation processed successfully!');
-        setNewDonation({ amount: '', purpose: '' });
-        loadDonations();
-      } else {
-        setError('Failed to process donation');
-      }
+      unwrap(await backend().make_donation(donation));
+      setSuccessMessage('Donation processed successfully!');
+      setNewDonation({ amount: '', purpose: '', organizationId: '' });
+      loadDonations();
     } catch (err) {
       console.error('Error making donation:', err);
-      setError('Failed to process donation');
+      setError('Failed to process donation: ' + err.message);
     } finally {
       setProcessingDonation(false);
     }
@@ -111,6 +116,23 @@ const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
                 className="form-control"
               />
             </div>
+            <div className="form-group">
+              <label htmlFor="organization">Organization</label>
+              <select
+                id="organization"
+                value={newDonation.organizationId}
+                onChange={(e) => setNewDonation(prev => ({ ...prev, organizationId: e.target.value }))}
+                required
+                className="form-control"
+              >
+                <option value="">Select an organization</option>
+                {organizations.map(organization => (
+                  <option key={organization.id} value={organization.id}>
+                    {organization.name}
+                  </option>
+                ))}
+              </select>
+            </div>
             <div className="form-group">
               <label htmlFor="purpose">Purpose</label>
               <select
@@ -151,7 +173,7 @@ const DonorDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
                   <div className="donation-amount">${donation.amount.toFixed(2)}</div>
                   <div className="donation-details">
                     <p className="donation-date">
-                      {new Date(donation.date).toLocaleDateString()}
+                      {toDate(donation.date).toLocaleDateString()}
                     </p>
                     <div className="distribution-details">
                       {donation.distribution_details.map((detail, index) => (
@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { project_backend } from '../../../../declarations/project_backend';
+import { backend, disconnect, unwrap, fromOpt, fetchAllPages, statusName, nowNanos, toDate } from '../../api';
 import { useNavigate } from 'react-router-dom';
 import Header from '../../components/Header';
 import './OrganizationDashboard.css';
@@ -11,6 +11,7 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
   const [volunteers, setVolunteers] = useState([]);
   const [supplyBundles, setSupplyBundles] = useState([]);
   const [donations, setDonations] = useState([]);
+  const [organizationId, setOrganizationId] = useState(null);
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState('');
   const [selectedRequest, setSelectedRequest] = useState(null);
@@ -32,21 +33,44 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
       setError('');
       console.log('Loading data for organization dashboard...');
       
+      // The dashboard shows the first organization the account belongs to
+      const organizations = unwrap(await backend().get_my_organizations());
+      if (organizations.length === 0) {
+        throw new Error('this account is not a member of any organization');
+      }
+      const orgId = organizations[0].id;
+      setOrganizationId(orgId);
+
       console.log('Fetching requests...');
-      const requests = await project_backend.get_all_requests();
+      const requests = await fetchAllPages((startAfter) => backend().get_all_requests({
+        status: [], request_type: [], urgency: [], assigned_volunteer: [],
+        since: [], until: [], organization_id: [orgId], incident_id: []
+      }, startAfter, []));
       console.log('Received requests:', requests);
 
       console.log('Fetching volunteers...');
-      const vols = await project_backend.get_all_volunteers();
+      const listings = await fetchAllPages((startAfter) => backend().get_all_volunteers({
+        equipment: [], language: [], skill: [], certification: [], available_now: [], on_duty: []
+      }, startAfter, []));
+      const vols = listings.map(({ user, profile }) => ({
+        email: user.email,
+        name: user.name,
+        phone: user.phone,
+        on_duty: profile.on_duty
+      }));
       console.log('Received volunteers:', vols);
 
       console.log('Fetching supply bundles...');
-      const bundles = await project_backend.get_organization_supply_bundles();
+      const bundles = await fetchAllPages((startAfter) => backend().get_organization_supply_bundles(
+        orgId, { status: [], assigned_to: [], incident_id: [] }, startAfter, []
+      ));
       console.log('Received supply bundles:', bundles);
 
       console.log('Fetching donations...');
-      const don = await project_backend.get_organization_donations();
-      console.log('Received donations:', JSON.stringify(don, null, 2));
+      const don = await fetchAllPages((startAfter) => backend().get_organization_donations(
+        orgId, { donor_email: [], since: [], until: [], incident_id: [] }, startAfter, []
+      ));
+      console.log('Received donations:', don);
 
       setHelpRequests(requests || []);
       setVolunteers(vols || []);
@@ -64,86 +88,64 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
     }
   };
 
-  const handleVerifyRequest = async (requestId) => {
+  // Run a backend call for each of a victim's requests it applies to
+  const forEachRequest = async (requests, call) => {
+    const results = await Promise.all(requests.map(call));
+    results.forEach(unwrap);
+  };
+
+  const handleVerifyRequest = async (group) => {
     try {
-      console.log('Verifying request:', requestId);
-      // Split the requestId into victim_id and timestamp
-      const [victim_id, timestamp] = requestId.split('_');
-      const success = await project_backend.verify_help_request(
-        victim_id,
-        timestamp,
-        "Request verified by organization",
-        "organization"
+      console.log('Verifying requests of victim:', group.victim_id);
+      const pending = group.requests.filter(request => statusName(request.status) === 'pending');
+      await forEachRequest(pending, request =>
+        backend().verify_help_request(request.request_id, "Request verified by organization")
       );
-      
-      if (success) {
-        console.log('Request verified successfully');
-        loadData(); // Reload all data
-        setError('');
-      } else {
-        console.error('Failed to verify request');
-        setError('Failed to verify request. Please ensure the request is still pending.');
-      }
+      console.log('Requests verified successfully');
+      loadData(); // Reload all data
+      setError('');
     } catch (err) {
       console.error('Error verifying request:', err);
-      setError('An error occurred while verifying the request. Please try again.');
+      setError('Failed to verify request: ' + err.message);
     }
   };
 
-  const handleAssignVolunteer = async (requestId, volunteerId) => {
+  const handleAssignVolunteer = async (group, volunteerId) => {
     if (!volunteerId) {
       setError('Please select a volunteer');
       return;
     }
     try {
-      console.log('Assigning volunteer:', volunteerId, 'to request:', requestId);
-      // Split the requestId into victim_id and timestamp
-      const [victim_id, timestamp] = requestId.split('_');
-      
-      // Get all requests for this victim
-      const victimRequests = helpRequests.filter(req => req.victim_id === victim_id);
-      
-      // Assign volunteer to all requests for this victim
-      const assignments = await Promise.all(
-        victimRequests.map(request => 
-          project_backend.assign_volunteer_to_request(
-            `${request.victim_id}_${request.timestamp}`,
-            volunteerId
-          )
-        )
+      console.log('Assigning volunteer:', volunteerId, 'to requests of victim:', group.victim_id);
+      // Assign the volunteer to all of this victim's open requests
+      const open = group.requests.filter(request =>
+        ['pending', 'verified'].includes(statusName(request.status))
       );
-      
-      const success = assignments.every(result => result === true);
-      console.log('Assign volunteer result:', success);
-      
-      if (success) {
-        loadData(); // Reload data to show updated assignments
-      } else {
-        setError('Failed to assign volunteer to some requests');
-      }
+      await forEachRequest(open, request =>
+        backend().assign_volunteer_to_request(request.request_id, volunteerId)
+      );
+      loadData(); // Reload data to show updated assignments
     } catch (err) {
       console.error('Error assigning volunteer:', err);
-      setError('Failed to assign volunteer');
+      setError('Failed to assign volunteer: ' + err.message);
     }
   };
 
-  const handleApproveVolunteerRequest = async (requestId) => {
+  const handleApproveVolunteerRequest = async (group) => {
     try {
-      console.log('Approving volunteer request:', requestId);
-      const [victim_id, timestamp] = requestId.split('_');
-      
-      const success = await project_backend.approve_volunteer_request(victim_id, timestamp);
-      console.log('Approve volunteer request result:', success);
-      
-      if (success) {
-        loadData(); // Reload data to show updated status
-        setError('');
-      } else {
-        setError('Failed to approve volunteer request');
-      }
+      console.log('Approving volunteer work for victim:', group.victim_id);
+      const inProgress = group.requests.filter(request =>
+        fromOpt(request.assigned_volunteer) &&
+        ['assigned', 'accepted', 'inprogress'].includes(statusName(request.status))
+      );
+      await forEachRequest(inProgress, request =>
+        backend().approve_volunteer_request(request.request_id)
+      );
+      loadData(); // Reload data to show updated status
+      setError('');
     } catch (err) {
       console.error('Error approving volunteer request:', err);
-      setError('Failed to approve volunteer request');
+      setError('Failed to approve volunteer request: ' + err.message);
     }
   };
 
@@ -154,20 +156,18 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
         ...newBundle,
         id: Date.now().toString(),
         status: 'available',
-        created_at: new Date().toISOString()
+        created_at: nowNanos(),
+        organization_id: organizationId,
+        assigned_to: [],
+        incident_id: []
       };
       console.log('Creating bundle:', bundle);
-      const success = await project_backend.create_supply_bundle(bundle);
-      console.log('Create bundle result:', success);
-      if (success) {
-        setNewBundle({ name: '', description: '', items: [] });
-        loadData(); // Reload data to show new bundle
-      } else {
-        setError('Failed to create supply bundle');
-      }
+      unwrap(await backend().create_supply_bundle(bundle));
+      setNewBundle({ name: '', description: '', items: [] });
+      loadData(); // Reload data to show new bundle
     } catch (err) {
       console.error('Error creating bundle:', err);
-      setError('Failed to create supply bundle');
+      setError('Failed to create supply bundle: ' + err.message);
     }
   };
 
@@ -178,20 +178,16 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
     }
     try {
       console.log('Distributing bundle:', bundleId, 'to volunteer:', volunteerId);
-      const success = await project_backend.distribute_supply_bundle(bundleId, volunteerId);
-      console.log('Distribute bundle result:', success);
-      if (success) {
-        loadData(); // Reload data to show updated distribution
-      } else {
-        setError('Failed to distribute bundle');
-      }
+      unwrap(await backend().distribute_supply_bundle(bundleId, volunteerId));
+      loadData(); // Reload data to show updated distribution
     } catch (err) {
       console.error('Error distributing bundle:', err);
-      setError('Failed to distribute bundle');
+      setError('Failed to distribute bundle: ' + err.message);
     }
   };
 
   const handleLogout = () => {
+    disconnect();
     setIsAuthenticated(false);
     setCurrentUser(null);
   };
@@ -247,12 +243,13 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
               {(() => {
                 // Group requests by victim_id
                 const groupedRequests = helpRequests.reduce((acc, request) => {
+                  const status = statusName(request.status);
                   if (!acc[request.victim_id]) {
                     acc[request.victim_id] = {
                       victim_id: request.victim_id,
                       requests: [],
                       combined_needs: new Set(),
-                      status: request.status,
+                      status,
                       location: request.location,
                       assigned_volunteer: null
                     };
@@ -260,16 +257,17 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                   acc[request.victim_id].requests.push(request);
                   acc[request.victim_id].combined_needs.add(request.request_type);
                   // Update status to show the most urgent one
-                  if (request.status === "pending" && acc[request.victim_id].status !== "pending") {
+                  if (status === "pending" && acc[request.victim_id].status !== "pending") {
                     acc[request.victim_id].status = "pending";
-                  } else if (request.status === "completed") {
+                  } else if (status === "completed") {
                     acc[request.victim_id].status = "completed";
-                  } else if (request.status === "verified" && acc[request.victim_id].status !== "pending") {
+                  } else if (status === "verified" && acc[request.victim_id].status !== "pending") {
                     acc[request.victim_id].status = "verified";
                   }
                   // Update assigned volunteer if this request has one
-                  if (request.assigned_volunteer && typeof request.assigned_volunteer === 'string') {
-                    acc[request.victim_id].assigned_volunteer = request.assigned_volunteer;
+                  const assignedVolunteer = fromOpt(request.assigned_volunteer);
+                  if (assignedVolunteer) {
+                    acc[request.victim_id].assigned_volunteer = assignedVolunteer;
                   }
                   return acc;
                 }, {});
@@ -287,9 +285,8 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                     return statusOrder[a.status] - statusOrder[b.status];
                   })
                   .map(group => {
-                    const requestId = `${group.victim_id}_${group.requests[0].timestamp}`;
                     return (
-                      <div key={requestId} className={`request-card ${group.status}`}>
+                      <div key={group.victim_id} className={`request-card ${group.status}`}>
                         <h3>Combined Requests for Victim {group.victim_id}</h3>
                         <div className="combined-needs">
                           <h4>Needs:</h4>
@@ -308,7 +305,7 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                         <div className="request-actions">
                           {group.status === "pending" && (
                             <button
-                              onClick={() => handleVerifyRequest(requestId)}
+                              onClick={() => handleVerifyRequest(group)}
                               className="verify-button"
                             >
                               Verify All Requests
@@ -316,7 +313,7 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                           )}
                           {group.status === "verified" && !group.assigned_volunteer && (
                             <select
-                              onChange={(e) => handleAssignVolunteer(requestId, e.target.value)}
+                              onChange={(e) => handleAssignVolunteer(group, e.target.value)}
                               className="volunteer-select"
                             >
                               <option value="">Select Volunteer</option>
@@ -336,7 +333,7 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                                 })()}
                               </div>
                               <button
-                                onClick={() => handleApproveVolunteerRequest(requestId)}
+                                onClick={() => handleApproveVolunteerRequest(group)}
                                 className="approve-button"
                               >
                                 Approve Volunteer Completion
@@ -352,8 +349,8 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                         <div className="individual-requests">
                           <h4>Individual Requests:</h4>
                           <ul>
-                            {group.requests.map((request, index) => (
-                              <li key={`${requestId}_${index}`}>
+                            {group.requests.map((request) => (
+                              <li key={request.request_id.toString()}>
                                 <strong>{request.request_type}</strong>: {request.description}
                                 <span className={`urgency ${request.urgency}`}>
                                   {request.urgency}
@@ -375,11 +372,11 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
             <h2>Volunteers</h2>
             <div className="volunteers-list">
               {volunteers.map(volunteer => (
-                <div key={volunteer.id} className="volunteer-card">
+                <div key={volunteer.email} className="volunteer-card">
                   <h3>{volunteer.name}</h3>
                   <p>Email: {volunteer.email}</p>
                   <p>Phone: {volunteer.phone}</p>
-                  <p>Active Assignments: {volunteer.active_assignments}</p>
+                  <p>On Duty: {volunteer.on_duty ? 'Yes' : 'No'}</p>
                 </div>
               ))}
             </div>
@@ -425,7 +422,7 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                     >
                       <option value="">Assign to Volunteer</option>
                       {volunteers.map(vol => (
-                        <option key={vol.id} value={vol.id}>
+                        <option key={vol.email} value={vol.email}>
                           {vol.name}
                         </option>
                       ))}
@@ -451,7 +448,7 @@ const OrganizationDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser
                     <div className="donation-amount">${donation.amount.toFixed(2)}</div>
                   </div>
                   <div className="donation-date">
-                    Date: {new Date(donation.date).toLocaleDateString()}
+                    Date: {toDate(donation.date).toLocaleDateString()}
                   </div>
                   <div className="donation-details">
                     <h4>Distribution Details:</h4>
@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { project_backend } from '../../../../declarations/project_backend';
+import { backend, disconnect, unwrap, statusName, variantName, toDate } from '../../api';
 import { useNavigate } from 'react-router-dom';
 import Header from '../../components/Header';
 import './VictimDashboard.css';
@@ -28,7 +28,7 @@ const VictimDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
   const loadUserRequests = async () => {
     try {
       console.log('Loading requests for user:', currentUser?.email);
-      const requests = await project_backend.get_user_requests(currentUser?.email);
+      const requests = unwrap(await backend().get_user_requests(currentUser?.email));
       console.log('User requests:', requests);
       setActiveRequests(requests);
     } catch (err) {
@@ -138,40 +138,33 @@ const VictimDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
         // Continue with existing location or coordinates
       }
       
+      // The backend fills in the victim, status, timestamp and id, and
+      // routes the request to an organization from its coordinates
       const helpRequest = {
-        victim_id: currentUser.email,
         request_type: newRequest.type,
         description: `${newRequest.type.charAt(0).toUpperCase() + newRequest.type.slice(1)} needed for ${newRequest.numberOfPeople} people. ${newRequest.description}`,
         urgency: newRequest.urgency,
         location: locationAddress,
-        status: 'pending',
-        timestamp: new Date().toISOString(),
-        latitude: latitude.toString(),
-        longitude: longitude.toString(),
-        verification_note: [], // Empty array represents None/null in Candid
-        verified_by: [], // Empty array represents None/null in Candid
+        coordinates: { lat: latitude, lon: longitude },
         organization_id: [], // Empty array represents None/null in Candid
-        assigned_volunteer: [] // Empty array represents None/null in Candid
+        incident_id: [] // Empty array represents None/null in Candid
       };
 
       console.log('Submitting help request:', helpRequest);
-      const success = await project_backend.create_help_request(helpRequest);
+      const requestId = unwrap(await backend().create_help_request(helpRequest));
+      console.log('Created help request:', requestId);
 
-      if (success) {
-        setSuccess('Your request has been submitted successfully!');
-        setNewRequest({
-          type: 'food',
-          description: '',
-          urgency: 'medium',
-          location: '',
-          status: 'pending',
-          numberOfPeople: '1'
-        });
-        // Reload the requests list
-        await loadUserRequests();
-      } else {
-        setError('Failed to submit request. Please try again.');
-      }
+      setSuccess('Your request has been submitted successfully!');
+      setNewRequest({
+        type: 'food',
+        description: '',
+        urgency: 'medium',
+        location: '',
+        status: 'pending',
+        numberOfPeople: '1'
+      });
+      // Reload the requests list
+      await loadUserRequests();
     } catch (err) {
       console.error('Error submitting request:', err);
       setError('Failed to submit request: ' + (err.message || 'Please ensure location access is enabled'));
@@ -228,27 +221,15 @@ const VictimDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
     );
   };
 
-  const handleCancelRequest = async (timestamp) => {
+  const handleCancelRequest = async (requestId) => {
     try {
-      console.log('Attempting to cancel request with timestamp:', timestamp);
-      setCancellingRequestId(timestamp);
-      
-      if (!timestamp) {
-        throw new Error('Request timestamp is undefined');
-      }
+      console.log('Attempting to cancel request:', requestId);
+      setCancellingRequestId(requestId);
 
-      console.log('Calling backend to cancel request...');
-      // Pass both email and timestamp to uniquely identify the request
-      const success = await project_backend.cancel_help_request(currentUser.email, timestamp);
-      console.log('Cancel request response:', success);
-      
-      if (success) {
-        setSuccess('Request cancelled successfully');
-        // Reload the requests list
-        await loadUserRequests();
-      } else {
-        throw new Error('Backend returned false for cancellation');
-      }
+      unwrap(await backend().cancel_help_request(requestId));
+      setSuccess('Request cancelled successfully');
+      // Reload the requests list
+      await loadUserRequests();
     } catch (err) {
       console.error('Error cancelling request:', err);
       setError(`Failed to cancel request: ${err.message}`);
@@ -258,6 +239,7 @@ const VictimDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
   };
 
   const handleLogout = () => {
+    disconnect();
     setIsAuthenticated(false);
     setCurrentUser(null);
   };
@@ -387,21 +369,21 @@ const VictimDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
             <p className="no-requests">You don't have any active requests.</p>
           ) : (
             <div className="requests-list">
-              {activeRequests.map((request, index) => (
-                <div key={index} className="request-card">
+              {activeRequests.map((request) => (
+                <div key={request.request_id.toString()} className="request-card">
                   <div className="request-header">
                     <h3>{request.request_type.charAt(0).toUpperCase() + request.request_type.slice(1)}</h3>
                     <div className="request-header-right">
-                      <span className={`status ${request.status.toLowerCase()}`}>
-                        {request.status.charAt(0).toUpperCase() + request.status.slice(1)}
+                      <span className={`status ${statusName(request.status)}`}>
+                        {variantName(request.status)}
                       </span>
-                      {request.status.toLowerCase() === 'pending' && (
+                      {statusName(request.status) === 'pending' && (
                         <button
                           className="cancel-button"
-                          onClick={() => handleCancelRequest(request.timestamp)}
-                          disabled={cancellingRequestId === request.timestamp}
+                          onClick={() => handleCancelRequest(request.request_id)}
+                          disabled={cancellingRequestId === request.request_id}
                         >
-                          {cancellingRequestId === request.timestamp ? (
+                          {cancellingRequestId === request.request_id ? (
                             <span className="loading-spinner"></span>
                           ) : (
                             '❌ Cancel'
@@ -417,7 +399,7 @@ const VictimDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) =>
                     </span>
                     <span className="location">{request.location}</span>
                     <span className="timestamp">
-                      {new Date(request.timestamp).toLocaleString()}
+                      {toDate(request.timestamp).toLocaleString()}
                     </span>
                   </div>
                 </div>
@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { project_backend } from '../../../../declarations/project_backend';
+import { backend, disconnect, unwrap, fromOpt, statusName, variantName, toDate } from '../../api';
 import { useNavigate } from 'react-router-dom';
 import { MapContainer, TileLayer, Marker, Popup, useMap } from 'react-leaflet';
 import L from 'leaflet';
@@ -37,6 +37,10 @@ const MapUpdater = ({ center }) => {
   return null;
 };
 
+// How far around the volunteer to look for open requests
+const SEARCH_RADIUS_KM = 10;
+const NEARBY_LIMIT = 50;
+
 const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser }) => {
   const navigate = useNavigate();
   const [nearbyRequests, setNearbyRequests] = useState([]);
@@ -160,15 +164,7 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
       setSuccess('Location updated successfully');
       
       // Update volunteer's location in the backend
-      await project_backend.update_volunteer_location(
-        currentUser.email,
-        latitude.toString(),
-        longitude.toString(),
-        address
-      );
-
-      // Load nearby requests after updating location
-      await loadNearbyRequests();
+      unwrap(await backend().update_volunteer_location({ lat: latitude, lon: longitude }, address));
     } catch (err) {
       console.error('Error getting location:', err);
       setError('Failed to get location: ' + (err.message || 'Please ensure location access is enabled'));
@@ -180,11 +176,14 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
   const loadNearbyRequests = async () => {
     try {
       setLoading(true);
-      const requests = await project_backend.get_nearby_requests(
-        location.latitude.toString(),
-        location.longitude.toString()
-      );
-      setNearbyRequests(requests);
+      const nearby = unwrap(await backend().get_nearby_requests(
+        { lat: location.latitude, lon: location.longitude },
+        SEARCH_RADIUS_KM,
+        NEARBY_LIMIT,
+        { request_type: [], urgency: [], matching_my_skills: [] }
+      ));
+      // Coordinates and distances arrive coarsened for privacy
+      setNearbyRequests(nearby.map(({ request, distance_km }) => ({ ...request, distance_km })));
     } catch (err) {
       console.error('Error loading nearby requests:', err);
       setError('Failed to load nearby requests');
@@ -196,40 +195,19 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
   const handleVerifyRequest = async (request) => {
     try {
       console.log('Verifying request:', request);
-      const success = await project_backend.verify_help_request(
-        request.victim_id,
-        request.timestamp,
-        "Verified by volunteer",
-        "volunteer"
-      );
-      console.log('Verify request result:', success);
-      if (success) {
-        console.log('Request verified successfully');
-        loadNearbyRequests(); // Reload the list
-        setSelectedRequest(null);
-      } else {
-        console.error('Failed to verify request');
-        setError('Failed to verify request. Please ensure the request is still pending.');
-      }
+      unwrap(await backend().verify_help_request(request.request_id, verificationNote.trim()));
+      console.log('Request verified successfully');
+      setVerificationNote('');
+      loadNearbyRequests(); // Reload the list
+      setSelectedRequest(null);
     } catch (err) {
       console.error('Error verifying request:', err);
-      setError('An error occurred while verifying the request. Please try again.');
+      setError('Failed to verify request: ' + err.message);
     }
   };
 
-  const calculateDistance = (lat1, lon1, lat2, lon2) => {
-    const R = 6371; // Earth's radius in km
-    const dLat = (lat2 - lat1) * Math.PI / 180;
-    const dLon = (lon2 - lon1) * Math.PI / 180;
-    const a = 
-      Math.sin(dLat/2) * Math.sin(dLat/2) +
-      Math.cos(lat1 * Math.PI / 180) * Math.cos(lat2 * Math.PI / 180) * 
-      Math.sin(dLon/2) * Math.sin(dLon/2);
-    const c = 2 * Math.atan2(Math.sqrt(a), Math.sqrt(1-a));
-    return (R * c).toFixed(1); // Distance in km
-  };
-
   const handleLogout = () => {
+    disconnect();
     setIsAuthenticated(false);
     setCurrentUser(null);
   };
@@ -286,14 +264,13 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
                 </Marker>
               )}
               {nearbyRequests.map((request) => {
-                const lat = parseFloat(request.latitude);
-                const lng = parseFloat(request.longitude);
-                if (isNaN(lat) || isNaN(lng)) return null;
+                const coordinates = fromOpt(request.coordinates);
+                if (!coordinates) return null;
                 
                 return (
                   <Marker
-                    key={`${request.victim_id}-${request.timestamp}`}
-                    position={[lat, lng]}
+                    key={request.request_id.toString()}
+                    position={[coordinates.lat, coordinates.lon]}
                     icon={ICONS[request.urgency.toLowerCase()] || ICONS.low}
                   >
                     <Popup>
@@ -302,8 +279,8 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
                         <p>{request.description}</p>
                         <p><strong>Urgency:</strong> {getUrgencyDescription(request.request_type, request.urgency)}</p>
                         <p><strong>Location:</strong> {request.location}</p>
-                        <p><strong>Status:</strong> {request.status}</p>
-                        {request.status.toLowerCase() === 'pending' && (
+                        <p><strong>Status:</strong> {variantName(request.status)}</p>
+                        {statusName(request.status) === 'pending' && (
                           <button
                             onClick={() => setSelectedRequest(request)}
                             className="verify-button"
@@ -352,13 +329,13 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
             <p className="no-requests">No nearby requests found.</p>
           ) : (
             <div className="requests-list">
-              {nearbyRequests.map((request, index) => (
-                <div key={index} className="request-card">
+              {nearbyRequests.map((request) => (
+                <div key={request.request_id.toString()} className="request-card">
                   <div className="request-header">
                     <h3>{request.request_type.charAt(0).toUpperCase() + request.request_type.slice(1)}</h3>
                     <div className="request-header-right">
-                      <span className={`status ${request.status.toLowerCase()}`}>
-                        {request.status.charAt(0).toUpperCase() + request.status.slice(1)}
+                      <span className={`status ${statusName(request.status)}`}>
+                        {variantName(request.status)}
                       </span>
                     </div>
                   </div>
@@ -368,21 +345,14 @@ const VolunteerDashboard = ({ currentUser, setIsAuthenticated, setCurrentUser })
                       {getUrgencyDescription(request.request_type, request.urgency)}
                     </span>
                     <span className="location">📍 {request.location}</span>
-                    {location.latitude && request.latitude && (
-                      <span className="distance">
-                        📏 {calculateDistance(
-                          location.latitude,
-                          location.longitude,
-                          parseFloat(request.latitude),
-                          parseFloat(request.longitude)
-                        )} km away
-                      </span>
-                    )}
+                    <span className="distance">
+                      📏 about {request.distance_km} km away
+                    </span>
                     <span className="timestamp">
-                      🕒 {new Date(request.timestamp).toLocaleString()}
+                      🕒 {toDate(request.timestamp).toLocaleString()}
                     </span>
                   </div>
-                  {request.status.toLowerCase() === 'pending' && (
+                  {statusName(request.status) === 'pending' && (
                     <div className="verification-section">
                       <textarea
                         placeholder="Add verification notes..."